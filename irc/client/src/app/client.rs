@@ -70,12 +70,19 @@ impl Client {
     }
     ///Permite que el cliente reciba mensajes del servidor.
     pub fn receive(&self, tx: Sender<String>) -> Result<(), Error> {
-        let socket = &self.socket;
+        let mut socket = &self.socket;
         let reader = BufReader::new(socket);
         let mut lines = reader.lines();
 
         while let Some(Ok(line)) = lines.next() {
             println!("Mensaje recibido: {:?}", line);
+            // El servidor envía PING a las conexiones inactivas y espera la respuesta
+            let msg = Message::from(line.clone());
+            if msg.command == "PING" {
+                let token = msg.parameters.first().cloned().unwrap_or_default();
+                let _ = socket.write(format!("PONG :{}\n", token).as_bytes());
+                continue;
+            }
             match tx.send(line) {
                 Ok(()) => (),
                 Err(e) => eprintln!("Error enviando mensaje: {}", e),
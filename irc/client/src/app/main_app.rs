@@ -36,7 +36,7 @@ use super::buttons_handlers::console_button_handle::handle_console_button;
 use super::buttons_handlers::nick_button_handler::handle_nick_button;
 use super::buttons_handlers::quit_button_handle::{handle_quit_button, handle_squit_button};
 use super::buttons_handlers::remove_channel_button_handle::handle_remove_channel;
-use super::reply_codes::RPL_WHOIUSER;
+use super::reply_codes::{ERR_NOMOTD, RPL_WHOIUSER};
 use super::widgets::chat_button_list::ChattingButtonList;
 use super::widgets::chat_container::ChatContainer;
 
@@ -352,7 +352,14 @@ fn app_loop(
 
     // Handle received message
     rx.attach(None, move |texto| {
-        let msg = Message::from(texto);
+        let mut msg = Message::from(texto);
+        // Las respuestas numéricas llegan dirigidas a nuestro nick, lo descartamos
+        if msg.command.len() == 3
+            && msg.command.chars().all(|c| c.is_ascii_digit())
+            && !msg.parameters.is_empty()
+        {
+            msg.parameters.remove(0);
+        }
         if let Some(mut sender) = msg.prefix {
             // Display message
             if msg.command.as_str() == "PRIVMSG" {
@@ -459,7 +466,8 @@ fn app_loop(
                 }
             }
         }
-        if msg.command.as_str().starts_with('4') {
+        // Que el servidor no tenga mensaje del día no es un error para el usuario
+        if msg.command.as_str().starts_with('4') && msg.command != ERR_NOMOTD {
             popup_error_main_window(msg.parameters.clone());
         }
 
@@ -491,10 +499,11 @@ fn app_loop(
                 update_user_info(users_list.clone(), msg.parameters);
             }
             "353" => {
+                // "353 <tipo de canal> <canal> :[@|+]<nick> ..."
                 let names_text = format!(
                     "Members of {}: {}",
-                    msg.parameters[0].clone(),
-                    msg.parameters[1].clone()
+                    msg.parameters[1].clone(),
+                    msg.parameters[2].clone()
                 );
                 popup_window(names_text, "Channel members");
             }
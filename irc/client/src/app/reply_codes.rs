@@ -3,3 +3,4 @@ pub const RPL_LIST: &str = "322";
 pub const RPL_LISTEND: &str = "323";
 pub const RPL_WHO: &str = "352";*/
 pub const RPL_WHOIUSER: &str = "311";
+pub const ERR_NOMOTD: &str = "422";
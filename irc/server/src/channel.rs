@@ -1,19 +1,33 @@
-use crate::channel_modes::ChannelModes;
-use crate::message::{InvalidMessageError, Message};
+use crate::channel_modes::{mode_changes_parameters, parse_mode_changes, ChannelModes, ModeChange};
+use crate::command::Command;
+use crate::date::unix_time;
+use crate::mask::{has_wildcards, matches_casemapped, matches_user_mask, normalize_mask};
+use crate::member::{members_from_string, members_to_string, Member, MemberStatus};
+use crate::message::{InvalidMessageError, Message, MAX_MESSAGE_LENGTH};
+use crate::registry::{casefold, same_name, Registry};
 use crate::replies::{
     err_bad_channel_key, err_banned_from_chan, err_chan_opriv_is_needed, err_channel_is_full,
     err_invite_only_chan, err_key_set, err_need_more_params, err_no_oper_host, err_no_such_channel,
     err_not_on_channel, err_unknown_mode, err_user_on_channel, error_no_such_nick, rpl_banlist,
-    rpl_end_of_ban_list, rpl_end_of_names, rpl_inviting, rpl_list, rpl_list_end, rpl_list_start,
-    rpl_name_rply, rpl_no_topic, rpl_topic, rpl_you_are_oper,
+    rpl_channel_mode_is, rpl_creation_time, rpl_end_of_ban_list, rpl_end_of_except_list,
+    rpl_end_of_invite_list, rpl_end_of_names, rpl_except_list, rpl_invite_list, rpl_inviting,
+    rpl_list, rpl_list_end, rpl_list_start, rpl_name_rply, rpl_no_topic, rpl_topic,
+    rpl_topic_who_time, rpl_you_are_oper,
 };
 use crate::server::Server;
 use crate::server_errors::ServerError;
-use crate::server_messages_interpreter::forward_invite_msg;
+use crate::server_messages_interpreter::propagate_user_msg;
 use crate::user::User;
+use std::cmp::Ordering;
 use std::collections::HashSet;
-use std::sync::{Arc, Mutex};
-use std::thread;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///Tópico de un canal, con el nick de quien lo estableció y el momento en que lo hizo.
+pub struct Topic {
+    pub text: String,
+    pub set_by: String,
+    pub set_at: u64,
+}
 
 #[derive(Debug, Clone)]
 ///Medio de comunicación para un determinado grupo de clientes. Cada canal tiene un nombre,
@@ -21,15 +35,19 @@ use std::thread;
 /// que limitan determinadas acciones.
 pub struct Channel {
     pub name: String,
-    pub users: Vec<String>,
-    pub topic: Option<String>,
-    pub admins: Vec<String>,
+    // miembros del canal con su estado (operador, voz) y el momento en que se unieron
+    pub members: Registry<Member>,
+    pub topic: Option<Topic>,
     pub mode: ChannelModes,
     // el limite de usuarios en un canal solo se puede setear una vez y no puede ser menor que la cantidad de usuarios actual del canal
     pub limit: Option<usize>,
     pub ban_masks: Vec<String>,
-    pub can_speak_users: Vec<String>,
+    // máscaras que quedan exceptuadas de los bans (+e) y que no necesitan invitación (+I)
+    pub exception_masks: Vec<String>,
+    pub invite_masks: Vec<String>,
     pub password: Option<String>,
+    // momento de creación del canal, informado con RPL_CREATIONTIME
+    pub created_at: u64,
 }
 ///Instancia un nuevo canal.
 impl Channel {
@@ -38,170 +56,111 @@ impl Channel {
 
         Self {
             name: name.to_string(),
-            users: Vec::new(),
+            members: Registry::new(),
             topic: None,
-            admins: Vec::new(),
             mode: ChannelModes::new(),
             limit: None,
             ban_masks: Vec::new(),
-            can_speak_users: Vec::new(),
+            exception_masks: Vec::new(),
+            invite_masks: Vec::new(),
             password: None,
+            created_at: unix_time(),
         }
     }
 
-    pub fn get_users_list(&self) -> String {
-        let mut list = String::new();
-        for user in &self.users {
-            list += &(",".to_string() + user);
-        }
-        list
-    }
-    pub fn set_users(&mut self, mut list: String) {
-        list.remove(0);
-        let v: Vec<String> = list.split(',').map(|s| s.to_string()).collect();
-        self.users = v;
-    }
-    pub fn get_admins_list(&self) -> String {
-        let mut list = String::new();
-        for user in &self.admins {
-            list += &(",".to_string() + user);
-        }
-        list
-    }
-    pub fn set_admins(&mut self, mut list: String) {
-        list.remove(0);
-        let v: Vec<String> = list.split(',').map(|s| s.to_string()).collect();
-        self.admins = v;
-    }
-    pub fn get_ban_list(&self) -> String {
-        let mut list = String::new();
-        if self.ban_masks.is_empty() {
-            ".".to_string()
-        } else {
-            for mask in &self.ban_masks {
-                list += &(",".to_string() + mask);
+    ///Obtiene los miembros del canal con el formato en que se informan a otros servidores.
+    pub fn get_members_list(&self) -> Vec<String> {
+        members_to_string(&self.members)
+    }
+    ///Reemplaza los miembros del canal por los informados por otro servidor.
+    pub fn set_members(&mut self, list: &[String]) {
+        self.members = members_from_string(list);
+    }
+    pub fn set_modes(&mut self, letters: &str) {
+        for mode in letters.chars() {
+            match mode {
+                'l' => self.mode.activate_l(),
+                'k' => self.mode.activate_k(),
+                mode => {
+                    self.mode.set(mode, true);
+                }
             }
-            list
-        }
-    }
-    pub fn set_ban_list(&mut self, mut list: String) {
-        if !list.starts_with('.') {
-            list.remove(0);
-            let v: Vec<String> = list.split(',').map(|s| s.to_string()).collect();
-            self.ban_masks = v;
         }
     }
-    pub fn get_can_speak_users_list(&self) -> String {
-        let mut list = String::new();
-        if self.can_speak_users.is_empty() {
-            ".".to_string()
-        } else {
-            for user in &self.can_speak_users {
-                list += &(",".to_string() + user);
+    ///Establece los modos informados por otro servidor con el formato de `mode_parameters`:
+    /// las letras de los modos seguidas del límite y de la clave.
+    pub fn set_mode_parameters(&mut self, parameters: &[String]) {
+        let Some((letters, arguments)) = parameters.split_first() else {
+            return;
+        };
+        let mut arguments = arguments.iter();
+        self.set_modes(letters.trim_start_matches('+'));
+        for mode in letters.chars() {
+            match mode {
+                'l' => self.limit = arguments.next().and_then(|limit| limit.parse().ok()),
+                'k' => self.password = arguments.next().cloned(),
+                _ => {}
             }
-            list
-        }
-    }
-    pub fn set_speak_users(&mut self, mut list: String) {
-        if !list.starts_with('.') {
-            list.remove(0);
-            let v: Vec<String> = list.split(',').map(|s| s.to_string()).collect();
-            self.can_speak_users = v;
-        }
-    }
-    pub fn get_topic_option(&self) -> String {
-        if let Some(topic) = self.topic.clone() {
-            topic
-        } else {
-            ".".to_string()
-        }
-    }
-
-    pub fn set_topic(&mut self, topic: String) {
-        if !topic.starts_with('.') {
-            self.topic = Some(topic);
-        } else {
-            self.topic = None
-        }
-    }
-
-    pub fn get_limit_option(&self) -> String {
-        if let Some(limit) = self.limit {
-            limit.to_string()
-        } else {
-            ".".to_string()
-        }
-    }
-
-    pub fn set_limit(&mut self, limit: String) {
-        if let Ok(limit_number) = limit.parse::<usize>() {
-            self.limit = Option::from(limit_number);
-        } else {
-            self.limit = None;
-        }
-    }
-    pub fn get_password_option(&self) -> String {
-        if let Some(password) = self.password.clone() {
-            password
-        } else {
-            ".".to_string()
-        }
-    }
-    pub fn set_password(&mut self, password: String) {
-        if !password.starts_with('.') {
-            self.password = Some(password);
-        } else {
-            self.password = None
         }
     }
     ///Verifica si un determinado usuario es operador del canal.
-    pub fn is_admin(&self, user_nickname: &String) -> bool {
-        self.admins.iter().any(|user| user_nickname == user)
+    pub fn is_admin(&self, user_nickname: &str) -> bool {
+        self.members
+            .get(user_nickname)
+            .is_some_and(|member| member.status.op)
     }
-    ///Concede a un determinado usuario privilegios de operador en el canal.
+    ///Concede a un determinado usuario privilegios de operador en el canal. Si todavía no es
+    /// miembro, se lo agrega al canal con esos privilegios.
     pub fn add_admin(&mut self, user_nickname: String) {
         if !self.is_admin(&user_nickname) {
             println!("Se hizo admin de canal al usuario {}", &user_nickname);
-            self.admins.push(user_nickname);
-            println!("Admins del canal {:?}", self.admins);
+            self.member_entry(user_nickname).status.op = true;
         }
     }
     ///Quita a un determinado usuario privilegios de operador en el canal.
     pub fn remove_admin(&mut self, user_nickname: String) {
-        self.admins
-            .iter()
-            .position(|user| &user_nickname == user)
-            .map(|position| self.admins.remove(position));
+        if let Some(member) = self.members.get_mut(&user_nickname) {
+            member.status.op = false;
+        }
     }
     ///Quita a un determinado usuario la posibilidad de hablar en el canal.
     pub fn remove_speaker(&mut self, user_nickname: String) {
-        if self.can_speak(&user_nickname) {
-            self.can_speak_users
-                .iter()
-                .position(|user| &user_nickname == user)
-                .map(|position| self.can_speak_users.remove(position));
+        if let Some(member) = self.members.get_mut(&user_nickname) {
+            member.status.voice = false;
         }
     }
 
     ///Verifica si un determinado usuario está en el canal.
-    pub fn has_user(&self, user_nickname: &String) -> bool {
-        self.users.iter().any(|user| user_nickname == user)
+    pub fn has_user(&self, user_nickname: &str) -> bool {
+        self.members.contains(user_nickname)
     }
 
     ///Verifica si un determinado usuario está habilitado para hablar en el canal.
-    pub fn can_speak(&self, user_nickname: &String) -> bool {
-        self.can_speak_users
+    pub fn can_speak(&self, user_nickname: &str) -> bool {
+        self.members
+            .get(user_nickname)
+            .is_some_and(|member| member.status.voice)
+    }
+    ///Obtiene los nicks de los miembros del canal, en el orden en que se unieron.
+    pub fn nicknames(&self) -> Vec<String> {
+        self.members
             .iter()
-            .any(|user| user_nickname == user)
+            .map(|member| member.nickname.clone())
+            .collect()
     }
     ///Agrega un usuario al canal.
     pub fn add_user(&mut self, user_nickname: String) {
         if !self.has_user(&user_nickname) {
             println!("Se agrego al usuario {}", &user_nickname);
-            self.users.push(user_nickname);
-            println!("Usuarios del canal {:?}", self.users);
+            let _ = self.members.insert(Member::new(user_nickname));
+            println!("Usuarios del canal {:?}", self.nicknames());
         }
     }
+    ///Obtiene el miembro con el nick pasado, agregándolo al canal si todavía no lo es.
+    fn member_entry(&mut self, user_nickname: String) -> &mut Member {
+        self.add_user(user_nickname.clone());
+        self.members.get_mut(&user_nickname).unwrap()
+    }
     ///Evalúa si es posible agregar a un usuario al canal.
     pub fn can_add_user(&self) -> bool {
         !self.has_limit() || (self.has_limit() && self.has_free_space())
@@ -209,41 +168,60 @@ impl Channel {
     ///Evalúa si se alcanzó el límite de usuarios para el canal.
     pub fn has_free_space(&self) -> bool {
         if let Some(lim) = self.limit {
-            lim > self.users.len()
+            lim > self.members.len()
         } else {
             false
         }
     }
 
-    ///Habilita a un usuario a esribir en el canal.
+    ///Habilita a un usuario a esribir en el canal. Si todavía no es miembro, se lo agrega al
+    /// canal con voz.
     pub fn add_speaker(&mut self, user_nickname: String) {
         if !self.can_speak(&user_nickname) {
-            self.can_speak_users.push(user_nickname)
+            self.member_entry(user_nickname).status.voice = true;
         }
     }
-    ///Quita a un usuario del canal.
-    pub fn remove_user(&mut self, user_nickname: &String) {
-        self.users
-            .iter()
-            .position(|user| user_nickname == user)
-            .map(|position| self.users.remove(position));
-        self.admins
-            .iter()
-            .position(|user| user_nickname == user)
-            .map(|position| self.admins.remove(position));
-        println!("Usuarios del canal {:?}", self.users);
+    ///Quita a un usuario del canal, junto con su estado en él.
+    pub fn remove_user(&mut self, user_nickname: &str) {
+        self.members.remove(user_nickname);
+        println!("Usuarios del canal {:?}", self.nicknames());
     }
-    ///Elimina máscara de ban del listado.
+    ///Actualiza el nick de un miembro del canal, conservando su estado. Devuelve si el usuario
+    /// estaba en el canal.
+    pub fn rename_user(&mut self, user_nickname: &str, new_nickname: &str) -> bool {
+        self.members.rename(user_nickname, new_nickname).is_ok()
+    }
+    ///Elimina máscara de ban del listado. La máscara se completa a la forma `nick!user@host`
+    /// antes de buscarla.
     pub fn remove_ban(&mut self, ban_mask: String) {
-        self.ban_masks
-            .iter()
-            .position(|mask| &ban_mask == mask)
-            .map(|position| self.ban_masks.remove(position));
+        remove_mask(&mut self.ban_masks, &ban_mask);
     }
     ///Evalúa si el canal es visible.
     pub fn is_visible(&self) -> bool {
         !self.is_private() && !self.is_secret()
     }
+    ///Obtiene el tipo de canal informado en RPL_NAMREPLY: `@` si es secreto, `*` si es privado
+    /// y `=` si es público.
+    pub fn names_type(&self) -> char {
+        if self.is_secret() {
+            '@'
+        } else if self.is_private() {
+            '*'
+        } else {
+            '='
+        }
+    }
+    ///Obtiene el nick de un miembro precedido por su estado en el canal: `@` si es operador y
+    /// `+` si tiene voz.
+    pub fn member_with_prefix(&self, user_nickname: &str) -> String {
+        if self.is_admin(user_nickname) {
+            format!("@{}", user_nickname)
+        } else if self.can_speak(user_nickname) {
+            format!("+{}", user_nickname)
+        } else {
+            user_nickname.to_string()
+        }
+    }
     ///Evalúa si el canal es privado.
     pub fn is_private(&self) -> bool {
         self.mode.p
@@ -269,7 +247,6 @@ impl Channel {
         self.mode.m
     }
     ///Evalúa si el canal no tiene restricciones sobre quién puede enviar mensajes.
-
     pub fn is_not_msg_restricted(&self) -> bool {
         !self.is_no_msg_outside() && !self.is_moderated()
     }
@@ -282,36 +259,41 @@ impl Channel {
         self.mode.k
     }
     ///Evalúa si un determinado usuario puede enviar mensajes.
-    pub fn can_send_msg(&self, user_nickname: &String) -> bool {
+    pub fn can_send_msg(&self, user_nickname: &str) -> bool {
         self.is_admin(user_nickname) || self.can_speak(user_nickname)
     }
 
-    ///Evalúa si un determinado usuario esta banneado.
-    pub fn is_banned(&self, username: &String, hostname: &String) -> bool {
-        if self.ban_masks.is_empty() {
-            return false;
-        }
-        for mask in &self.ban_masks {
-            if check_banned(mask, username, hostname) {
-                return true;
-            }
-        }
-        false
+    ///Evalúa si un usuario, identificado por su prefijo `nick!user@host`, esta banneado. Las
+    /// máscaras de excepción (+e) tienen prioridad sobre los bans.
+    pub fn is_banned(&self, user_prefix: &str) -> bool {
+        self.ban_masks
+            .iter()
+            .any(|mask| matches_user_mask(mask, user_prefix))
+            && !self
+                .exception_masks
+                .iter()
+                .any(|mask| matches_user_mask(mask, user_prefix))
+    }
+    ///Evalúa si un usuario, identificado por su prefijo `nick!user@host`, puede entrar al canal
+    /// sin invitación por coincidir con alguna máscara +I.
+    pub fn is_invite_exempt(&self, user_prefix: &str) -> bool {
+        self.invite_masks
+            .iter()
+            .any(|mask| matches_user_mask(mask, user_prefix))
     }
 
     ///Obtiene un listado de los usuarios del canal.
     pub fn list_users(&self) -> Message {
         let mut line = String::from("Users from channel ") + &self.name;
-        for user_nickname in &self.users {
-            line = [line, user_nickname.clone()].join("\n");
+        for user_nickname in self.nicknames() {
+            line = [line, user_nickname].join("\n");
         }
         crate::message::Message::from(line)
     }
     ///Obtiene el topic del canal.
     pub fn get_topic(&self) -> String {
-        let topic = self.topic.clone();
-        match topic {
-            Some(topic_text) => topic_text,
+        match &self.topic {
+            Some(topic) => topic.text.clone(),
             None => "No topic is set".to_string(),
         }
     }
@@ -321,260 +303,368 @@ impl Channel {
             None => false,
         }
     }
-    pub fn change_topic(&mut self, topic: String) {
-        self.topic = Option::from(topic)
+    ///Cambia el tópico del canal, registrando quién lo hizo. Un texto vacío quita el tópico.
+    pub fn change_topic(&mut self, topic: String, set_by: &str) {
+        self.topic = Some(Topic {
+            text: topic,
+            set_by: set_by.to_string(),
+            set_at: unix_time(),
+        })
+        .filter(|topic| !topic.text.is_empty());
+    }
+    ///Aplica un cambio de tópico informado por otro servidor si es más reciente que el
+    /// actual. Si se cambiaron al mismo tiempo, gana el de mayor texto y luego el de mayor
+    /// autor, para que todos los servidores elijan el mismo. Devuelve si el tópico cambió.
+    pub fn merge_topic(&mut self, topic: Topic) -> bool {
+        let outdated = match &self.topic {
+            Some(current) => {
+                (topic.set_at, &topic.text, &topic.set_by)
+                    <= (current.set_at, &current.text, &current.set_by)
+            }
+            None => topic.text.is_empty(),
+        };
+        if outdated {
+            return false;
+        }
+        self.topic = Some(topic).filter(|topic| !topic.text.is_empty());
+        true
+    }
+    ///Obtiene las respuestas con el tópico del canal: RPL_TOPIC y RPL_TOPICWHOTIME, o
+    /// RPL_NOTOPIC si no tiene.
+    pub fn topic_replies(&self) -> Vec<Message> {
+        match &self.topic {
+            Some(topic) => vec![
+                rpl_topic(self.name.clone(), topic.text.clone()),
+                rpl_topic_who_time(self.name.clone(), topic.set_by.clone(), topic.set_at),
+            ],
+            None => vec![rpl_no_topic(self.name.clone())],
+        }
     }
     pub fn show_channel_topic(&self) -> Message {
         let line = format!("Topic from channel {}: {}", &self.name, &self.get_topic());
         crate::message::Message::from(line)
     }
-    pub fn correct_key(&self, parameters: Vec<String>) -> bool {
-        parameters.len() == 2 && self.password == Some(parameters[1].clone())
+    pub fn correct_key(&self, key: Option<&String>) -> bool {
+        key.is_some() && self.password.as_ref() == key
     }
 
-    pub fn activate_modes(
-        &mut self,
-        msg: Message,
-        mut response_vector: Vec<Message>,
-    ) -> Vec<Message> {
-        for mode in msg.parameters[1].chars().skip(1) {
-            response_vector = self.activate_mode(msg.clone(), mode, response_vector);
-        }
-        response_vector
-    }
-    pub fn deactivate_modes(
-        &mut self,
-        msg: Message,
-        mut response_vector: Vec<Message>,
-    ) -> Vec<Message> {
-        for mode in msg.parameters[1].chars().skip(1) {
-            response_vector = self.deactivate_mode(msg.clone(), mode, response_vector);
+    ///Combina el canal con la versión que informa otro servidor al vincularse, comparando el
+    /// momento de creación de cada uno: el canal más antiguo conserva sus modos, máscaras y
+    /// operadores, si se crearon al mismo tiempo se combinan, y el que pierde se queda sin
+    /// ellos. Los miembros de ambos lados quedan en el canal. Devuelve todos los cambios de
+    /// modo que sufrió este canal, para avisarles a sus miembros.
+    pub fn merge(&mut self, remote: Channel) -> Vec<ModeChange> {
+        let before = self.mode_state();
+        match remote.created_at.cmp(&self.created_at) {
+            Ordering::Less => {
+                for member in self.members.iter_mut() {
+                    member.status = MemberStatus::default();
+                }
+                self.created_at = remote.created_at;
+                self.mode = remote.mode.clone();
+                self.limit = remote.limit;
+                self.password = remote.password.clone();
+                self.ban_masks = remote.ban_masks.clone();
+                self.exception_masks = remote.exception_masks.clone();
+                self.invite_masks = remote.invite_masks.clone();
+            }
+            Ordering::Equal => {
+                self.set_modes(&remote.mode.letters());
+                self.limit = self.limit.or(remote.limit);
+                self.password = self.password.clone().or(remote.password.clone());
+                for (masks, remote_masks) in [
+                    (&mut self.ban_masks, &remote.ban_masks),
+                    (&mut self.exception_masks, &remote.exception_masks),
+                    (&mut self.invite_masks, &remote.invite_masks),
+                ] {
+                    for mask in remote_masks {
+                        if !masks.iter().any(|current| same_name(current, mask)) {
+                            masks.push(mask.clone());
+                        }
+                    }
+                }
+            }
+            Ordering::Greater => {}
         }
-        response_vector
-    }
-
-    pub fn activate_mode(
-        &mut self,
-        msg: Message,
-        mode: char,
-        mut response_vector: Vec<Message>,
-    ) -> Vec<Message> {
-        match mode {
-            'o' => self.operator_mode(msg, response_vector),
-            'p' => {
-                self.mode.activate_p();
-                response_vector
+        // solo se conservan los operadores y la voz del lado que no perdió
+        let keeps_remote_status = remote.created_at <= self.created_at;
+        for member in remote.members.iter() {
+            self.add_user(member.nickname.clone());
+            if keeps_remote_status && member.status.op {
+                self.add_admin(member.nickname.clone());
             }
-            's' => {
-                self.mode.activate_s();
-                response_vector
+            if keeps_remote_status && member.status.voice {
+                self.add_speaker(member.nickname.clone());
             }
-            't' => {
-                self.mode.activate_t();
-                response_vector
+        }
+        if let Some(topic) = remote.topic {
+            self.merge_topic(topic);
+        }
+        let after = self.mode_state();
+        let removed = before
+            .iter()
+            .filter(|change| !after.contains(change))
+            .map(|change| ModeChange {
+                adding: false,
+                mode: change.mode,
+                argument: change.argument.clone().filter(|_| change.mode != 'l'),
+            });
+        let added = after
+            .iter()
+            .filter(|change| !before.contains(change))
+            .cloned();
+        removed.chain(added).collect()
+    }
+    ///Obtiene el estado de los modos del canal como los cambios que lo activan: los modos con
+    /// su límite y clave, las máscaras de cada lista y los operadores y la voz de los miembros.
+    fn mode_state(&self) -> Vec<ModeChange> {
+        let mut state: Vec<ModeChange> = self
+            .mode
+            .letters()
+            .chars()
+            .map(|mode| ModeChange {
+                adding: true,
+                mode,
+                argument: match mode {
+                    'l' => self.limit.map(|limit| limit.to_string()),
+                    'k' => self.password.clone(),
+                    _ => None,
+                },
+            })
+            .collect();
+        for (mode, masks) in [
+            ('b', &self.ban_masks),
+            ('e', &self.exception_masks),
+            ('I', &self.invite_masks),
+        ] {
+            state.extend(masks.iter().map(|mask| ModeChange {
+                adding: true,
+                mode,
+                argument: Some(mask.clone()),
+            }));
+        }
+        for member in self.members.iter() {
+            for (active, mode) in [(member.status.op, 'o'), (member.status.voice, 'v')] {
+                if active {
+                    state.push(ModeChange {
+                        adding: true,
+                        mode,
+                        argument: Some(member.nickname.clone()),
+                    });
+                }
             }
-            'i' => {
-                self.mode.activate_i();
-                response_vector
+        }
+        state
+    }
+    ///Obtiene los parámetros de RPL_CHANNELMODEIS: las letras de los modos activos seguidas
+    /// del límite y de la clave. La clave solo se muestra a los miembros del canal.
+    pub fn mode_parameters(&self, show_key: bool) -> Vec<String> {
+        let mut parameters = vec!["+".to_string() + &self.mode.letters()];
+        if let Some(limit) = self.limit {
+            parameters.push(limit.to_string());
+        }
+        if let (Some(password), true) = (&self.password, show_key) {
+            parameters.push(password.clone());
+        }
+        parameters
+    }
+    ///Aplica un cambio de modo al canal. Devuelve si el canal cambió; los errores y los
+    /// listados de máscaras se agregan a las respuestas.
+    pub fn apply_mode(&mut self, change: &ModeChange, response_vector: &mut Vec<Message>) -> bool {
+        let argument = change.argument.as_ref();
+        match (change.adding, change.mode) {
+            (adding, 'o') => self.operator_mode(adding, argument, response_vector),
+            (adding, 'v') => self.speak_mode(adding, argument, response_vector),
+            (true, 'l') => self.limit_mode(argument, response_vector),
+            (false, 'l') => {
+                let changed = self.has_limit();
+                self.limit = None;
+                self.mode.deactivate_l();
+                changed
             }
-            'n' => {
-                self.mode.activate_n();
-                response_vector
+            (true, 'k') => self.key_mode(argument, response_vector),
+            (false, 'k') => {
+                let changed = self.has_key();
+                self.password = None;
+                self.mode.deactivate_k();
+                changed
             }
-            'm' => {
-                self.mode.activate_m();
-                response_vector
+            (adding, 'b' | 'e' | 'I') => {
+                self.mask_mode(adding, change.mode, argument, response_vector)
             }
-            'v' => self.speak_mode(msg, response_vector),
-            'l' => self.limit_mode(msg, response_vector),
-            'b' => self.ban_mode(msg, response_vector),
-            'k' => self.key_mode(msg, response_vector),
-            _ => {
+            (adding, 'p' | 's' | 'i' | 't' | 'n' | 'm') => self.mode.set(change.mode, adding),
+            (_, mode) => {
                 let unknown_mode = err_unknown_mode(mode);
                 response_vector.push(unknown_mode);
-                response_vector
+                false
             }
         }
     }
-    ///Si se cumplen las condiciones, otorga permisos de operador de canal al usuario
+    ///Si se cumplen las condiciones, otorga o quita permisos de operador de canal al usuario
     /// pasado por parámetro.
     pub fn operator_mode(
         &mut self,
-        msg: Message,
-        mut response_vector: Vec<Message>,
-    ) -> Vec<Message> {
-        if msg.parameters.len() < 3 {
-            let err_need_more_params = err_need_more_params(msg.command);
-            response_vector.push(err_need_more_params);
-        } else if !self.has_user(&msg.parameters[2]) {
-            let no_such_nick_msg = error_no_such_nick(msg.parameters[1].clone());
-            response_vector.push(no_such_nick_msg);
+        adding: bool,
+        argument: Option<&String>,
+        response_vector: &mut Vec<Message>,
+    ) -> bool {
+        let Some(nickname) = self.member_argument(argument, response_vector) else {
+            return false;
+        };
+        let changed = self.is_admin(&nickname) != adding;
+        if adding {
+            self.add_admin(nickname)
         } else {
-            self.add_admin(msg.parameters[2].clone())
+            self.remove_admin(nickname)
         }
-        response_vector
+        changed
     }
-    ///Si se cumplen las condiciones, quita permisos de operador de canal al usuario
-    /// pasado por parámetro.
-    pub fn deoperator_mode(
+    ///Si se cumplen las condiciones, agrega o quita al usuario pasado por parámetro la
+    /// posibilidad de enviar mensajes en ese canal.
+    pub fn speak_mode(
         &mut self,
-        msg: Message,
-        mut response_vector: Vec<Message>,
-    ) -> Vec<Message> {
-        if msg.parameters.len() < 3 {
-            let err_need_more_params = err_need_more_params(msg.command);
-            response_vector.push(err_need_more_params);
-        } else if !self.has_user(&msg.parameters[2]) {
-            let no_such_nick_msg = error_no_such_nick(msg.parameters[1].clone());
-            response_vector.push(no_such_nick_msg);
-        } else {
-            self.remove_admin(msg.parameters[2].clone())
-        }
-        response_vector
-    }
-    ///Si se cumplen las condiciones, agrega  al usuario pasado por parámetro la posibilidad
-    /// de enviar mensajes en ese canal.
-    pub fn speak_mode(&mut self, msg: Message, mut response_vector: Vec<Message>) -> Vec<Message> {
-        if msg.parameters.len() < 3 {
-            let err_need_more_params = err_need_more_params(msg.command);
-            response_vector.push(err_need_more_params);
-        } else if !self.has_user(&msg.parameters[2]) {
-            let no_such_nick_msg = error_no_such_nick(msg.parameters[1].clone());
-            response_vector.push(no_such_nick_msg);
+        adding: bool,
+        argument: Option<&String>,
+        response_vector: &mut Vec<Message>,
+    ) -> bool {
+        let Some(nickname) = self.member_argument(argument, response_vector) else {
+            return false;
+        };
+        let changed = self.can_speak(&nickname) != adding;
+        if adding {
+            self.add_speaker(nickname)
         } else {
-            self.add_speaker(msg.parameters[2].clone())
+            self.remove_speaker(nickname)
         }
-        response_vector
-    }
-    ///Si se cumplen las condiciones, le quita al usuario pasado por parámetro la posibilidad
-    /// de enviar mensajes en ese canal.
-    pub fn despeak_mode(
-        &mut self,
-        msg: Message,
-        mut response_vector: Vec<Message>,
-    ) -> Vec<Message> {
-        if msg.parameters.len() < 3 {
-            let err_need_more_params = err_need_more_params(msg.command);
-            response_vector.push(err_need_more_params);
-        } else if !self.has_user(&msg.parameters[2]) {
-            let no_such_nick_msg = error_no_such_nick(msg.parameters[1].clone());
-            response_vector.push(no_such_nick_msg);
-        } else {
-            self.remove_speaker(msg.parameters[2].clone())
+        changed
+    }
+    ///Obtiene el nick que recibe un modo de miembro (`o` o `v`), que debe estar en el canal.
+    fn member_argument(
+        &self,
+        argument: Option<&String>,
+        response_vector: &mut Vec<Message>,
+    ) -> Option<String> {
+        match argument {
+            None => {
+                let err_need_more_params = err_need_more_params("MODE".to_string());
+                response_vector.push(err_need_more_params);
+                None
+            }
+            Some(nickname) if !self.has_user(nickname) => {
+                let no_such_nick_msg = error_no_such_nick(nickname.clone());
+                response_vector.push(no_such_nick_msg);
+                None
+            }
+            Some(nickname) => Some(nickname.clone()),
         }
-        response_vector
     }
     ///Si se cumplen las condiciones, establece un límite de usuarios que pueden acceder
     /// al canal.
-    pub fn limit_mode(&mut self, msg: Message, mut response_vector: Vec<Message>) -> Vec<Message> {
-        if msg.parameters.len() < 3 {
-            let err_need_more_params = err_need_more_params(msg.command);
+    pub fn limit_mode(
+        &mut self,
+        argument: Option<&String>,
+        response_vector: &mut Vec<Message>,
+    ) -> bool {
+        let Some(argument) = argument else {
+            let err_need_more_params = err_need_more_params("MODE".to_string());
             response_vector.push(err_need_more_params);
-        }
+            return false;
+        };
         // el limite de usuarios en un canal solo se puede setear una vez y no puede ser menor que la cantidad de usuarios actual del canal
-        else if let Ok(limit) = msg.parameters[2].parse::<usize>() {
-            if !self.has_limit() && limit >= self.users.len() {
+        match argument.parse::<usize>() {
+            Ok(limit) if !self.has_limit() && limit >= self.members.len() => {
                 self.limit = Option::from(limit);
                 self.mode.activate_l();
+                true
             }
+            _ => false,
         }
-        response_vector
     }
-    ///Si se cumplen las condiciones, agrega una mascara de ban a la lista de
-    /// máscaras del canal.
-    pub fn ban_mode(&mut self, msg: Message, mut response_vector: Vec<Message>) -> Vec<Message> {
-        if msg.parameters.len() < 2 {
-            let err_need_more_params = err_need_more_params(msg.command);
-            response_vector.push(err_need_more_params);
-        } else if msg.parameters.len() == 2 {
-            for ban_mask in &self.ban_masks {
-                let ban_list = rpl_banlist(self.name.clone(), ban_mask.clone());
-                response_vector.push(ban_list)
+    ///Si se cumplen las condiciones, agrega o quita una máscara de ban (+b), de excepción de
+    /// ban (+e) o de excepción de invitación (+I). Sin máscara, devuelve la lista pedida.
+    pub fn mask_mode(
+        &mut self,
+        adding: bool,
+        mode: char,
+        argument: Option<&String>,
+        response_vector: &mut Vec<Message>,
+    ) -> bool {
+        let name = self.name.clone();
+        let (masks, list_reply, end_of_list_reply): (_, fn(String, String) -> Message, _) =
+            match mode {
+                'b' => (
+                    &mut self.ban_masks,
+                    rpl_banlist,
+                    rpl_end_of_ban_list(name.clone()),
+                ),
+                'e' => (
+                    &mut self.exception_masks,
+                    rpl_except_list,
+                    rpl_end_of_except_list(name.clone()),
+                ),
+                _ => (
+                    &mut self.invite_masks,
+                    rpl_invite_list,
+                    rpl_end_of_invite_list(name.clone()),
+                ),
+            };
+        match argument {
+            None => {
+                for mask in masks.iter() {
+                    response_vector.push(list_reply(name.clone(), mask.clone()));
+                }
+                response_vector.push(end_of_list_reply);
+                false
             }
-            let end_ban_list = rpl_end_of_ban_list(self.name.clone());
-            response_vector.push(end_ban_list)
-        } else if msg.parameters.len() == 3 && self.ban_masks.len() < 3 {
-            self.ban_masks.push(msg.parameters[2].clone());
-        }
-        response_vector
-    }
-    ///Si se cumplen las condiciones, quita una mascara de ban de la lista de
-    /// máscaras del canal.
-    pub fn unban_mode(&mut self, msg: Message, mut response_vector: Vec<Message>) -> Vec<Message> {
-        if msg.parameters.len() < 3 {
-            let err_need_more_params = err_need_more_params(msg.command);
-            response_vector.push(err_need_more_params);
-        } else {
-            self.remove_ban(msg.parameters[2].clone());
+            Some(mask) if adding => add_mask(masks, mask),
+            Some(mask) => remove_mask(masks, mask),
         }
-        response_vector
     }
     ///Establece la contraseña de un canal.
-    pub fn key_mode(&mut self, msg: Message, mut response_vector: Vec<Message>) -> Vec<Message> {
-        if msg.parameters.len() < 3 {
-            let err_need_more_params = err_need_more_params(msg.command);
-            response_vector.push(err_need_more_params);
-        } else if self.has_key() {
-            let err_key_set = err_key_set(self.name.clone());
-            response_vector.push(err_key_set);
-        } else {
-            self.password = Some(msg.parameters[2].clone());
-            self.mode.activate_k();
-        }
-        response_vector
-    }
-    pub fn deactivate_mode(
+    pub fn key_mode(
         &mut self,
-        msg: Message,
-        mode: char,
-        mut response_vector: Vec<Message>,
-    ) -> Vec<Message> {
-        match mode {
-            'o' => self.deoperator_mode(msg, response_vector),
-            'p' => {
-                self.mode.deactivate_p();
-                response_vector
-            }
-            's' => {
-                self.mode.deactivate_s();
-                response_vector
-            }
-            't' => {
-                self.mode.deactivate_t();
-                response_vector
-            }
-            'i' => {
-                self.mode.deactivate_i();
-                response_vector
+        argument: Option<&String>,
+        response_vector: &mut Vec<Message>,
+    ) -> bool {
+        match argument {
+            None => {
+                let err_need_more_params = err_need_more_params("MODE".to_string());
+                response_vector.push(err_need_more_params);
+                false
             }
-            'n' => {
-                self.mode.deactivate_n();
-                response_vector
+            Some(_) if self.has_key() => {
+                let err_key_set = err_key_set(self.name.clone());
+                response_vector.push(err_key_set);
+                false
             }
-            'm' => {
-                self.mode.deactivate_m();
-                response_vector
-            }
-            'v' => self.despeak_mode(msg, response_vector),
-            'l' => {
-                self.limit = None;
-                self.mode.deactivate_l();
-                response_vector
-            }
-            'b' => self.unban_mode(msg, response_vector),
-            'k' => {
-                self.password = None;
-                self.mode.deactivate_k();
-                response_vector
-            }
-            _ => {
-                let unknown_mode = err_unknown_mode(mode);
-                response_vector.push(unknown_mode);
-                response_vector
+            Some(key) => {
+                self.password = Some(key.clone());
+                self.mode.activate_k();
+                true
             }
         }
     }
 }
+///Agrega una máscara, completada a la forma `nick!user@host`, a una lista de máscaras del canal.
+/// Cada lista admite hasta tres máscaras. Devuelve si la máscara se agregó.
+fn add_mask(masks: &mut Vec<String>, new_mask: &str) -> bool {
+    let new_mask = normalize_mask(new_mask);
+    if masks.len() >= 3 || masks.iter().any(|mask| same_name(mask, &new_mask)) {
+        return false;
+    }
+    masks.push(new_mask);
+    true
+}
+///Quita una máscara de una lista de máscaras del canal. La máscara se completa a la forma
+/// `nick!user@host` antes de buscarla. Devuelve si la máscara estaba en la lista.
+fn remove_mask(masks: &mut Vec<String>, removed_mask: &str) -> bool {
+    let removed_mask = normalize_mask(removed_mask);
+    masks
+        .iter()
+        .position(|mask| same_name(&removed_mask, mask))
+        .map(|position| masks.remove(position))
+        .is_some()
+}
 ///Crea un canal si el nombre seteado es válido.
 pub fn create_valid_channel(name: String) -> Result<Channel, InvalidMessageError> {
     let vec_bytes = name.as_bytes();
@@ -592,117 +682,175 @@ pub fn create_valid_channel(name: String) -> Result<Channel, InvalidMessageError
         Ok(Channel::new(&name))
     }
 }
-///Evalúa si un usuario esta banneado en un canal.
-pub fn check_banned(mask: &str, username: &String, hostname: &String) -> bool {
-    let mask_parts: Vec<&str> = mask.split('@').collect();
-    let mask_username = &mask_parts[0][1..];
-    let mask_hostname = &mask_parts[1];
-    match_ban_expression(mask_username, username) && match_ban_expression(mask_hostname, hostname)
-}
-pub fn match_ban_expression(mask_part: &str, query_part: &String) -> bool {
-    if mask_part == "*" || mask_part.is_empty() {
-        return true;
-    }
-    match mask_part.strip_prefix('*') {
-        Some(stripped) => query_part.ends_with(stripped),
-        None => mask_part == query_part,
-    }
-}
 
 /// Se encarga de interpretar el mensaje de JOIN enviado a un servidor
 /// y en caso de éxito agregar al usuario al canal o crear un nuevo canal y luego agregar
-/// al usuario.
-
+/// al usuario. Cada ingreso se les informa a los miembros del canal y al resto de la red.
 pub fn join_msg(
-    msg: Message,
-    mut user_nickname: String,
-    users: Arc<Mutex<Vec<User>>>,
-    channels: Arc<Mutex<Vec<Channel>>>,
-    connected_servers: Arc<Mutex<Vec<Server>>>,
-    server_name: String,
+    channel_names: Vec<String>,
+    keys: Vec<String>,
+    user_nickname: String,
+    users: &mut Registry<User>,
+    channels: &mut Registry<Channel>,
+    connected_servers: &[Server],
+    received_from: &str,
 ) -> Result<Vec<Message>, ServerError> {
     let mut response_vector = Vec::new();
-    let mut lock_user = users.lock().unwrap();
-    if msg.parameters[0].starts_with('&') && msg.parameters.len() == 2 {
-        user_nickname = msg.parameters[1].clone();
-    }
-    if let Some(user) = lock_user
-        .iter_mut()
-        .find(|user| user.nickname == user_nickname)
-    {
-        if msg.parameters.is_empty() {
-            let need_more_params_message = err_need_more_params(msg.command);
-            response_vector.push(need_more_params_message);
-            return Ok(response_vector);
-        }
-        //si existe el canal, agrego al usuario
-        let mut lock = channels.lock().unwrap();
-
-        for channel_name in msg.parameters[0].split(',') {
-            if let Some(channel) = lock.iter_mut().find(|channel| channel.name == channel_name) {
-                if channel.name.starts_with('&') {
-                    let mut invite_msg =
-                        Message::from(format!("JOIN {} {}", channel_name, user_nickname));
-                    if msg.prefix.is_none() {
-                        invite_msg = Message::from(format!(
-                            ":{} JOIN {} {}",
-                            server_name.clone(),
-                            channel_name,
-                            user_nickname.clone()
-                        ));
-                    }
+    let mut joins = Vec::new();
+    if let Some(user) = users.get_mut(&user_nickname) {
+        let prefix = user.prefix();
+        let mut new_channels = Vec::new();
 
-                    forward_invite_msg(invite_msg, connected_servers.clone());
-                }
-                let replies = add_user_to_channel(channel, user, msg.parameters.clone());
+        for (position, channel_name) in channel_names.iter().enumerate() {
+            let replies_start = response_vector.len();
+            //si existe el canal, agrego al usuario
+            if let Some(channel) = channels.get_mut(channel_name) {
+                let was_member = channel.has_user(&user_nickname);
+                let replies = add_user_to_channel(channel, user, keys.get(position));
                 response_vector.extend(replies);
+                if was_member || !channel.has_user(&user_nickname) {
+                    continue;
+                }
+                joins.push(channel.name.clone());
             }
             //si no existe el canal, creo el canal, agrego al usuario y sumo el canal al server
             else {
-                let channel = create_valid_channel(channel_name.to_string().clone());
+                let channel = create_valid_channel(channel_name.clone());
                 match channel {
                     Err(_e) => {
-                        let no_such_channel_message =
-                            err_no_such_channel(channel_name.to_string().clone());
+                        let no_such_channel_message = err_no_such_channel(channel_name.clone());
                         response_vector.push(no_such_channel_message);
+                        continue;
                     }
                     Ok(mut channel) => {
                         channel.add_user(user_nickname.clone());
-                        user.add_channel(&channel_name.to_string());
+                        user.add_channel(channel_name);
                         channel.add_admin(user_nickname.clone());
-                        let topic_message =
-                            rpl_topic(channel.name.clone(), channel.get_topic().clone());
-                        let namerply_message =
-                            rpl_name_rply(channel.name.clone(), channel.users.clone());
+                        let topic_messages = channel.topic_replies();
+                        let namerply_message = names_reply(&channel, channel.nicknames());
                         let end_of_names_message = rpl_end_of_names(channel.name.clone());
-                        notify_new_channel(
-                            users.clone(),
-                            channel.name.clone(),
-                            channel.get_topic(),
-                        );
-                        lock.push(channel);
-                        response_vector.push(topic_message);
+                        new_channels.push((channel.name.clone(), channel.get_topic()));
+                        joins.push(channel.name.clone());
+                        let _ = channels.insert(channel);
+                        response_vector.extend(topic_messages);
                         response_vector.push(namerply_message);
                         response_vector.push(end_of_names_message);
                     }
                 }
             }
+            // El usuario recibe su propio JOIN antes que el tópico y los nombres del canal
+            if user.socket.is_some() {
+                let join = join_event_msg(&prefix, &joins[joins.len() - 1]);
+                response_vector.insert(replies_start, join);
+            }
+        }
+        for (channel_name, channel_topic) in new_channels {
+            notify_new_channel(users, &channel_name, &channel_topic);
+        }
+        for channel_name in joins {
+            let join = join_event_msg(&prefix, &channel_name);
+            if let Some(channel) = channels.get(&channel_name) {
+                send_to_channel_members(channel, users, &join, &user_nickname);
+            }
+            propagate_user_msg(join, &user_nickname, received_from, connected_servers);
         }
     }
     Ok(response_vector)
 }
-///Notifica al resto de los servidores que existe un nuevo canal
-fn notify_new_channel(users: Arc<Mutex<Vec<User>>>, channel_name: String, channel_topic: String) {
-    thread::spawn(move || {
-        let users_lock = users.lock().unwrap();
-        for u in users_lock.iter() {
-            let _ = u.send_private_message(rpl_list(
-                channel_name.to_string(),
-                channel_topic.to_string(),
-            ));
-            let _ = u.send_private_message(rpl_list_end());
-        }
-    });
+///Arma el mensaje con el que se informa un evento de un usuario, con su prefijo
+/// `nick!user@host`.
+pub fn user_event_msg(prefix: &str, command: Command) -> Message {
+    let mut msg = Message::from(command);
+    msg.prefix = Some(prefix.to_string());
+    msg
+}
+///Arma los mensajes con los que se le informa un canal a otro servidor: uno o más SJOIN con
+/// sus modos y sus miembros, sin superar el largo máximo de un mensaje, un BMASK por cada
+/// lista de máscaras y el tópico con quien lo estableció y cuándo.
+pub fn channel_burst(channel: &Channel, servername: &str) -> Vec<Message> {
+    let sjoin = |members: Vec<String>| {
+        let command = Command::Sjoin {
+            created_at: channel.created_at,
+            channel: channel.name.clone(),
+            modes: channel.mode_parameters(true),
+            members,
+        };
+        user_event_msg(servername, command)
+    };
+    // largo del SJOIN sin miembros, contando el CR-LF final
+    let empty_length = String::from(sjoin(Vec::new())).len() + 2;
+    let mut burst = Vec::new();
+    let mut members = Vec::new();
+    let mut length = empty_length;
+    for member in channel.get_members_list() {
+        if !members.is_empty() && length + member.len() + 1 > MAX_MESSAGE_LENGTH {
+            burst.push(sjoin(std::mem::take(&mut members)));
+            length = empty_length;
+        }
+        length += member.len() + 1;
+        members.push(member);
+    }
+    burst.push(sjoin(members));
+
+    for (mode, masks) in [
+        ('b', &channel.ban_masks),
+        ('e', &channel.exception_masks),
+        ('I', &channel.invite_masks),
+    ] {
+        if !masks.is_empty() {
+            let command = Command::Bmask {
+                created_at: channel.created_at,
+                channel: channel.name.clone(),
+                mode,
+                masks: masks.clone(),
+            };
+            burst.push(user_event_msg(servername, command));
+        }
+    }
+    if let Some(topic) = &channel.topic {
+        let command = Command::Topic {
+            channel: channel.name.clone(),
+            topic: Some(topic.text.clone()),
+            set_at: Some(topic.set_at),
+        };
+        burst.push(user_event_msg(&topic.set_by, command));
+    }
+    burst
+}
+///Arma el JOIN de un usuario a un canal.
+pub fn join_event_msg(prefix: &str, channel_name: &str) -> Message {
+    let command = Command::Join {
+        channels: vec![channel_name.to_string()],
+        keys: Vec::new(),
+    };
+    user_event_msg(prefix, command)
+}
+///Envía un mensaje a los miembros del canal conectados a este servidor, salvo al usuario que
+/// lo originó.
+pub fn send_to_channel_members(
+    channel: &Channel,
+    users: &Registry<User>,
+    message: &Message,
+    sender: &str,
+) {
+    for member in channel.members.iter() {
+        if let Some(user) = users.get(&member.nickname) {
+            if user.socket.is_some() && !same_name(&member.nickname, sender) {
+                let _ = user.send_private_message(message.clone());
+            }
+        }
+    }
+}
+///Notifica a los usuarios que existe un nuevo canal
+fn notify_new_channel(users: &[User], channel_name: &str, channel_topic: &str) {
+    for u in users.iter() {
+        let _ = u.send_reply(rpl_list(
+            channel_name.to_string(),
+            1,
+            channel_topic.to_string(),
+        ));
+        let _ = u.send_reply(rpl_list_end());
+    }
 }
 
 /// Si el usuario tiene permitido ingresar al canal, lo agrega.
@@ -710,73 +858,84 @@ fn notify_new_channel(users: Arc<Mutex<Vec<User>>>, channel_name: String, channe
 pub fn add_user_to_channel(
     channel: &mut Channel,
     user: &mut User,
-    parameters: Vec<String>,
+    key: Option<&String>,
 ) -> Vec<Message> {
     let mut responses = Vec::new();
-    if channel.is_invite_only() {
+    if channel.is_invite_only() && !channel.is_invite_exempt(&user.prefix()) {
         let err_invite_only = err_invite_only_chan(channel.name.clone());
         responses.push(err_invite_only);
-    } else if channel.is_banned(&user.username, &user.hostname) {
+    } else if channel.is_banned(&user.prefix()) {
         let err_banned = err_banned_from_chan(channel.name.clone());
         responses.push(err_banned);
     } else if channel.has_limit() && !channel.has_free_space() {
         let err_channel_full = err_channel_is_full(channel.name.clone());
         responses.push(err_channel_full);
-    } else if channel.has_key() && !channel.correct_key(parameters) {
+    } else if channel.has_key() && !channel.correct_key(key) {
         let err_bad_chan_key = err_bad_channel_key(channel.name.clone());
         responses.push(err_bad_chan_key);
     } else {
         channel.add_user(user.nickname.clone());
         user.add_channel(&channel.name.clone());
-        let topic_message = rpl_topic(channel.name.clone(), channel.get_topic());
-        let namerply_message = rpl_name_rply(channel.name.clone(), channel.users.clone());
+        let namerply_message = names_reply(channel, channel.nicknames());
         let end_of_names_message = rpl_end_of_names(channel.name.clone());
-        responses.push(topic_message);
+        responses.extend(channel.topic_replies());
         responses.push(namerply_message);
         responses.push(end_of_names_message);
-        list_channel(channel, user, &mut responses)
     }
 
     responses
 }
 /// Se encarga de interpretar el mensaje de PART enviado a un servidor
-/// y en caso de éxito quitar al usuario del canal.
+/// y en caso de éxito quitar al usuario del canal, avisándoles a sus miembros y al resto de la red.
 pub fn part_msg(
-    msg: Message,
+    channel_names: Vec<String>,
+    reason: Option<String>,
     user_nickname: String,
-    users: Arc<Mutex<Vec<User>>>,
-    channels: Arc<Mutex<Vec<Channel>>>,
+    users: &mut Registry<User>,
+    channels: &mut Registry<Channel>,
+    connected_servers: &[Server],
+    received_from: &str,
 ) -> Result<Vec<Message>, ServerError> {
     let mut response_vector = Vec::new();
-    let mut lock_user = users.lock().unwrap();
-    if let Some(user) = lock_user
-        .iter_mut()
-        .find(|user| user.nickname == user_nickname)
-    {
-        if msg.parameters.is_empty() {
-            let need_more_params_message = err_need_more_params(msg.command);
-            response_vector.push(need_more_params_message);
-            return Ok(response_vector);
-        }
+    if let Some(user) = users.get(&user_nickname) {
+        let prefix = user.prefix();
+        let is_local = user.socket.is_some();
+        let mut parts = Vec::new();
         //Busco si existe el canal
-        let mut lock = channels.lock().unwrap();
-        for channel_name in msg.parameters[0].split(',') {
-            if let Some(channel) = lock.iter_mut().find(|channel| channel.name == channel_name) {
-                //si el canal tiene al usuario
+        for channel_name in channel_names.iter() {
+            if let Some(channel) = channels.get_mut(channel_name) {
+                //si el canal tiene al usuario, se les avisa a todos los miembros antes de quitarlo
                 if channel.has_user(&user_nickname) {
+                    let command = Command::Part {
+                        channels: vec![channel.name.clone()],
+                        message: reason.clone(),
+                    };
+                    let part = user_event_msg(&prefix, command);
+                    send_to_channel_members(channel, users, &part, &user_nickname);
+                    if is_local {
+                        response_vector.push(part.clone());
+                    }
                     channel.remove_user(&user_nickname);
-                    user.leave_channel(&channel_name.to_string())
+                    parts.push((channel.name.clone(), part));
                 } else {
-                    let not_on_channel = err_not_on_channel(channel_name.to_string().clone());
+                    let not_on_channel = err_not_on_channel(channel_name.clone());
                     response_vector.push(not_on_channel);
                 }
             }
             //si no existe el canal
             else {
-                let no_such_channel_message = err_no_such_channel(channel_name.to_string().clone());
+                let no_such_channel_message = err_no_such_channel(channel_name.clone());
                 response_vector.push(no_such_channel_message);
             }
         }
+        if let Some(user) = users.get_mut(&user_nickname) {
+            for (channel_name, _) in parts.iter() {
+                user.leave_channel(channel_name);
+            }
+        }
+        for (_, part) in parts {
+            propagate_user_msg(part, &user_nickname, received_from, connected_servers);
+        }
     }
 
     Ok(response_vector)
@@ -784,45 +943,33 @@ pub fn part_msg(
 
 /// Le permite a un usuario convertirse en operador del canal.
 pub fn oper_ch_msg(
-    msg: Message,
-    users: Arc<Mutex<Vec<User>>>,
-    channels: Arc<Mutex<Vec<Channel>>>,
+    username: &str,
+    password: &str,
+    users: &Registry<User>,
+    channels: &mut Registry<Channel>,
     nickname: String,
 ) -> Result<Vec<Message>, ServerError> {
     let mut response_vector = Vec::new();
 
-    if msg.parameters.len() != 3 {
-        let more_params = err_need_more_params(msg.command);
-        response_vector.push(more_params);
-        println!("Incorrect number of parameters in message");
-        return Ok(response_vector);
-    }
-    let lock_users = users.lock().unwrap();
-    let mut lock_channel = channels.lock().unwrap();
-    let _current_user = lock_users
+    users
+        .get(&nickname)
+        .ok_or_else(|| ServerError::new("Cannot obtain current user"))?;
+    match users
         .iter()
-        .find(|user| user.nickname == nickname)
-        .unwrap();
-    match lock_users
-        .iter()
-        .find(|user| user.username == msg.parameters[1] && user.password == msg.parameters[2])
+        .find(|user| user.username == username && user.password == password)
         .ok_or(())
     {
         Ok(admin) => {
-            match lock_channel
+            match channels
                 .iter()
                 .find(|channel| channel.has_user(&admin.nickname))
                 .ok_or(())
             {
                 Ok(channel) => {
-                    let mut aux_channel = channel.clone();
-                    let index = lock_channel
-                        .iter()
-                        .position(|x| x.name == channel.name)
-                        .unwrap();
-                    lock_channel.remove(index);
-                    aux_channel.add_admin(admin.clone().nickname);
-                    lock_channel.push(aux_channel);
+                    let channel_name = channel.name.clone();
+                    if let Some(channel) = channels.get_mut(&channel_name) {
+                        channel.add_admin(admin.nickname.clone());
+                    }
                     let oper_rply = rpl_you_are_oper();
                     response_vector.push(oper_rply);
                 }
@@ -847,29 +994,17 @@ pub fn oper_ch_msg(
 /// y en caso de éxito agregar al usuario invitado al canal. Si no, le notifica el motivo
 /// por el cual no pudo ser agregado.
 pub fn invite_msg(
-    msg: Message,
-    users: Arc<Mutex<Vec<User>>>,
+    invited_nickname: &str,
+    channel_name: &str,
+    users: &mut Registry<User>,
     nick: String,
-    channels: Arc<Mutex<Vec<Channel>>>,
+    channels: &mut Registry<Channel>,
 ) -> Result<Vec<Message>, ServerError> {
     let mut response_vector = Vec::new();
-    if msg.parameters.len() != 2 {
-        let need_more_params_message = err_need_more_params(msg.command);
-        response_vector.push(need_more_params_message);
-        return Ok(response_vector);
-    }
-    let mut lock_user = users.lock().unwrap();
     //si existe el usuario que se quiere invitar
-    if let Some(invited_user) = lock_user
-        .iter_mut()
-        .find(|user| user.nickname == msg.parameters[0])
-    {
-        let mut lock_channel = channels.lock().unwrap();
+    if let Some(invited_user) = users.get_mut(invited_nickname) {
         //si existe el canal
-        if let Some(channel) = lock_channel
-            .iter_mut()
-            .find(|channel| channel.name == msg.parameters[1])
-        {
+        if let Some(channel) = channels.get_mut(channel_name) {
             // si el usuario que invita no esta en el canal
             if !channel.has_user(&nick) {
                 let err_not_on_channel = err_not_on_channel(channel.name.clone());
@@ -892,22 +1027,22 @@ pub fn invite_msg(
                 response_vector.push(err_channel_full);
             }
             // si el usuario a invitar esta banneado
-            else if channel.is_banned(&invited_user.username, &invited_user.hostname) {
+            else if channel.is_banned(&invited_user.prefix()) {
                 let err_banned = err_banned_from_chan(channel.name.clone());
                 response_vector.push(err_banned);
             } else {
                 channel.add_user(invited_user.nickname.clone());
-                invited_user.add_channel(&msg.parameters[1]);
+                invited_user.add_channel(&channel.name.clone());
                 let inviting_message =
                     rpl_inviting(invited_user.nickname.clone(), channel.name.clone());
                 response_vector.push(inviting_message)
             }
         } else {
-            let no_such_nick_msg = error_no_such_nick(msg.parameters[1].clone());
+            let no_such_nick_msg = error_no_such_nick(channel_name.to_string());
             response_vector.push(no_such_nick_msg)
         }
     } else {
-        let no_such_nick_msg = error_no_such_nick(msg.parameters[0].clone());
+        let no_such_nick_msg = error_no_such_nick(invited_nickname.to_string());
         response_vector.push(no_such_nick_msg)
     }
 
@@ -916,95 +1051,120 @@ pub fn invite_msg(
 /// Se encarga de interpretar el mensaje de NAMES enviado a un servidor
 /// y en caso de éxito informar los nombres de los usuarios del canal.
 pub fn names_msg(
-    msg: Message,
-    users: Arc<Mutex<Vec<User>>>,
+    channel_names: Vec<String>,
+    users: &Registry<User>,
     nick: String,
-    channels: Arc<Mutex<Vec<Channel>>>,
+    channels: &Registry<Channel>,
 ) -> Result<Vec<Message>, ServerError> {
     let mut response_vector = Vec::new();
-    let lock_user = users.lock().unwrap();
-    let user = lock_user.iter().find(|user| user.nickname == nick).unwrap();
+    let user = users
+        .get(&nick)
+        .ok_or_else(|| ServerError::new("Cannot obtain current user"))?;
 
-    let lock_channel = channels.lock().unwrap();
-    if msg.parameters.is_empty() {
+    if channel_names.is_empty() {
+        // sin parámetros se listan todos los canales visibles y, al final, los usuarios
+        // visibles que no están en ninguno de ellos
         let mut listed_users = HashSet::new();
-        for channel in lock_channel.iter() {
+        for channel in channels.iter() {
             if let Some(channel_users) = names_channel(channel, user) {
-                if channel.has_user(&user.nickname) || channel.is_visible() {
-                    let namerply_message =
-                        rpl_name_rply(channel.name.clone(), channel.users.clone());
-                    let end_of_names_message = rpl_end_of_names(channel.name.clone().to_string());
-                    response_vector.push(namerply_message);
-                    response_vector.push(end_of_names_message);
-                }
-                listed_users.extend(channel_users);
-            }
-        }
-        let mut not_listed_users = Vec::new();
-        for element in lock_user.iter() {
-            if element.channels.is_empty() || !listed_users.contains(&element.nickname) {
-                not_listed_users.push(element.nickname.clone());
+                let namerply_message = names_reply(channel, visible_members(channel, users, user));
+                let end_of_names_message = rpl_end_of_names(channel.name.clone());
+                response_vector.push(namerply_message);
+                response_vector.push(end_of_names_message);
+                listed_users.extend(channel_users.iter().map(|member| casefold(member)));
             }
         }
+        let not_listed_users: Vec<String> = users
+            .iter()
+            .filter(|element| {
+                !listed_users.contains(&casefold(&element.nickname)) && element.is_visible_to(user)
+            })
+            .map(|element| element.nickname.clone())
+            .collect();
         if !not_listed_users.is_empty() {
-            let namerply_message = rpl_name_rply("*".to_string(), not_listed_users);
+            let namerply_message = rpl_name_rply('*', "*".to_string(), not_listed_users);
             let end_of_names_message = rpl_end_of_names("*".to_string());
             response_vector.push(namerply_message);
             response_vector.push(end_of_names_message);
         }
     } else {
-        for channel_name in msg.parameters[0].split(',') {
-            if let Some(channel) = lock_channel
-                .iter()
-                .find(|channel| channel.name == channel_name)
-            {
-                if channel.has_user(&user.nickname) || channel.is_visible() {
+        for channel_name in channel_names.iter() {
+            // los canales inexistentes, secretos o privados solo reciben el fin de la lista
+            match channels.get(channel_name) {
+                Some(channel) if names_channel(channel, user).is_some() => {
                     let namerply_message =
-                        rpl_name_rply(channel.name.clone(), channel.users.clone());
-                    let end_of_names_message = rpl_end_of_names(channel.name.clone().to_string());
+                        names_reply(channel, visible_members(channel, users, user));
                     response_vector.push(namerply_message);
-                    response_vector.push(end_of_names_message);
+                    response_vector.push(rpl_end_of_names(channel.name.clone()));
                 }
+                _ => response_vector.push(rpl_end_of_names(channel_name.clone())),
             }
         }
     }
     Ok(response_vector)
 }
+///Arma la respuesta RPL_NAMREPLY de un canal, con el tipo de canal y el estado de cada miembro.
+fn names_reply(channel: &Channel, members: Vec<String>) -> Message {
+    let members = members
+        .iter()
+        .map(|member| channel.member_with_prefix(member))
+        .collect();
+    rpl_name_rply(channel.names_type(), channel.name.clone(), members)
+}
+///Obtiene los miembros de un canal que un usuario puede ver: todos si pertenece al canal y,
+/// si no, solo los que no son invisibles.
+pub fn visible_members(channel: &Channel, users: &Registry<User>, user: &User) -> Vec<String> {
+    if channel.has_user(&user.nickname) {
+        return channel.nicknames();
+    }
+    channel
+        .members
+        .iter()
+        .map(|member| &member.nickname)
+        .filter(|member| users.get(member).is_none_or(|member| !member.modes.i))
+        .cloned()
+        .collect()
+}
 pub fn names_channel(channel: &Channel, user: &User) -> Option<HashSet<String>> {
     if channel.has_user(&user.nickname) || channel.is_visible() {
-        Some(HashSet::from_iter(channel.users.clone()))
+        Some(HashSet::from_iter(channel.nicknames()))
     } else {
         None
     }
 }
 
 /// Se encarga de interpretar el mensaje de LIST enviado a un servidor
-/// y en caso de éxito listar los canales y sus tópicos, si los canales no son secretos.
+/// y en caso de éxito listar los canales y sus tópicos, si los canales no son secretos. Los
+/// nombres pedidos pueden ser máscaras con `*` y `?`.
 pub fn list_msg(
-    msg: Message,
+    channel_names: Vec<String>,
     nick: String,
-    users: Arc<Mutex<Vec<User>>>,
-    channels: Arc<Mutex<Vec<Channel>>>,
+    users: &Registry<User>,
+    channels: &Registry<Channel>,
 ) -> Result<Vec<Message>, ServerError> {
     let mut response_vector = Vec::new();
     let list_start_message = rpl_list_start();
     response_vector.push(list_start_message);
 
-    let lock_user = users.lock().unwrap();
-    let user = lock_user.iter().find(|user| user.nickname == nick).unwrap();
+    let user = users
+        .get(&nick)
+        .ok_or_else(|| ServerError::new("Cannot obtain current user"))?;
 
-    let lock_channel = channels.lock().unwrap();
-    if msg.parameters.is_empty() {
-        for channel in lock_channel.iter() {
-            list_channel(channel, user, &mut response_vector);
+    if channel_names.is_empty() {
+        for channel in channels.iter() {
+            list_channel(channel, users, user, &mut response_vector);
         }
     } else {
-        for channel_name in msg.parameters[0].split(',') {
-            if let Some(channel) = lock_channel
-                .iter()
-                .find(|channel| channel.name == channel_name)
-            {
-                list_channel(channel, user, &mut response_vector)
+        for channel_name in channel_names.iter() {
+            if has_wildcards(channel_name) {
+                for channel in channels
+                    .iter()
+                    .filter(|channel| matches_casemapped(channel_name, &channel.name))
+                {
+                    list_channel(channel, users, user, &mut response_vector);
+                }
+            } else if let Some(channel) = channels.get(channel_name) {
+                list_channel(channel, users, user, &mut response_vector)
             }
         }
     }
@@ -1013,121 +1173,182 @@ pub fn list_msg(
     Ok(response_vector)
 }
 
-pub fn list_channel(channel: &Channel, user: &User, response_vector: &mut Vec<Message>) {
+pub fn list_channel(
+    channel: &Channel,
+    users: &Registry<User>,
+    user: &User,
+    response_vector: &mut Vec<Message>,
+) {
+    let visible_users = visible_members(channel, users, user).len();
     if channel.is_visible() || (channel.is_secret() && channel.has_user(&user.nickname)) {
-        let rpl_list = rpl_list(channel.name.clone(), channel.get_topic());
+        let rpl_list = rpl_list(channel.name.clone(), visible_users, channel.get_topic());
         response_vector.push(rpl_list);
     } else if channel.is_private() {
         if channel.has_user(&user.nickname) {
-            let rpl_list = rpl_list(channel.name.clone(), channel.get_topic());
+            let rpl_list = rpl_list(channel.name.clone(), visible_users, channel.get_topic());
             response_vector.push(rpl_list);
         } else {
-            let rpl_list = rpl_list(channel.name.clone(), "".to_string());
+            let rpl_list = rpl_list(channel.name.clone(), visible_users, "".to_string());
             response_vector.push(rpl_list);
         }
     }
 }
 
 /// Se encarga de interpretar el mensaje de TOPIC enviado a un servidor
-/// y en caso de éxito informar el tópico del canal o cambiarlo.
+/// y en caso de éxito informar el tópico del canal o cambiarlo, avisándoles a sus miembros
+/// y al resto de la red. Los cambios informados por otros servidores traen el momento en que
+/// se hicieron y solo se aplican si son más recientes que el tópico actual.
 pub fn topic_msg(
-    msg: Message,
+    channel_name: &str,
+    new_topic: Option<String>,
+    set_at: Option<u64>,
     nick: String,
-    channels: Arc<Mutex<Vec<Channel>>>,
+    server: &mut Server,
+    received_from: &str,
 ) -> Result<Vec<Message>, ServerError> {
     let mut response_vector = Vec::new();
-    if msg.parameters.is_empty() {
-        let need_more_params_message = err_need_more_params(msg.command);
-        response_vector.push(need_more_params_message);
-        return Ok(response_vector);
-    }
-    let mut lock_channel = channels.lock().unwrap();
     //si existe el canal
-    if let Some(channel) = lock_channel
-        .iter_mut()
-        .find(|channel| channel.name == msg.parameters[0])
-    {
-        //TOPIC channel devuelve el topico
-        if msg.parameters.len() == 1 {
-            if !channel.has_user(&nick) {
-                let not_on_channel = err_not_on_channel(channel.name.clone());
-                response_vector.push(not_on_channel);
-                return Ok(response_vector);
-            } else if channel.has_topic() {
-                let rpl_topic = rpl_topic(channel.name.clone(), channel.get_topic());
-                response_vector.push(rpl_topic);
-                return Ok(response_vector);
-            } else {
-                let rpl_no_topic = rpl_no_topic(channel.name.clone());
-                response_vector.push(rpl_no_topic);
-                return Ok(response_vector);
-            }
+    let Some(channel) = server.channels.get_mut(channel_name) else {
+        return Ok(response_vector);
+    };
+    //TOPIC channel devuelve el topico
+    let Some(new_topic) = new_topic else {
+        if !channel.has_user(&nick) {
+            let not_on_channel = err_not_on_channel(channel.name.clone());
+            response_vector.push(not_on_channel);
+        } else {
+            response_vector.extend(channel.topic_replies());
         }
+        return Ok(response_vector);
+    };
 
-        //TOPIC channel topic , cambia el topico
-        if msg.parameters.len() == 2 {
-            if (channel.is_topic_operator_only() && channel.is_admin(&nick))
-                || (!channel.is_topic_operator_only() && channel.has_user(&nick))
-            {
-                channel.change_topic(msg.parameters[1].clone());
-                let rpl_topic = rpl_topic(channel.name.clone(), channel.get_topic());
-                response_vector.push(rpl_topic);
-                return Ok(response_vector);
-            } else {
-                let chan_opriv_msg = err_chan_opriv_is_needed(channel.name.clone());
-                response_vector.push(chan_opriv_msg)
-            }
+    //TOPIC channel topic , cambia el topico
+    let changed = match set_at {
+        Some(set_at) => channel.merge_topic(Topic {
+            text: new_topic.clone(),
+            set_by: nick.clone(),
+            set_at,
+        }),
+        None if (channel.is_topic_operator_only() && channel.is_admin(&nick))
+            || (!channel.is_topic_operator_only() && channel.has_user(&nick)) =>
+        {
+            channel.change_topic(new_topic.clone(), &nick);
+            response_vector.extend(channel.topic_replies());
+            true
+        }
+        None => {
+            let chan_opriv_msg = err_chan_opriv_is_needed(channel.name.clone());
+            response_vector.push(chan_opriv_msg);
+            false
         }
+    };
+    if changed {
+        let prefix = match server.users.get(&nick) {
+            Some(user) => user.prefix(),
+            None => nick.clone(),
+        };
+        let command = Command::Topic {
+            channel: channel.name.clone(),
+            topic: Some(new_topic.clone()),
+            set_at: None,
+        };
+        let topic = user_event_msg(&prefix, command);
+        send_to_channel_members(channel, &server.users, &topic, &nick);
+        // los demás servidores reciben el momento del cambio para resolver conflictos
+        let set_at = set_at.unwrap_or_else(|| {
+            channel
+                .topic
+                .as_ref()
+                .map_or_else(unix_time, |topic| topic.set_at)
+        });
+        let command = Command::Topic {
+            channel: channel.name.clone(),
+            topic: Some(new_topic),
+            set_at: Some(set_at),
+        };
+        propagate_user_msg(
+            Message::from(command),
+            &nick,
+            received_from,
+            &server.connected_servers,
+        );
     }
-
     Ok(response_vector)
 }
 
 /// Se encarga de interpretar el mensaje de MODE enviado a un servidor
 /// y de agregar o quitar modos en la configuración del canal.
 pub fn mode_msg(
-    msg: Message,
+    target: &str,
+    changes: Vec<String>,
     nick: String,
-    channels: Arc<Mutex<Vec<Channel>>>,
+    users: &Registry<User>,
+    channels: &mut Registry<Channel>,
+    connected_servers: &[Server],
+    received_from: &str,
 ) -> Result<Vec<Message>, ServerError> {
     let mut response_vector = Vec::new();
-    if msg.parameters.len() < 2 {
-        let need_more_params_message = err_need_more_params(msg.command);
-        response_vector.push(need_more_params_message);
+    let Some(channel) = channels.get_mut(target) else {
+        let no_such_channel_message = err_no_such_channel(target.to_string());
+        response_vector.push(no_such_channel_message);
+        return Ok(response_vector);
+    };
+    // sin modos se informan los modos actuales del canal
+    if changes.is_empty() {
+        let parameters = channel.mode_parameters(channel.has_user(&nick));
+        response_vector.push(rpl_channel_mode_is(channel.name.clone(), parameters));
+        response_vector.push(rpl_creation_time(channel.name.clone(), channel.created_at));
         return Ok(response_vector);
     }
-    let mut lock_channel = channels.lock().unwrap();
-    if let Some(channel) = lock_channel
-        .iter_mut()
-        .find(|channel| channel.name == msg.parameters[0])
-    {
-        if !channel.is_admin(&nick) {
-            let chan_opriv_msg = err_chan_opriv_is_needed(channel.name.clone());
-            response_vector.push(chan_opriv_msg);
-            return Ok(response_vector);
-        }
-        // evaluar si activa o desactiva modos
-        let activate_flag = msg.parameters[1].chars().next().unwrap();
-        let response_vector = match activate_flag {
-            '+' => channel.activate_modes(msg, response_vector),
-            '-' => channel.deactivate_modes(msg, response_vector),
-            _ => {
-                let unkwnown_mode = err_unknown_mode(activate_flag);
-                response_vector.push(unkwnown_mode);
-                response_vector
-            }
-        };
-        Ok(response_vector)
-    } else {
-        let no_such_channel_message = err_no_such_channel(msg.parameters[0].clone());
-        response_vector.push(no_such_channel_message);
-        Ok(response_vector)
+    // evaluar si el texto de modos empieza activando o desactivando
+    let activate_flag = changes[0].chars().next().unwrap_or(' ');
+    if activate_flag != '+' && activate_flag != '-' {
+        let unkwnown_mode = err_unknown_mode(activate_flag);
+        response_vector.push(unkwnown_mode);
+        return Ok(response_vector);
+    }
+    // los cambios que llegan de otro servidor ya se validaron allí y se aplican sin revisar
+    let is_admin = !received_from.is_empty() || channel.is_admin(&nick);
+    let mut applied = Vec::new();
+    let mut needs_privileges = false;
+    for change in parse_mode_changes(&changes) {
+        // cualquiera puede consultar las listas de máscaras, pero solo los operadores cambian modos
+        let is_list_query = matches!(change.mode, 'b' | 'e' | 'I') && change.argument.is_none();
+        if !is_admin && !is_list_query {
+            needs_privileges = true;
+        } else if channel.apply_mode(&change, &mut response_vector) {
+            applied.push(change);
+        }
+    }
+    if needs_privileges {
+        let chan_opriv_msg = err_chan_opriv_is_needed(channel.name.clone());
+        response_vector.push(chan_opriv_msg);
     }
+    if applied.is_empty() {
+        return Ok(response_vector);
+    }
+    let prefix = match users.get(&nick) {
+        Some(user) => user.prefix(),
+        None => nick.clone(),
+    };
+    let command = Command::Mode {
+        target: channel.name.clone(),
+        changes: mode_changes_parameters(&applied),
+    };
+    let mode = user_event_msg(&prefix, command);
+    send_to_channel_members(channel, users, &mode, &nick);
+    if users.get(&nick).is_some_and(|user| user.socket.is_some()) {
+        response_vector.push(mode.clone());
+    }
+    propagate_user_msg(mode, &nick, received_from, connected_servers);
+    Ok(response_vector)
 }
 
 #[cfg(test)]
 mod tests_channel {
-    use crate::channel::create_valid_channel;
+    use crate::channel::{channel_burst, create_valid_channel, Channel, Topic};
+    use crate::channel_modes::mode_changes_parameters;
+    use crate::message::{Message, MAX_MESSAGE_LENGTH};
 
     #[test]
     fn test_recibir_nombre_valido_crea_canal() {
@@ -1142,7 +1363,7 @@ mod tests_channel {
         let mut channel = create_valid_channel(channel_name).unwrap();
         let user_nickname = "nick1".to_string();
         channel.add_user(user_nickname);
-        assert!(channel.has_user(&"nick1".to_string()));
+        assert!(channel.has_user("nick1"));
     }
 
     #[test]
@@ -1151,9 +1372,9 @@ mod tests_channel {
         let mut channel = create_valid_channel(channel_name).unwrap();
         let user_nickname = "nick1".to_string();
         channel.add_user(user_nickname);
-        assert!(channel.has_user(&"nick1".to_string()));
-        channel.remove_user(&"nick1".to_string());
-        assert!(!channel.has_user(&"nick1".to_string()));
+        assert!(channel.has_user("nick1"));
+        channel.remove_user("nick1");
+        assert!(!channel.has_user("nick1"));
     }
 
     #[test]
@@ -1171,60 +1392,214 @@ mod tests_channel {
         let user_nickname2 = "nick2".to_string();
         channel.add_user(user_nickname);
         channel.add_user(user_nickname2);
-        assert_eq!(channel.get_users_list(), ",nick1,nick2");
+        channel.add_admin("nick2".to_string());
+        assert_eq!(channel.get_members_list(), ["nick1", "@nick2"]);
+        channel.remove_user("nick1");
+        channel.remove_user("nick2");
+        assert!(channel.get_members_list().is_empty());
+    }
+
+    #[test]
+    fn test_renombrar_usuario_actualiza_miembros_operadores_y_voz() {
+        let mut channel = create_valid_channel("#channel1".to_string()).unwrap();
+        channel.add_user("nick1".to_string());
+        channel.add_admin("nick1".to_string());
+        channel.add_speaker("nick1".to_string());
+
+        assert!(!channel.rename_user("nick2", "nick3"));
+        assert!(channel.rename_user("NICK1", "nuevo"));
+        assert!(channel.has_user("nuevo") && !channel.has_user("nick1"));
+        assert!(channel.is_admin("nuevo"));
+        assert!(channel.can_speak("nuevo"));
+    }
+
+    fn channel_created_at(created_at: u64, operator: &str) -> Channel {
+        let mut channel = create_valid_channel("#channel1".to_string()).unwrap();
+        channel.created_at = created_at;
+        channel.add_admin(operator.to_string());
+        channel
+    }
+
+    #[test]
+    fn test_canal_mas_antiguo_conserva_modos_y_operadores_al_combinar() {
+        let mut local = channel_created_at(200, "local");
+        local.mode.activate_t();
+        local.add_speaker("local".to_string());
+        local.ban_masks.push("malo!*@*".to_string());
+        let mut remote = channel_created_at(100, "remoto");
+        remote.mode.activate_n();
+
+        let changes = local.merge(remote);
+
+        assert_eq!(
+            vec!["-tbov+no", "malo!*@*", "local", "local", "remoto"],
+            mode_changes_parameters(&changes)
+        );
+        assert!(local.ban_masks.is_empty());
+        assert_eq!(100, local.created_at);
+        assert_eq!("n", local.mode.letters());
+        assert!(local.has_user("local") && !local.is_admin("local"));
+        assert!(local.is_admin("remoto"));
+    }
+
+    #[test]
+    fn test_canal_mas_nuevo_pierde_modos_y_operadores_del_otro_lado() {
+        let mut local = channel_created_at(100, "local");
+        let mut remote = channel_created_at(200, "remoto");
+        remote.mode.activate_m();
+
+        assert!(local.merge(remote).is_empty());
+        assert_eq!(100, local.created_at);
+        assert_eq!("", local.mode.letters());
+        assert!(local.is_admin("local"));
+        assert!(local.has_user("remoto") && !local.is_admin("remoto"));
+    }
+
+    #[test]
+    fn test_canales_creados_al_mismo_tiempo_combinan_modos_y_operadores() {
+        let mut local = channel_created_at(100, "local");
+        local.mode.activate_t();
+        let mut remote = channel_created_at(100, "remoto");
+        remote.mode.activate_n();
+        remote.ban_masks.push("malo!*@*".to_string());
+
+        assert_eq!(
+            vec!["+nbo", "malo!*@*", "remoto"],
+            mode_changes_parameters(&local.merge(remote))
+        );
+        assert_eq!("tn", local.mode.letters());
+        assert!(local.is_admin("local") && local.is_admin("remoto"));
+        assert_eq!(vec!["malo!*@*".to_string()], local.ban_masks);
+    }
+
+    #[test]
+    fn test_burst_de_canal_informa_modos_miembros_mascaras_y_topico() {
+        let mut channel = channel_created_at(100, "nick0");
+        channel.mode.activate_t();
+        channel.limit = Some(50);
+        channel.mode.activate_l();
+        channel.ban_masks.push("malo!*@*".to_string());
+        channel.topic = Some(Topic {
+            text: "un topic con espacios".to_string(),
+            set_by: "nick0".to_string(),
+            set_at: 150,
+        });
+        for number in 1..60 {
+            channel.add_user(format!("usuario_numero_{}", number));
+        }
+
+        let burst: Vec<String> = channel_burst(&channel, "server1")
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        let sjoins: Vec<&String> = burst
+            .iter()
+            .filter(|line| line.starts_with(":server1 SJOIN 100 #channel1 +tl 50 :"))
+            .collect();
+        assert!(sjoins.len() > 1);
+        assert!(sjoins
+            .iter()
+            .all(|line| line.len() + 2 <= MAX_MESSAGE_LENGTH));
+        assert!(sjoins[0].contains(":@nick0 usuario_numero_1 "));
+        let members: usize = sjoins
+            .iter()
+            .map(|line| {
+                Message::from(line.to_string()).parameters[4]
+                    .split(' ')
+                    .count()
+            })
+            .sum();
+        assert_eq!(60, members);
+        assert!(burst.contains(&":server1 BMASK 100 #channel1 b malo!*@*".to_string()));
+        assert_eq!(
+            ":nick0 TOPIC #channel1 150 :un topic con espacios",
+            burst[burst.len() - 1]
+        );
     }
 }
 
 #[cfg(test)]
 mod tests_channel_msgs {
 
-    use std::sync::{Arc, Mutex};
     //use std::sync::mpsc::channel;
     use crate::channel::{
-        invite_msg, join_msg, list_msg, mode_msg, names_msg, part_msg, topic_msg, Channel,
+        invite_msg, join_msg, list_msg, mode_msg, names_msg, part_msg, topic_msg, Channel, Topic,
     };
+    use crate::interpreter::process_message;
     use crate::message::Message;
+    use crate::registry::Registry;
     use crate::server::Server;
     use crate::user::User;
 
+    fn server_with(users: Registry<User>, channels: Registry<Channel>) -> Server {
+        let mut server = Server::new();
+        server.users = users;
+        server.channels = channels;
+        server
+    }
+
     #[test]
     fn test_join_con_nombre_valido_crea_canal() {
         let mut user = User::new(None);
         user.nickname = "nick1".to_string();
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(Vec::new()));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::new();
+        let con_servers: Vec<Server> = vec![];
         let result = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
         assert_eq!(result.len(), 3);
-        assert_eq!(result[0].command, "332");
+        assert_eq!(result[0].command, "331");
         assert_eq!(result[1].command, "353");
         assert_eq!(result[2].command, "366");
     }
 
+    #[test]
+    fn test_join_a_canal_con_topic_devuelve_topic_y_nombres() {
+        let mut user = User::new(None);
+        user.nickname = "nick1".to_string();
+        let mut users = Registry::from(vec![user]);
+        let mut channel = Channel::new(&"#canal1".to_string());
+        channel.change_topic("my topic".to_string(), "nick2");
+        let mut channels = Registry::from(vec![channel]);
+        let result = join_msg(
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &[],
+            "",
+        )
+        .unwrap();
+        let commands: Vec<&str> = result.iter().map(|reply| reply.command.as_str()).collect();
+        assert_eq!(vec!["332", "333", "353", "366"], commands);
+        assert_eq!(result[0].parameters, ["#canal1", "my topic"]);
+    }
+
     #[test]
     fn test_join_con_nombre_invalido_devuelve_no_such_channel() {
         let mut user = User::new(None);
         user.nickname = "nick1".to_string();
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(Vec::new()));
-        let msg = Message::from("JOIN canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::new();
+        let con_servers: Vec<Server> = vec![];
         let result = join_msg(
-            msg,
+            vec!["canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
         assert_eq!(result.len(), 1);
@@ -1234,27 +1609,31 @@ mod tests_channel_msgs {
     fn test_tres_joins_validos_genera_resultado_correcto() {
         let mut user = User::new(None);
         user.nickname = "nick1".to_string();
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(Vec::new()));
-        let msg = Message::from("JOIN #canal1,#canal2,#canal3".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::new();
+        let con_servers: Vec<Server> = vec![];
         let result = join_msg(
-            msg,
+            vec![
+                "#canal1".to_string(),
+                "#canal2".to_string(),
+                "#canal3".to_string(),
+            ],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
         assert_eq!(result.len(), 9);
-        assert_eq!(result[0].command, "332");
+        assert_eq!(result[0].command, "331");
         assert_eq!(result[1].command, "353");
         assert_eq!(result[2].command, "366");
-        assert_eq!(result[3].command, "332");
+        assert_eq!(result[3].command, "331");
         assert_eq!(result[4].command, "353");
         assert_eq!(result[5].command, "366");
-        assert_eq!(result[6].command, "332");
+        assert_eq!(result[6].command, "331");
         assert_eq!(result[7].command, "353");
         assert_eq!(result[8].command, "366");
     }
@@ -1264,15 +1643,17 @@ mod tests_channel_msgs {
         user.nickname = "nick1".to_string();
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_user("nick1".to_string());
-        user.add_channel(&"#canal1".to_string());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg_part = Message::from("PART #canal1".to_string());
+        user.add_channel("#canal1");
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
         let result = part_msg(
-            msg_part,
+            vec!["#canal1".to_string()],
+            None,
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
+            &mut users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
         assert_eq!(result.len(), 0);
@@ -1283,15 +1664,14 @@ mod tests_channel_msgs {
         user.nickname = "nick1".to_string();
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_user("nick1".to_string());
-        user.add_channel(&"#canal1".to_string());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
+        user.add_channel("#canal1");
+        let users = Registry::from(vec![user]);
+        let channels = Registry::from(vec![channel]);
         let msg_part = Message::from("PART".to_string());
-        let result = part_msg(
+        let result = process_message(
             msg_part,
-            "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
+            &mut server_with(users, channels),
+            &mut "nick1".to_string(),
         )
         .unwrap();
         assert_eq!(result.len(), 1);
@@ -1302,14 +1682,16 @@ mod tests_channel_msgs {
     fn test_part_con_canal_no_existente_devuelve_no_such_channel() {
         let mut user = User::new(None);
         user.nickname = "nick1".to_string();
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(Vec::new()));
-        let msg_part = Message::from("PART #canal2".to_string());
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::new();
         let result = part_msg(
-            msg_part,
+            vec!["#canal2".to_string()],
+            None,
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
+            &mut users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
         assert_eq!(result.len(), 1);
@@ -1321,52 +1703,119 @@ mod tests_channel_msgs {
         let mut user = User::new(None);
         user.nickname = "nick1".to_string();
         let channel = Channel::new(&"#canal2".to_string());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg_part = Message::from("PART #canal2".to_string());
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
         let result = part_msg(
-            msg_part,
+            vec!["#canal2".to_string()],
+            None,
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
+            &mut users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].command, "442");
     }
     #[test]
-    fn test_names_de_un_canal_devuelve_usuarios_del_canal() {
+    fn test_names_de_un_canal_devuelve_usuarios_del_canal() {
+        let mut user = User::new(None);
+        user.nickname = "nick1".to_string();
+        let mut user2 = User::new(None);
+        user2.nickname = "nick2".to_string();
+        let mut user3 = User::new(None);
+        user3.nickname = "nick3".to_string();
+        let mut user4 = User::new(None);
+        user4.nickname = "nick4".to_string();
+        let users = Registry::from(vec![user, user2, user3, user4]);
+        let mut channel = Channel::new(&"#canal1".to_string());
+        channel.add_user("nick1".to_string());
+        channel.add_user("nick2".to_string());
+        channel.add_user("nick3".to_string());
+        channel.add_user("nick4".to_string());
+
+        let channels = Registry::from(vec![channel]);
+        let result = names_msg(
+            vec!["#canal1".to_string()],
+            &users,
+            "nick1".to_string(),
+            &channels,
+        )
+        .unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].command, "353");
+        assert_eq!(
+            result[0].parameters,
+            vec!["=", "#canal1", "nick1 nick2 nick3 nick4"]
+        );
+        assert_eq!(result[1].command, "366");
+    }
+    #[test]
+    fn test_names_oculta_usuarios_invisibles_a_quienes_no_son_del_canal() {
+        let mut user = User::new(None);
+        user.nickname = "nick1".to_string();
+        let mut user2 = User::new(None);
+        user2.nickname = "nick2".to_string();
+        user2.modes.i = true;
+        let mut user3 = User::new(None);
+        user3.nickname = "nick3".to_string();
+        let users = Registry::from(vec![user, user2, user3]);
+        let mut channel = Channel::new(&"#canal1".to_string());
+        channel.add_user("nick1".to_string());
+        channel.add_user("nick2".to_string());
+        let channels = Registry::from(vec![channel]);
+        let names = |nick: &str| {
+            names_msg(
+                vec!["#canal1".to_string()],
+                &users,
+                nick.to_string(),
+                &channels,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(names("nick3")[0].parameters, vec!["=", "#canal1", "nick1"]);
+        assert_eq!(
+            names("nick1")[0].parameters,
+            vec!["=", "#canal1", "nick1 nick2"]
+        );
+    }
+    #[test]
+    fn test_names_indica_tipo_de_canal_y_estado_de_los_miembros() {
         let mut user = User::new(None);
         user.nickname = "nick1".to_string();
         let mut user2 = User::new(None);
         user2.nickname = "nick2".to_string();
-        let mut user3 = User::new(None);
-        user3.nickname = "nick3".to_string();
-        let mut user4 = User::new(None);
-        user4.nickname = "nick4".to_string();
-        let users = Arc::new(Mutex::new(vec![user, user2, user3, user4]));
+        let users = Registry::from(vec![user, user2]);
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_user("nick1".to_string());
         channel.add_user("nick2".to_string());
-        channel.add_user("nick3".to_string());
-        channel.add_user("nick4".to_string());
+        channel.add_admin("nick1".to_string());
+        channel.add_speaker("nick2".to_string());
+        channel.mode.activate_s();
+        let mut channel2 = Channel::new(&"#canal2".to_string());
+        channel2.add_user("nick1".to_string());
+        channel2.mode.activate_p();
+        let channels = Registry::from(vec![channel, channel2]);
+        let names = |nick: &str| {
+            names_msg(
+                vec!["#canal1".to_string(), "#canal2".to_string()],
+                &users,
+                nick.to_string(),
+                &channels,
+            )
+            .unwrap()
+        };
 
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg_names = Message::from("NAMES #canal1".to_string());
-        let result = names_msg(
-            msg_names,
-            users.clone(),
-            "nick1".to_string(),
-            channels.clone(),
-        )
-        .unwrap();
-        assert_eq!(result.len(), 2);
-        assert_eq!(result[0].command, "353");
-        assert_eq!(
-            result[0].parameters,
-            vec!["#canal1", " nick1 nick2 nick3 nick4"]
-        );
-        assert_eq!(result[1].command, "366");
+        let result = names("nick1");
+        assert_eq!(result[0].parameters, vec!["@", "#canal1", "@nick1 +nick2"]);
+        assert_eq!(result[2].parameters, vec!["*", "#canal2", "nick1"]);
+        let result = names("nick2");
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].parameters, vec!["@", "#canal1", "@nick1 +nick2"]);
+        assert_eq!(result[2].command, "366");
+        assert_eq!(result[2].parameters[0], "#canal2");
     }
     #[test]
     fn test_names_de_dos_canales_devuelve_usuarios_de_canales() {
@@ -1378,7 +1827,7 @@ mod tests_channel_msgs {
         user3.nickname = "nick3".to_string();
         let mut user4 = User::new(None);
         user4.nickname = "nick4".to_string();
-        let users = Arc::new(Mutex::new(vec![user, user2, user3, user4]));
+        let users = Registry::from(vec![user, user2, user3, user4]);
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_user("nick1".to_string());
         channel.add_user("nick2".to_string());
@@ -1387,21 +1836,23 @@ mod tests_channel_msgs {
         channel2.add_user("nick3".to_string());
         channel2.add_user("nick4".to_string());
 
-        let channels = Arc::new(Mutex::new(vec![channel, channel2]));
-        let msg_names = Message::from("NAMES #canal1,#canal2".to_string());
+        let channels = Registry::from(vec![channel, channel2]);
         let result = names_msg(
-            msg_names,
-            users.clone(),
+            vec!["#canal1".to_string(), "#canal2".to_string()],
+            &users,
             "nick1".to_string(),
-            channels.clone(),
+            &channels,
         )
         .unwrap();
         assert_eq!(result.len(), 4);
         assert_eq!(result[0].command, "353");
-        assert_eq!(result[0].parameters, vec!["#canal1", " nick1 nick2"]);
+        assert_eq!(result[0].parameters, vec!["=", "#canal1", "nick1 nick2"]);
         assert_eq!(result[1].command, "366");
         assert_eq!(result[2].command, "353");
-        assert_eq!(result[2].parameters, vec!["#canal2", " nick1 nick3 nick4"]);
+        assert_eq!(
+            result[2].parameters,
+            vec!["=", "#canal2", "nick1 nick3 nick4"]
+        );
         assert_eq!(result[3].command, "366");
     }
     #[test]
@@ -1416,35 +1867,31 @@ mod tests_channel_msgs {
         user4.nickname = "nick4".to_string();
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_user("nick1".to_string());
-        user.add_channel(&"#canal1".to_string());
+        user.add_channel("#canal1");
         channel.add_user("nick2".to_string());
-        user2.add_channel(&"#canal1".to_string());
+        user2.add_channel("#canal1");
         let mut channel2 = Channel::new(&"#canal2".to_string());
         channel2.add_user("nick1".to_string());
-        user.add_channel(&"#canal2".to_string());
+        user.add_channel("#canal2");
         channel2.add_user("nick3".to_string());
-        user3.add_channel(&"#canal2".to_string());
+        user3.add_channel("#canal2");
         channel2.add_user("nick4".to_string());
-        user4.add_channel(&"#canal2".to_string());
+        user4.add_channel("#canal2");
 
-        let users = Arc::new(Mutex::new(vec![user, user2, user3, user4]));
+        let users = Registry::from(vec![user, user2, user3, user4]);
 
-        let channels = Arc::new(Mutex::new(vec![channel, channel2]));
+        let channels = Registry::from(vec![channel, channel2]);
 
-        let msg_names = Message::from("NAMES".to_string());
-        let result = names_msg(
-            msg_names,
-            users.clone(),
-            "nick1".to_string(),
-            channels.clone(),
-        )
-        .unwrap();
+        let result = names_msg(vec![], &users, "nick1".to_string(), &channels).unwrap();
         assert_eq!(result.len(), 4);
         assert_eq!(result[0].command, "353");
-        assert_eq!(result[0].parameters, vec!["#canal1", " nick1 nick2"]);
+        assert_eq!(result[0].parameters, vec!["=", "#canal1", "nick1 nick2"]);
         assert_eq!(result[1].command, "366");
         assert_eq!(result[2].command, "353");
-        assert_eq!(result[2].parameters, vec!["#canal2", " nick1 nick3 nick4"]);
+        assert_eq!(
+            result[2].parameters,
+            vec!["=", "#canal2", "nick1 nick3 nick4"]
+        );
         assert_eq!(result[3].command, "366");
     }
     #[test]
@@ -1462,35 +1909,31 @@ mod tests_channel_msgs {
         user5.nickname = "nick5".to_string();
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_user("nick1".to_string());
-        user.add_channel(&"#canal1".to_string());
+        user.add_channel("#canal1");
         channel.add_user("nick2".to_string());
-        user2.add_channel(&"#canal1".to_string());
+        user2.add_channel("#canal1");
         let mut channel2 = Channel::new(&"#canal2".to_string());
         channel2.add_user("nick1".to_string());
-        user.add_channel(&"#canal2".to_string());
+        user.add_channel("#canal2");
         channel2.add_user("nick3".to_string());
-        user3.add_channel(&"#canal2".to_string());
+        user3.add_channel("#canal2");
         channel2.add_user("nick4".to_string());
-        user4.add_channel(&"#canal2".to_string());
-        let users = Arc::new(Mutex::new(vec![user, user2, user3, user4, user5]));
-        let channels = Arc::new(Mutex::new(vec![channel, channel2]));
-        let msg_names = Message::from("NAMES".to_string());
-        let result = names_msg(
-            msg_names,
-            users.clone(),
-            "nick1".to_string(),
-            channels.clone(),
-        )
-        .unwrap();
+        user4.add_channel("#canal2");
+        let users = Registry::from(vec![user, user2, user3, user4, user5]);
+        let channels = Registry::from(vec![channel, channel2]);
+        let result = names_msg(vec![], &users, "nick1".to_string(), &channels).unwrap();
         assert_eq!(result.len(), 6);
         assert_eq!(result[0].command, "353");
-        assert_eq!(result[0].parameters, vec!["#canal1", " nick1 nick2"]);
+        assert_eq!(result[0].parameters, vec!["=", "#canal1", "nick1 nick2"]);
         assert_eq!(result[1].command, "366");
         assert_eq!(result[2].command, "353");
-        assert_eq!(result[2].parameters, vec!["#canal2", " nick1 nick3 nick4"]);
+        assert_eq!(
+            result[2].parameters,
+            vec!["=", "#canal2", "nick1 nick3 nick4"]
+        );
         assert_eq!(result[3].command, "366");
         assert_eq!(result[4].command, "353");
-        assert_eq!(result[4].parameters, vec!["*", " nick5"]);
+        assert_eq!(result[4].parameters, vec!["*", "*", "nick5"]);
     }
     #[test]
     fn test_invite_de_un_canal_devuelve_rpl_inviting() {
@@ -1500,15 +1943,15 @@ mod tests_channel_msgs {
         user2.nickname = "nick2".to_string();
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_user("nick1".to_string());
-        user.add_channel(&"canal1".to_string());
-        let users = Arc::new(Mutex::new(vec![user, user2]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg_invite = Message::from("INVITE nick2 #canal1".to_string());
+        user.add_channel("canal1");
+        let mut users = Registry::from(vec![user, user2]);
+        let mut channels = Registry::from(vec![channel]);
         let result = invite_msg(
-            msg_invite,
-            users.clone(),
+            "nick2",
+            "#canal1",
+            &mut users,
             "nick1".to_string(),
-            channels.clone(),
+            &mut channels,
         )
         .unwrap();
         assert_eq!(result.len(), 1);
@@ -1520,25 +1963,24 @@ mod tests_channel_msgs {
         user.nickname = "nick1".to_string();
         let mut user2 = User::new(None);
         user2.nickname = "nick2".to_string();
-        let users = Arc::new(Mutex::new(vec![user, user2]));
-        let channels = Arc::new(Mutex::new(Vec::new()));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user, user2]);
+        let mut channels = Registry::new();
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
         let msg_invite = Message::from("INVITE nick2".to_string());
-        let result = invite_msg(
+        let result = process_message(
             msg_invite,
-            users.clone(),
-            "nick1".to_string(),
-            channels.clone(),
+            &mut server_with(users, channels),
+            &mut "nick1".to_string(),
         )
         .unwrap();
         assert_eq!(result.len(), 1);
@@ -1552,15 +1994,15 @@ mod tests_channel_msgs {
         user2.nickname = "nick2".to_string();
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_user("nick1".to_string());
-        user.add_channel(&"#canal1".to_string());
-        let users = Arc::new(Mutex::new(vec![user, user2]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg_invite = Message::from("INVITE nick3 #canal1".to_string());
+        user.add_channel("#canal1");
+        let mut users = Registry::from(vec![user, user2]);
+        let mut channels = Registry::from(vec![channel]);
         let result = invite_msg(
-            msg_invite,
-            users.clone(),
+            "nick3",
+            "#canal1",
+            &mut users,
             "nick1".to_string(),
-            channels.clone(),
+            &mut channels,
         )
         .unwrap();
         assert_eq!(result.len(), 1);
@@ -1574,16 +2016,16 @@ mod tests_channel_msgs {
         user2.nickname = "nick2".to_string();
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_user("nick1".to_string());
-        user.add_channel(&"canal1".to_string());
+        user.add_channel("canal1");
         channel.mode.activate_i();
-        let users = Arc::new(Mutex::new(vec![user, user2]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg_invite = Message::from("INVITE nick2 #canal1".to_string());
+        let mut users = Registry::from(vec![user, user2]);
+        let mut channels = Registry::from(vec![channel]);
         let result = invite_msg(
-            msg_invite,
-            users.clone(),
+            "nick2",
+            "#canal1",
+            &mut users,
             "nick1".to_string(),
-            channels.clone(),
+            &mut channels,
         )
         .unwrap();
         assert_eq!(result.len(), 1);
@@ -1597,17 +2039,17 @@ mod tests_channel_msgs {
         user2.nickname = "nick2".to_string();
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_user("nick1".to_string());
-        user.add_channel(&"canal1".to_string());
+        user.add_channel("canal1");
         channel.mode.activate_i();
         channel.add_admin("nick1".to_string());
-        let users = Arc::new(Mutex::new(vec![user, user2]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg_invite = Message::from("INVITE nick2 #canal1".to_string());
+        let mut users = Registry::from(vec![user, user2]);
+        let mut channels = Registry::from(vec![channel]);
         let result = invite_msg(
-            msg_invite,
-            users.clone(),
+            "nick2",
+            "#canal1",
+            &mut users,
             "nick1".to_string(),
-            channels.clone(),
+            &mut channels,
         )
         .unwrap();
         assert_eq!(result.len(), 1);
@@ -1617,112 +2059,188 @@ mod tests_channel_msgs {
     fn test_list_de_un_canal_devuelve_rpl_list() {
         let mut user = User::new(None);
         user.nickname = "nick1".to_string();
-        let users = Arc::new(Mutex::new(vec![user]));
+        let users = Registry::from(vec![user]);
         let mut channel = Channel::new(&"#canal1".to_string());
-        channel.topic = Some("my topic".to_string());
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("LIST #canal1".to_string());
-        let result = list_msg(msg, "nick1".to_string(), users.clone(), channels.clone()).unwrap();
+        channel.change_topic("my topic".to_string(), "nick1");
+        let channels = Registry::from(vec![channel]);
+        let result = list_msg(
+            vec!["#canal1".to_string()],
+            "nick1".to_string(),
+            &users,
+            &channels,
+        )
+        .unwrap();
         assert_eq!(result.len(), 3);
         assert_eq!(result[0].command, "321");
         assert_eq!(result[1].command, "322");
-        assert_eq!(result[1].parameters, vec!["#canal1", "#", "my topic"]);
+        assert_eq!(result[1].parameters, vec!["#canal1", "0", "my topic"]);
         assert_eq!(result[2].command, "323");
     }
     #[test]
     fn test_list_de_un_canal_privado_devuelve_rpl_list_sin_topic() {
         let mut user = User::new(None);
         user.nickname = "nick1".to_string();
-        let users = Arc::new(Mutex::new(vec![user]));
+        let users = Registry::from(vec![user]);
         let mut channel = Channel::new(&"#canal1".to_string());
-        channel.topic = Some("my topic".to_string());
+        channel.change_topic("my topic".to_string(), "nick1");
         channel.mode.activate_p();
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("LIST #canal1".to_string());
-        let result = list_msg(msg, "nick1".to_string(), users.clone(), channels.clone()).unwrap();
+        let channels = Registry::from(vec![channel]);
+        let result = list_msg(
+            vec!["#canal1".to_string()],
+            "nick1".to_string(),
+            &users,
+            &channels,
+        )
+        .unwrap();
         assert_eq!(result.len(), 3);
         assert_eq!(result[0].command, "321");
         assert_eq!(result[1].command, "322");
-        assert_eq!(result[1].parameters, vec!["#canal1", "#"]);
+        assert_eq!(result[1].parameters, vec!["#canal1", "0", ""]);
         assert_eq!(result[2].command, "323");
     }
     #[test]
     fn test_list_de_un_canal_privado_con_usuario_en_canal_devuelve_rpl_list() {
         let mut user = User::new(None);
         user.nickname = "nick1".to_string();
-        let users = Arc::new(Mutex::new(vec![user]));
+        let mut users = Registry::from(vec![user]);
         let mut channel = Channel::new(&"#canal1".to_string());
-        channel.topic = Some("my topic".to_string());
+        channel.change_topic("my topic".to_string(), "nick1");
         channel.mode.activate_p();
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
-        let msg_list = Message::from("LIST #canal1".to_string());
         let result = list_msg(
-            msg_list,
+            vec!["#canal1".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
+            &users,
+            &channels,
         )
         .unwrap();
         assert_eq!(result.len(), 3);
         assert_eq!(result[0].command, "321");
         assert_eq!(result[1].command, "322");
-        assert_eq!(result[1].parameters, vec!["#canal1", "#", "my topic"]);
+        assert_eq!(result[1].parameters, vec!["#canal1", "1", "my topic"]);
         assert_eq!(result[2].command, "323");
     }
     #[test]
     fn test_list_sin_parametro_devuelve_info_de_canales() {
         let mut user = User::new(None);
         user.nickname = "nick1".to_string();
-        let users = Arc::new(Mutex::new(vec![user]));
+        let users = Registry::from(vec![user]);
         let mut channel = Channel::new(&"#canal1".to_string());
-        channel.topic = Some("my topic".to_string());
+        channel.change_topic("my topic".to_string(), "nick1");
         channel.mode.activate_p();
         let mut channel2 = Channel::new(&"#canal2".to_string());
-        channel2.topic = Some("my topic2".to_string());
+        channel2.change_topic("my topic2".to_string(), "nick1");
         let mut channel3 = Channel::new(&"#canal3".to_string());
-        channel3.topic = Some("my topic3".to_string());
-        let channels = Arc::new(Mutex::new(vec![channel, channel2, channel3]));
-        let msg_list = Message::from("LIST".to_string());
-        let result = list_msg(
-            msg_list,
-            "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-        )
-        .unwrap();
+        channel3.change_topic("my topic3".to_string(), "nick1");
+        let channels = Registry::from(vec![channel, channel2, channel3]);
+        let result = list_msg(vec![], "nick1".to_string(), &users, &channels).unwrap();
         assert_eq!(result.len(), 5);
         assert_eq!(result[0].command, "321");
         assert_eq!(result[1].command, "322");
-        assert_eq!(result[1].parameters, vec!["#canal1", "#"]);
-        assert_eq!(result[2].parameters, vec!["#canal2", "#", "my topic2"]);
-        assert_eq!(result[3].parameters, vec!["#canal3", "#", "my topic3"]);
+        assert_eq!(result[1].parameters, vec!["#canal1", "0", ""]);
+        assert_eq!(result[2].parameters, vec!["#canal2", "0", "my topic2"]);
+        assert_eq!(result[3].parameters, vec!["#canal3", "0", "my topic3"]);
         assert_eq!(result[4].command, "323");
     }
     #[test]
+    fn test_burst_de_otro_servidor_agrega_usuarios_y_combina_canales() {
+        let mut user = User::new(None);
+        user.nickname = "local".to_string();
+        let mut channel = Channel::new(&"&canal".to_string());
+        channel.created_at = 200;
+        channel.add_admin("local".to_string());
+        let users = Registry::from(vec![user]);
+        let channels = Registry::from(vec![channel]);
+        let mut server = server_with(users, channels);
+
+        for line in [
+            ":server2 NICK remoto 1 1700000000 rem host2 server2 +i :Usuario Remoto",
+            ":server2 SJOIN 100 &canal +n :@remoto",
+            ":server2 BMASK 100 &canal b :malo!*@*",
+            ":remoto TOPIC &canal 150 :topic remoto",
+            ":server2 EOB",
+        ] {
+            let msg = Message::from(line.to_string());
+            let result = process_message(msg, &mut server, &mut String::new()).unwrap();
+            assert!(result.is_empty());
+        }
+
+        let remote = server.users.get("remoto").unwrap();
+        assert_eq!(1, remote.hopcount);
+        assert_eq!("server2", remote.server);
+        assert!(remote.modes.i && remote.is_in_channel("&canal"));
+        let channel = &server.channels[0];
+        assert_eq!(100, channel.created_at);
+        assert!(channel.is_admin("remoto"));
+        assert!(channel.has_user("local") && !channel.is_admin("local"));
+        assert_eq!(vec!["malo!*@*".to_string()], channel.ban_masks);
+        assert_eq!("topic remoto", channel.get_topic());
+    }
+    #[test]
+    fn test_cliente_no_puede_enviar_mensajes_de_burst() {
+        let mut server = server_with(Registry::new(), Registry::new());
+        let msg = Message::from("SJOIN 100 &canal +n :@nick1".to_string());
+        let result = process_message(msg, &mut server, &mut "nick1".to_string()).unwrap();
+        assert_eq!(result[0].command, "462");
+        assert!(server.channels.is_empty());
+    }
+    #[test]
+    fn test_topic_de_cliente_con_varias_palabras_no_usa_el_formato_entre_servidores() {
+        let mut user = User::new(None);
+        user.nickname = "nick1".to_string();
+        let mut channel = Channel::new(&"#canal".to_string());
+        channel.add_admin("nick1".to_string());
+        let users = Registry::from(vec![user]);
+        let channels = Registry::from(vec![channel]);
+        let mut server = server_with(users, channels);
+
+        let mut topic_after = |line: &str| {
+            let msg = Message::from(line.to_string());
+            process_message(msg, &mut server, &mut "nick1".to_string()).unwrap();
+            server.channels[0].topic.clone().unwrap()
+        };
+
+        assert_eq!("hola", topic_after("TOPIC #canal hola mundo").text);
+        let topic = topic_after(":remoto TOPIC #canal 150 :falso");
+        assert_eq!(
+            ("150", "nick1"),
+            (topic.text.as_str(), topic.set_by.as_str())
+        );
+        assert_ne!(150, topic.set_at);
+    }
+    #[test]
     fn test_topic_con_parametros_insuficientes_devuelve_need_more_params() {
-        let channels = Arc::new(Mutex::new(Vec::new()));
+        let mut server = server_with(Registry::new(), Registry::new());
         let msg = Message::from("TOPIC".to_string());
-        let result = topic_msg(msg, "nick1".to_string(), channels.clone()).unwrap();
+        let result = process_message(msg, &mut server, &mut "nick1".to_string()).unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].command, "461");
     }
     #[test]
     fn test_topic_con_un_parametro_y_usuario_no_esta_en_canal_devuelve_not_on_channel() {
         let channel = Channel::new(&"#canal2".to_string());
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("TOPIC #canal2".to_string());
-        let result = topic_msg(msg, "nick1".to_string(), channels.clone()).unwrap();
+        let channels = Registry::from(vec![channel]);
+        let users = Registry::new();
+        let result = topic_msg(
+            "#canal2",
+            None,
+            None,
+            "nick1".to_string(),
+            &mut server_with(users, channels),
+            "",
+        )
+        .unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].command, "442");
     }
@@ -1730,21 +2248,28 @@ mod tests_channel_msgs {
     fn test_topic_con_un_parametro_usuario_en_canal_y_sin_topic_devuelve_no_topic() {
         let mut user = User::new(None);
         user.nickname = "nick1".to_string();
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(Vec::new()));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::new();
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = topic_msg(
+            "#canal1",
+            None,
+            None,
+            "nick1".to_string(),
+            &mut server_with(users, channels),
+            "",
         )
         .unwrap();
-        let msg_topic = Message::from("TOPIC #canal1".to_string());
-        let result = topic_msg(msg_topic, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].command, "331");
     }
@@ -1752,26 +2277,35 @@ mod tests_channel_msgs {
     fn test_topic_con_un_parametro_usuario_en_canal_y_topic_devuelve_topic() {
         let mut user = User::new(None);
         user.nickname = "nick1".to_string();
-        let users = Arc::new(Mutex::new(vec![user]));
+        let mut users = Registry::from(vec![user]);
         let mut channel = Channel::new(&"#canal1".to_string());
-        channel.topic = Some("my topic".to_string());
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        channel.change_topic("my topic".to_string(), "nick1");
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
-        let msg_topic = Message::from("TOPIC #canal1".to_string());
-        let result = topic_msg(msg_topic, "nick1".to_string(), channels.clone()).unwrap();
-        assert_eq!(result.len(), 1);
+        let result = topic_msg(
+            "#canal1",
+            None,
+            None,
+            "nick1".to_string(),
+            &mut server_with(users, channels),
+            "",
+        )
+        .unwrap();
+        assert_eq!(result.len(), 2);
         assert_eq!(result[0].command, "332");
         assert_eq!(result[0].parameters, ["#canal1", "my topic"]);
+        assert_eq!(result[1].command, "333");
+        assert_eq!(result[1].parameters[1], "nick1");
     }
     #[test]
     fn test_topic_con_modo_canal_y_usuario_admin_cambia_topic() {
@@ -1780,24 +2314,32 @@ mod tests_channel_msgs {
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.mode.activate_t();
         channel.add_admin("nick1".to_string());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
-        let msg_topic = Message::from("TOPIC #canal1 :cambio el topic".to_string());
-        let result = topic_msg(msg_topic, "nick1".to_string(), channels.clone()).unwrap();
-        assert_eq!(result.len(), 1);
+        let result = topic_msg(
+            "#canal1",
+            Some("cambio el topic".to_string()),
+            None,
+            "nick1".to_string(),
+            &mut server_with(users, channels),
+            "",
+        )
+        .unwrap();
+        assert_eq!(result.len(), 2);
         assert_eq!(result[0].command, "332");
         assert_eq!(result[0].parameters, ["#canal1", "cambio el topic"]);
+        assert_eq!(result[1].command, "333");
     }
     #[test]
     fn test_topic_con_modo_canal_y_usuario_no_admin_devuelve_chanopriv() {
@@ -1805,21 +2347,28 @@ mod tests_channel_msgs {
         user.nickname = "nick1".to_string();
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.mode.activate_t();
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = topic_msg(
+            "#canal1",
+            Some("cambio el topic".to_string()),
+            None,
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut server_with(users, channels),
+            "",
         )
         .unwrap();
-        let msg_topic = Message::from("TOPIC #canal1 :cambio el topic".to_string());
-        let result = topic_msg(msg_topic, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].command, "482");
     }
@@ -1829,24 +2378,102 @@ mod tests_channel_msgs {
         user.nickname = "nick1".to_string();
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
-        let msg_topic = Message::from("TOPIC #canal1 :cambio el topic".to_string());
-        let result = topic_msg(msg_topic, "nick1".to_string(), channels.clone()).unwrap();
-        assert_eq!(result.len(), 1);
+        let result = topic_msg(
+            "#canal1",
+            Some("cambio el topic".to_string()),
+            None,
+            "nick1".to_string(),
+            &mut server_with(users, channels),
+            "",
+        )
+        .unwrap();
+        assert_eq!(result.len(), 2);
         assert_eq!(result[0].command, "332");
         assert_eq!(result[0].parameters, ["#canal1", "cambio el topic"]);
+        assert_eq!(result[1].command, "333");
+    }
+    #[test]
+    fn test_topic_de_otro_servidor_gana_si_es_mas_reciente() {
+        let mut user = User::new(None);
+        user.nickname = "nick1".to_string();
+        let mut channel = Channel::new(&"#canal1".to_string());
+        channel.add_user("nick1".to_string());
+        channel.topic = Some(Topic {
+            text: "actual".to_string(),
+            set_by: "nick1".to_string(),
+            set_at: 200,
+        });
+        let users = Registry::from(vec![user]);
+        let channels = Registry::from(vec![channel]);
+        let mut server = server_with(users, channels);
+        let topic = |server: &mut Server, text: &str, set_at: u64| {
+            topic_msg(
+                "#canal1",
+                Some(text.to_string()),
+                Some(set_at),
+                "remoto".to_string(),
+                server,
+                "",
+            )
+            .unwrap()
+        };
+
+        assert!(topic(&mut server, "viejo", 100).is_empty());
+        assert!(topic(&mut server, "abajo", 200).is_empty());
+        assert_eq!("actual", server.channels[0].get_topic());
+        assert!(topic(&mut server, "nuevo", 300).is_empty());
+        let channel = &server.channels[0];
+        assert_eq!(
+            Some(Topic {
+                text: "nuevo".to_string(),
+                set_by: "remoto".to_string(),
+                set_at: 300,
+            }),
+            channel.topic
+        );
+    }
+    #[test]
+    fn test_topicos_del_mismo_momento_se_resuelven_igual_en_ambos_servidores() {
+        let topic = |text: &str, set_by: &str| Topic {
+            text: text.to_string(),
+            set_by: set_by.to_string(),
+            set_at: 200,
+        };
+        for (local, remote, winner) in [
+            (
+                topic("hola", "nick1"),
+                topic("chau", "nick2"),
+                topic("hola", "nick1"),
+            ),
+            (
+                topic("hola", "nick1"),
+                topic("hola", "nick2"),
+                topic("hola", "nick2"),
+            ),
+        ] {
+            let mut channel = Channel::new(&"#canal1".to_string());
+            channel.topic = Some(local.clone());
+            let mut other = Channel::new(&"#canal1".to_string());
+            other.topic = Some(remote.clone());
+
+            channel.merge_topic(remote);
+            other.merge_topic(local);
+            assert_eq!(Some(winner.clone()), channel.topic);
+            assert_eq!(Some(winner), other.topic);
+        }
     }
     #[test]
     fn test_mode_o_sin_activate_flag_devuelve_unknown_mode() {
@@ -1856,21 +2483,29 @@ mod tests_channel_msgs {
         user2.nickname = "nick2".to_string();
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
-        let users = Arc::new(Mutex::new(vec![user, user2]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user, user2]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["o".to_string(), "nick2".to_string()],
+            "nick1".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 o nick2".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].command, "472");
     }
@@ -1881,21 +2516,29 @@ mod tests_channel_msgs {
         let mut user2 = User::new(None);
         user2.nickname = "nick2".to_string();
         let channel = Channel::new(&"#canal1".to_string());
-        let users = Arc::new(Mutex::new(vec![user, user2]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user, user2]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+o".to_string(), "nick2".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +o nick2".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].command, "482");
     }
@@ -1908,40 +2551,48 @@ mod tests_channel_msgs {
         user2.nickname = "nick2".to_string();
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
-        let users = Arc::new(Mutex::new(vec![user, user2]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let msg2 = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
-        let con_servers2: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user, user2]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
+        let con_servers2: Vec<Server> = vec![];
 
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
         let _ = join_msg(
-            msg2,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick2".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers2,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers2,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+o".to_string(), "nick2".to_string()],
+            "nick1".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +o nick2".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
         {
-            assert!(channel.is_admin(&"nick2".to_string()));
+            assert!(channel.is_admin("nick2"));
         }
     }
     #[test]
@@ -1953,40 +2604,48 @@ mod tests_channel_msgs {
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
         channel.add_admin("nick2".to_string());
-        assert!(channel.is_admin(&"nick2".to_string()));
-        let users = Arc::new(Mutex::new(vec![user, user2]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let msg2 = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
-        let con_servers2: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        assert!(channel.is_admin("nick2"));
+        let mut users = Registry::from(vec![user, user2]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
+        let con_servers2: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
         let _ = join_msg(
-            msg2,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick2".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers2,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers2,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["-o".to_string(), "nick2".to_string()],
+            "nick1".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 -o nick2".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
         {
-            assert!(!channel.is_admin(&"nick2".to_string()));
+            assert!(!channel.is_admin("nick2"));
         }
     }
     #[test]
@@ -1997,21 +2656,29 @@ mod tests_channel_msgs {
         user2.nickname = "nick2".to_string();
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
-        let users = Arc::new(Mutex::new(vec![user, user2]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user, user2]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+o".to_string(), "nick2".to_string()],
+            "nick1".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +o nick2".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].command, "401");
     }
@@ -2022,23 +2689,31 @@ mod tests_channel_msgs {
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
         assert!(!channel.is_private());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+p".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +p".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2054,23 +2729,31 @@ mod tests_channel_msgs {
         channel.add_admin("nick1".to_string());
         channel.mode.activate_p();
         assert!(channel.is_private());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["-p".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 -p".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2085,23 +2768,31 @@ mod tests_channel_msgs {
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
         assert!(!channel.is_secret());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+s".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +s".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2117,23 +2808,31 @@ mod tests_channel_msgs {
         channel.add_admin("nick1".to_string());
         channel.mode.activate_s();
         assert!(channel.is_secret());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["-s".to_string()],
+            "nick1".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 -s".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2148,23 +2847,31 @@ mod tests_channel_msgs {
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
         assert!(!channel.is_topic_operator_only());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+t".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +t".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2180,23 +2887,31 @@ mod tests_channel_msgs {
         channel.add_admin("nick1".to_string());
         channel.mode.activate_t();
         assert!(channel.is_topic_operator_only());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["-t".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 -t".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2211,23 +2926,31 @@ mod tests_channel_msgs {
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
         assert!(!channel.is_invite_only());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+i".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +i".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2243,23 +2966,31 @@ mod tests_channel_msgs {
         channel.add_admin("nick1".to_string());
         channel.mode.activate_i();
         assert!(channel.is_invite_only());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["-i".to_string()],
+            "nick1".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 -i".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2274,23 +3005,31 @@ mod tests_channel_msgs {
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
         assert!(!channel.is_no_msg_outside());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+n".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +n".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2306,23 +3045,31 @@ mod tests_channel_msgs {
         channel.add_admin("nick1".to_string());
         channel.mode.activate_n();
         assert!(channel.is_no_msg_outside());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["-n".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 -n".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2337,23 +3084,31 @@ mod tests_channel_msgs {
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
         assert!(!channel.is_moderated());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+m".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +m".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2369,23 +3124,31 @@ mod tests_channel_msgs {
         channel.add_admin("nick1".to_string());
         channel.mode.activate_m();
         assert!(channel.is_moderated());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["-m".to_string()],
+            "nick1".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 -m".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2401,41 +3164,63 @@ mod tests_channel_msgs {
         user2.nickname = "nick2".to_string();
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
-        assert_eq!(channel.can_speak_users.len(), 0);
-        let users = Arc::new(Mutex::new(vec![user, user2]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let msg2 = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
-        let con_servers2: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        assert_eq!(
+            channel
+                .members
+                .iter()
+                .filter(|member| member.status.voice)
+                .count(),
+            0
+        );
+        let mut users = Registry::from(vec![user, user2]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
+        let con_servers2: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
         let _ = join_msg(
-            msg2,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick2".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers2,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers2,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+v".to_string(), "nick2".to_string()],
+            "nick1".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +v nick2".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
         {
-            assert_eq!(channel.can_speak_users.len(), 1);
-            assert!(channel.can_speak(&"nick2".to_string()))
+            assert_eq!(
+                channel
+                    .members
+                    .iter()
+                    .filter(|member| member.status.voice)
+                    .count(),
+                1
+            );
+            assert!(channel.can_speak("nick2"))
         }
     }
     #[test]
@@ -2447,42 +3232,64 @@ mod tests_channel_msgs {
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
         channel.add_speaker("nick2".to_string());
-        assert_eq!(channel.can_speak_users.len(), 1);
-        assert!(channel.can_speak(&"nick2".to_string()));
-        let users = Arc::new(Mutex::new(vec![user, user2]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let msg2 = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
-        let con_servers2: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        assert_eq!(
+            channel
+                .members
+                .iter()
+                .filter(|member| member.status.voice)
+                .count(),
+            1
+        );
+        assert!(channel.can_speak("nick2"));
+        let mut users = Registry::from(vec![user, user2]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
+        let con_servers2: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
         let _ = join_msg(
-            msg2,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick2".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers2,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers2,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["-v".to_string(), "nick2".to_string()],
+            "nick1".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 -v nick2".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
         {
-            assert_eq!(channel.can_speak_users.len(), 0);
-            assert!(!channel.can_speak(&"nick2".to_string()))
+            assert_eq!(
+                channel
+                    .members
+                    .iter()
+                    .filter(|member| member.status.voice)
+                    .count(),
+                0
+            );
+            assert!(!channel.can_speak("nick2"))
         }
     }
     #[test]
@@ -2492,23 +3299,31 @@ mod tests_channel_msgs {
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
         assert!(!channel.has_limit());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+l".to_string(), "10".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +l 10".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2528,23 +3343,31 @@ mod tests_channel_msgs {
         channel.mode.activate_l();
         assert!(channel.has_limit());
         assert_eq!(channel.limit, Some(5));
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["-l".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 -l".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2562,34 +3385,42 @@ mod tests_channel_msgs {
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
         assert!(!channel.has_limit());
-        let users = Arc::new(Mutex::new(vec![user, user2]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let msg2 = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
-        let con_servers2: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user, user2]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
+        let con_servers2: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
         let _ = join_msg(
-            msg2,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick2".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers2,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers2,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+l".to_string(), "1".to_string()],
+            "nick1".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +l 1".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2608,23 +3439,31 @@ mod tests_channel_msgs {
         channel.mode.activate_l();
         assert!(channel.has_limit());
         assert_eq!(channel.limit, Some(5));
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+l".to_string(), "10".to_string()],
+            "nick1".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +l 10".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2640,23 +3479,31 @@ mod tests_channel_msgs {
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
         assert!(!channel.has_key());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+k".to_string(), "password".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +k password".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2675,24 +3522,32 @@ mod tests_channel_msgs {
         channel.password = Some("password".to_string());
         channel.mode.activate_k();
         assert!(channel.has_key());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1 password".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec!["password".to_string()],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+k".to_string(), "passwordchange".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +k passwordchange".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].command, "467");
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2711,23 +3566,31 @@ mod tests_channel_msgs {
         channel.password = Some("password".to_string());
         channel.mode.activate_k();
         assert!(channel.has_key());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1 password".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec!["password".to_string()],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["-k".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 -k".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2743,23 +3606,31 @@ mod tests_channel_msgs {
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
         assert!(channel.ban_masks.is_empty());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+b".to_string(), "*!*@*".to_string()],
+            "nick1".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +b *!*@*".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2775,21 +3646,29 @@ mod tests_channel_msgs {
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
         channel.ban_masks.push("*!*@*".to_string());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+b".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +b".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 2);
         assert_eq!(result[0].command, "367");
         assert_eq!(result[0].parameters, ["#canal1", "*!*@*"]);
@@ -2803,23 +3682,31 @@ mod tests_channel_msgs {
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin("nick1".to_string());
         channel.ban_masks.push("*!*@*".to_string());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["-b".to_string(), "*!*@*".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 -b *!*@*".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2836,23 +3723,31 @@ mod tests_channel_msgs {
         assert!(!channel.is_invite_only());
         assert!(!channel.is_moderated());
         assert!(!channel.is_secret());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let _ = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
+            "nick1".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
+        )
+        .unwrap();
+        let result = mode_msg(
+            "#canal1",
+            vec!["+ims".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &users,
+            &mut channels,
+            &[],
+            "",
         )
         .unwrap();
-        let msg_mode = Message::from("MODE #canal1 +ims".to_string());
-        let result = mode_msg(msg_mode, "nick1".to_string(), channels.clone()).unwrap();
         assert_eq!(result.len(), 0);
-        let mut lock_channel = channels.lock().unwrap();
+        let mut lock_channel = channels;
         if let Some(channel) = lock_channel
             .iter_mut()
             .find(|channel| channel.name == "#canal1")
@@ -2871,19 +3766,19 @@ mod tests_channel_msgs {
         channel.limit = Some(2);
         channel.add_user("user1".to_string());
         channel.add_user("user2".to_string());
-        assert_eq!(channel.users.len(), 2);
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        assert_eq!(channel.members.len(), 2);
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
 
         let result = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
         assert_eq!(result.len(), 1);
@@ -2895,40 +3790,186 @@ mod tests_channel_msgs {
         user.nickname = "nick1".to_string();
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.mode.activate_i();
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let result = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].command, "473");
     }
     #[test]
+    fn test_join_con_excepciones_de_ban_y_de_invitacion() {
+        let mut user = User::new(None);
+        user.nickname = "regular".to_string();
+        user.username = "juan".to_string();
+        user.hostname = "pc1.isp.com".to_string();
+        let mut channel = Channel::new(&"#canal1".to_string());
+        channel.mode.activate_i();
+        channel.ban_masks.push("*!*@*.isp.com".to_string());
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let join = |users: &mut Registry<User>, channels: &mut Registry<Channel>| {
+            join_msg(
+                vec!["#canal1".to_string()],
+                vec![],
+                "regular".to_string(),
+                users,
+                channels,
+                &[],
+                "",
+            )
+            .unwrap()
+        };
+
+        assert_eq!("473", join(&mut users, &mut channels)[0].command);
+        channels
+            .get_mut("#canal1")
+            .unwrap()
+            .invite_masks
+            .push("regular!*@*".to_string());
+        assert_eq!("474", join(&mut users, &mut channels)[0].command);
+        channels
+            .get_mut("#canal1")
+            .unwrap()
+            .exception_masks
+            .push("*!juan@*".to_string());
+        assert_eq!("331", join(&mut users, &mut channels)[0].command);
+    }
+    #[test]
+    fn test_mode_e_e_i_agregan_listan_y_quitan_mascaras() {
+        let mut channel = Channel::new(&"#canal1".to_string());
+        channel.add_admin("nick1".to_string());
+        let mut channels = Registry::from(vec![channel]);
+        let mut mode = |changes: &[&str]| {
+            let changes = changes.iter().map(|change| change.to_string()).collect();
+            mode_msg(
+                "#canal1",
+                changes,
+                "nick1".to_string(),
+                &Registry::new(),
+                &mut channels,
+                &[],
+                "",
+            )
+            .unwrap()
+        };
+
+        assert!(mode(&["+e", "juan@*.isp.com"]).is_empty());
+        assert!(mode(&["+I", "regular"]).is_empty());
+        let result = mode(&["+e"]);
+        assert_eq!(result[0].command, "348");
+        assert_eq!(result[0].parameters, ["#canal1", "*!juan@*.isp.com"]);
+        assert_eq!(result[1].command, "349");
+        let result = mode(&["+I"]);
+        assert_eq!(result[0].command, "346");
+        assert_eq!(result[0].parameters, ["#canal1", "regular!*@*"]);
+        assert_eq!(result[1].command, "347");
+
+        mode(&["-e", "JUAN@*.isp.com"]);
+        mode(&["-I", "regular!*@*"]);
+        let channel = &channels[0];
+        assert!(channel.exception_masks.is_empty());
+        assert!(channel.invite_masks.is_empty());
+    }
+    #[test]
+    fn test_mode_aplica_varios_modos_con_sus_argumentos() {
+        let mut channel = Channel::new(&"#canal1".to_string());
+        channel.add_admin("nick1".to_string());
+        channel.add_user("nick1".to_string());
+        channel.add_user("nick2".to_string());
+        channel.mode.activate_i();
+        let mut channels = Registry::from(vec![channel]);
+        let mut mode = |changes: &[&str]| {
+            let changes = changes.iter().map(|change| change.to_string()).collect();
+            mode_msg(
+                "#canal1",
+                changes,
+                "nick1".to_string(),
+                &Registry::new(),
+                &mut channels,
+                &[],
+                "",
+            )
+            .unwrap()
+        };
+
+        assert!(mode(&["+ntlk-i", "10", "secreta"]).is_empty());
+        assert!(mode(&["+vv", "nick1", "nick2"]).is_empty());
+        let result = mode(&["+o-q", "nick3"]);
+        assert_eq!(result[0].command, "401");
+        assert_eq!(result[1].command, "472");
+
+        let result = mode(&[]);
+        assert_eq!(result[0].command, "324");
+        assert_eq!(result[0].parameters, ["#canal1", "+tnlk", "10", "secreta"]);
+        assert_eq!(result[1].command, "329");
+        let channel = &channels[0];
+        assert_eq!(Some(10), channel.limit);
+        assert!(!channel.is_invite_only());
+        assert!(channel.can_speak("nick1") && channel.can_speak("nick2"));
+    }
+    #[test]
+    fn test_mode_de_usuario_sin_privilegios_solo_consulta_listas() {
+        let mut channel = Channel::new(&"#canal1".to_string());
+        channel.add_user("nick1".to_string());
+        channel.ban_masks.push("malo!*@*".to_string());
+        channel.password = Some("secreta".to_string());
+        channel.mode.activate_k();
+        let mut channels = Registry::from(vec![channel]);
+        let mut mode = |nickname: &str, changes: &[&str]| {
+            let changes = changes.iter().map(|change| change.to_string()).collect();
+            mode_msg(
+                "#canal1",
+                changes,
+                nickname.to_string(),
+                &Registry::new(),
+                &mut channels,
+                &[],
+                "",
+            )
+            .unwrap()
+        };
+
+        let result = mode("nick1", &["+b"]);
+        assert_eq!(result[0].command, "367");
+        assert_eq!(result[1].command, "368");
+        let result = mode("nick1", &["+bm", "otro"]);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].command, "482");
+        assert_eq!(
+            mode("nick1", &[])[0].parameters,
+            ["#canal1", "+k", "secreta"]
+        );
+        assert_eq!(mode("afuera", &[])[0].parameters, ["#canal1", "+k"]);
+        assert_eq!(1, channels[0].ban_masks.len());
+    }
+    #[test]
     fn test_join_a_canal_con_clave_sin_poner_clave_devuelve_error() {
         let mut user = User::new(None);
         user.nickname = "nick1".to_string();
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.mode.activate_k();
         channel.password = Some("password".to_string());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let result = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec![],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
         assert_eq!(result.len(), 1);
@@ -2942,17 +3983,17 @@ mod tests_channel_msgs {
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.mode.activate_k();
         channel.password = Some("password".to_string());
-        let users = Arc::new(Mutex::new(vec![user]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg = Message::from("JOIN #canal1 invalidpassword".to_string());
-        let con_servers: Arc<Mutex<Vec<Server>>> = Arc::new(Mutex::new(vec![]));
+        let mut users = Registry::from(vec![user]);
+        let mut channels = Registry::from(vec![channel]);
+        let con_servers: Vec<Server> = vec![];
         let result = join_msg(
-            msg,
+            vec!["#canal1".to_string()],
+            vec!["invalidpassword".to_string()],
             "nick1".to_string(),
-            users.clone(),
-            channels.clone(),
-            con_servers,
-            "".to_string(),
+            &mut users,
+            &mut channels,
+            &con_servers,
+            "",
         )
         .unwrap();
         assert_eq!(result.len(), 1);
@@ -2968,15 +4009,15 @@ mod tests_channel_msgs {
         channel.mode.activate_l();
         channel.limit = Some(1);
         channel.add_user("nick1".to_string());
-        user.add_channel(&"#canal1".to_string());
-        let users = Arc::new(Mutex::new(vec![user, user2]));
-        let channels = Arc::new(Mutex::new(vec![channel]));
-        let msg_invite = Message::from("INVITE nick2 #canal1".to_string());
+        user.add_channel("#canal1");
+        let mut users = Registry::from(vec![user, user2]);
+        let mut channels = Registry::from(vec![channel]);
         let result = invite_msg(
-            msg_invite,
-            users.clone(),
+            "nick2",
+            "#canal1",
+            &mut users,
             "nick1".to_string(),
-            channels.clone(),
+            &mut channels,
         )
         .unwrap();
         assert_eq!(result.len(), 1);
@@ -2990,7 +4031,7 @@ mod tests_channel_msgs {
         channel.ban_masks.push(ban_mask);
         let username = "hola".to_string();
         let hostname = "chau".to_string();
-        assert!(channel.is_banned(&username, &hostname));
+        assert!(channel.is_banned(&format!("nick!{}@{}", username, hostname)));
     }
     #[test]
     fn test_is_banned_no_cumple_con_mask_con_asteriscos_devuelve_false() {
@@ -2999,7 +4040,7 @@ mod tests_channel_msgs {
         channel.ban_masks.push(ban_mask);
         let username = "aaahola".to_string();
         let hostname = "chau".to_string();
-        assert!(!channel.is_banned(&username, &hostname));
+        assert!(!channel.is_banned(&format!("nick!{}@{}", username, hostname)));
     }
     #[test]
     fn test_is_banned_cumple_con_mask_sin_texto_username_y_asterisco_hostname_devuelve_true() {
@@ -3008,7 +4049,7 @@ mod tests_channel_msgs {
         channel.ban_masks.push(ban_mask);
         let username = "hola".to_string();
         let hostname = "chau".to_string();
-        assert!(channel.is_banned(&username, &hostname));
+        assert!(channel.is_banned(&format!("nick!{}@{}", username, hostname)));
     }
     #[test]
     fn test_is_banned_cumple_con_mask_con_asterisco_username_y_asterisco_hostname_devuelve_true() {
@@ -3017,7 +4058,7 @@ mod tests_channel_msgs {
         channel.ban_masks.push(ban_mask);
         let username = "hola".to_string();
         let hostname = "chau".to_string();
-        assert!(channel.is_banned(&username, &hostname));
+        assert!(channel.is_banned(&format!("nick!{}@{}", username, hostname)));
     }
     #[test]
     fn test_is_banned_con_username_correcto_hostname_incorrecto_devuelve_false() {
@@ -3026,7 +4067,7 @@ mod tests_channel_msgs {
         channel.ban_masks.push(ban_mask);
         let username = "hola".to_string();
         let hostname = "chau".to_string();
-        assert!(!channel.is_banned(&username, &hostname));
+        assert!(!channel.is_banned(&format!("nick!{}@{}", username, hostname)));
     }
     #[test]
     fn test_is_banned_con_username_incorrecto_hostname_correcto_devuelve_false() {
@@ -3035,7 +4076,7 @@ mod tests_channel_msgs {
         channel.ban_masks.push(ban_mask);
         let username = "hola".to_string();
         let hostname = "chau".to_string();
-        assert!(!channel.is_banned(&username, &hostname));
+        assert!(!channel.is_banned(&format!("nick!{}@{}", username, hostname)));
     }
     #[test]
     fn test_is_banned_cumple_con_mask_sin_texto_username_y_asterisco_y_sufijo_hostname_devuelve_true(
@@ -3045,7 +4086,7 @@ mod tests_channel_msgs {
         channel.ban_masks.push(ban_mask);
         let username = "hola".to_string();
         let hostname = "chau".to_string();
-        assert!(channel.is_banned(&username, &hostname));
+        assert!(channel.is_banned(&format!("nick!{}@{}", username, hostname)));
     }
     #[test]
     fn test_is_banned_no_cumple_ninguna_mask_devuelve_false() {
@@ -3058,7 +4099,17 @@ mod tests_channel_msgs {
         channel.ban_masks.push(ban_mask3);
         let username = "hola".to_string();
         let hostname = "chau".to_string();
-        assert!(!channel.is_banned(&username, &hostname));
+        assert!(!channel.is_banned(&format!("nick!{}@{}", username, hostname)));
+    }
+    #[test]
+    fn test_is_banned_compara_la_mascara_completa_con_casemapping() {
+        let mut channel = Channel::new(&"canal1".to_string());
+        channel.ban_masks.push("bad?nick!*@*".to_string());
+        channel.ban_masks.push("*!*@*.example.org".to_string());
+
+        assert!(channel.is_banned("BADxNICK!juan@pc1.fi.uba.ar"));
+        assert!(channel.is_banned("juan!juan@pc1.Example.org"));
+        assert!(!channel.is_banned("goodnick!juan@pc1.fi.uba.ar"));
     }
     #[test]
     fn test_is_banned_cumple_alguna_mask_devuelve_true() {
@@ -3071,6 +4122,6 @@ mod tests_channel_msgs {
         channel.ban_masks.push(ban_mask3);
         let username = "hola".to_string();
         let hostname = "chau".to_string();
-        assert!(channel.is_banned(&username, &hostname));
+        assert!(channel.is_banned(&format!("nick!{}@{}", username, hostname)));
     }
 }
@@ -1,7 +1,6 @@
 #[derive(Debug, Clone)]
 ///Almacena la información sobre qué modos están activados y cuáles desactivados
 /// para un determinado canal.
-
 pub struct ChannelModes {
     pub p: bool,
     pub s: bool,
@@ -80,3 +79,156 @@ impl ChannelModes {
         self.k = false
     }
 }
+
+impl ChannelModes {
+    ///Activa o desactiva uno de los modos sin argumentos (`p`, `s`, `i`, `t`, `n` o `m`).
+    /// Devuelve si el modo cambió de estado.
+    pub fn set(&mut self, mode: char, value: bool) -> bool {
+        let flag = match mode {
+            'p' => &mut self.p,
+            's' => &mut self.s,
+            'i' => &mut self.i,
+            't' => &mut self.t,
+            'n' => &mut self.n,
+            'm' => &mut self.m,
+            _ => return false,
+        };
+        let changed = *flag != value;
+        *flag = value;
+        changed
+    }
+
+    ///Obtiene las letras de los modos activados, en el orden `psitnmlk`.
+    pub fn letters(&self) -> String {
+        [
+            (self.p, 'p'),
+            (self.s, 's'),
+            (self.i, 'i'),
+            (self.t, 't'),
+            (self.n, 'n'),
+            (self.m, 'm'),
+            (self.l, 'l'),
+            (self.k, 'k'),
+        ]
+        .iter()
+        .filter(|(active, _)| *active)
+        .map(|(_, letter)| *letter)
+        .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///Cambio de modo de un canal ya interpretado: si el modo se activa o se desactiva, su letra
+/// y el argumento que le corresponde, si lo necesita.
+pub struct ModeChange {
+    pub adding: bool,
+    pub mode: char,
+    pub argument: Option<String>,
+}
+
+///Evalúa si un modo consume un argumento. Las listas de máscaras (`b`, `e`, `I`) lo toman si
+/// queda alguno; sin argumento se consultan.
+fn takes_argument(mode: char, adding: bool) -> bool {
+    matches!(mode, 'o' | 'v' | 'b' | 'e' | 'I' | 'k') || (mode == 'l' && adding)
+}
+
+///Interpreta los parámetros de MODE, como `+ntlk-i 10 secreta` o `+oo nick1 nick2`. El
+/// primer parámetro tiene las letras de los modos y cada modo que lo necesita consume el
+/// siguiente argumento. Si el texto no empieza con signo, los modos se activan.
+pub fn parse_mode_changes(parameters: &[String]) -> Vec<ModeChange> {
+    let mut changes = Vec::new();
+    let Some((letters, arguments)) = parameters.split_first() else {
+        return changes;
+    };
+    let mut arguments = arguments.iter();
+    let mut adding = true;
+    for mode in letters.chars() {
+        match mode {
+            '+' => adding = true,
+            '-' => adding = false,
+            _ => {
+                let argument = if takes_argument(mode, adding) {
+                    arguments.next().cloned()
+                } else {
+                    None
+                };
+                changes.push(ModeChange {
+                    adding,
+                    mode,
+                    argument,
+                });
+            }
+        }
+    }
+    changes
+}
+
+///Arma los parámetros de MODE que describen los cambios pasados, agrupando los modos con el
+/// mismo signo y dejando los argumentos al final: `+nl-i 10`.
+pub fn mode_changes_parameters(changes: &[ModeChange]) -> Vec<String> {
+    let mut letters = String::new();
+    let mut arguments = Vec::new();
+    let mut sign = None;
+    for change in changes {
+        if sign != Some(change.adding) {
+            letters.push(if change.adding { '+' } else { '-' });
+            sign = Some(change.adding);
+        }
+        letters.push(change.mode);
+        arguments.extend(change.argument.clone());
+    }
+    let mut parameters = vec![letters];
+    parameters.extend(arguments);
+    parameters
+}
+
+#[cfg(test)]
+mod tests_channel_modes {
+    use crate::channel_modes::{mode_changes_parameters, parse_mode_changes, ModeChange};
+
+    fn parameters(line: &str) -> Vec<String> {
+        line.split(' ')
+            .map(|parameter| parameter.to_string())
+            .collect()
+    }
+
+    fn change(adding: bool, mode: char, argument: Option<&str>) -> ModeChange {
+        ModeChange {
+            adding,
+            mode,
+            argument: argument.map(|argument| argument.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_cada_modo_consume_su_argumento() {
+        assert_eq!(
+            vec![
+                change(true, 'n', None),
+                change(true, 't', None),
+                change(true, 'l', Some("10")),
+                change(true, 'k', Some("secreta")),
+                change(false, 'i', None),
+            ],
+            parse_mode_changes(&parameters("+ntlk-i 10 secreta"))
+        );
+        assert_eq!(
+            vec![change(true, 'o', Some("a")), change(true, 'o', Some("b"))],
+            parse_mode_changes(&parameters("+oo a b"))
+        );
+        assert_eq!(
+            vec![change(false, 'l', None), change(true, 'b', None)],
+            parse_mode_changes(&parameters("-l+b"))
+        );
+    }
+
+    #[test]
+    fn test_cambios_se_agrupan_por_signo() {
+        let changes = parse_mode_changes(&parameters("+n+l-i-k 10 secreta"));
+
+        assert_eq!(
+            parameters("+nl-ik 10 secreta"),
+            mode_changes_parameters(&changes)
+        );
+    }
+}
@@ -0,0 +1,762 @@
+use crate::message::Message;
+use crate::replies::{
+    err_need_more_params, err_no_nickname_given, err_no_origin, err_no_recpient, err_no_text_tosend,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///Comando del protocolo ya interpretado, con sus parámetros validados. Se obtiene a partir de
+/// un Message con `Command::try_from` y puede volver a convertirse en Message para enviarlo.
+pub enum Command {
+    Pass {
+        password: String,
+    },
+    Nick {
+        nickname: String,
+    },
+    User {
+        username: String,
+        hostname: String,
+        servername: String,
+        realname: String,
+    },
+    Quit {
+        message: Option<String>,
+    },
+    Privmsg {
+        targets: Vec<String>,
+        text: String,
+    },
+    Notice {
+        targets: Vec<String>,
+        text: String,
+    },
+    Join {
+        channels: Vec<String>,
+        keys: Vec<String>,
+    },
+    Part {
+        channels: Vec<String>,
+        message: Option<String>,
+    },
+    Oper {
+        name: String,
+        password: String,
+    },
+    Kick {
+        channel: String,
+        nickname: String,
+        comment: Option<String>,
+    },
+    OperCh {
+        channel: String,
+        username: String,
+        password: String,
+    },
+    Invite {
+        nickname: String,
+        channel: String,
+    },
+    Names {
+        channels: Vec<String>,
+    },
+    List {
+        channels: Vec<String>,
+    },
+    Whois {
+        nicknames: Vec<String>,
+    },
+    Who {
+        mask: Option<String>,
+        operators_only: bool,
+    },
+    Topic {
+        channel: String,
+        topic: Option<String>,
+        // momento en que se cambió el tópico, informado solo entre servidores
+        set_at: Option<u64>,
+    },
+    Away {
+        message: Option<String>,
+    },
+    Motd {
+        target: Option<String>,
+    },
+    Ping {
+        origin: String,
+        target: Option<String>,
+    },
+    Pong {
+        origin: String,
+        target: Option<String>,
+    },
+    Mode {
+        target: String,
+        changes: Vec<String>,
+    },
+    ///Mensajes de actualización entre servidores. Su contenido lo interpreta
+    /// server_messages_interpreter.
+    Server {
+        parameters: Vec<String>,
+    },
+    Squit {
+        server: String,
+        comment: Option<String>,
+    },
+    ServerConnect {
+        host: String,
+        port: String,
+    },
+    ///Alta de un usuario de otro servidor, con la forma `NICK` de RFC 2813 más el momento en
+    /// que se conectó: `NICK nick hopcount signon user host server +modos :realname`.
+    RemoteUser {
+        nickname: String,
+        hopcount: usize,
+        signed_on: u64,
+        username: String,
+        hostname: String,
+        servername: String,
+        modes: String,
+        realname: String,
+    },
+    ///Estado de un canal informado entre servidores: su momento de creación, sus modos con
+    /// sus argumentos y sus miembros precedidos por su estado (`@nick`, `+nick`).
+    Sjoin {
+        created_at: u64,
+        channel: String,
+        modes: Vec<String>,
+        members: Vec<String>,
+    },
+    ///Máscaras de una de las listas de un canal (`b`, `e` o `I`) informadas entre servidores.
+    Bmask {
+        created_at: u64,
+        channel: String,
+        mode: char,
+        masks: Vec<String>,
+    },
+    ///Fin del estado de la red que un servidor le envía a otro al vincularse.
+    Eob,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///Motivo por el cual un Message no pudo convertirse en Command.
+pub enum CommandError {
+    NeedMoreParams(String),
+    NoNicknameGiven,
+    NoRecipient(String),
+    NoTextToSend,
+    NoOrigin,
+    UnknownCommand(String),
+}
+
+impl CommandError {
+    ///Respuesta numérica que corresponde enviarle al cliente, si la hay.
+    pub fn reply(&self) -> Option<Message> {
+        match self {
+            CommandError::NeedMoreParams(command) => Some(err_need_more_params(command.clone())),
+            CommandError::NoNicknameGiven => Some(err_no_nickname_given()),
+            CommandError::NoRecipient(command) => Some(err_no_recpient(command.clone())),
+            CommandError::NoTextToSend => Some(err_no_text_tosend()),
+            CommandError::NoOrigin => Some(err_no_origin()),
+            CommandError::UnknownCommand(_) => None,
+        }
+    }
+}
+
+impl TryFrom<Message> for Command {
+    type Error = CommandError;
+
+    ///Interpreta el comando del mensaje, verificando la cantidad de parámetros.
+    fn try_from(message: Message) -> Result<Self, Self::Error> {
+        let command_name = message.command.clone();
+        let from_server = message.prefix.is_some();
+        let mut params = message.parameters.into_iter();
+        let need_more_params = || CommandError::NeedMoreParams(command_name.clone());
+
+        let command =
+            match command_name.as_str() {
+                "PASS" => Command::Pass {
+                    password: params.next().ok_or_else(need_more_params)?,
+                },
+                "NICK" => {
+                    let nickname = params.next().ok_or(CommandError::NoNicknameGiven)?;
+                    // los servidores presentan a sus usuarios con un NICK de siete parámetros más,
+                    // que en el NICK de un cliente se ignoran
+                    let fields: Vec<String> = params.by_ref().take(7).collect();
+                    match <[String; 7]>::try_from(fields) {
+                        Ok(
+                            [hopcount, signed_on, username, hostname, servername, modes, realname],
+                        ) if from_server => Command::RemoteUser {
+                            nickname,
+                            hopcount: hopcount.parse().map_err(|_| need_more_params())?,
+                            signed_on: signed_on.parse().map_err(|_| need_more_params())?,
+                            username,
+                            hostname,
+                            servername,
+                            modes,
+                            realname,
+                        },
+                        _ => Command::Nick { nickname },
+                    }
+                }
+                "USER" => {
+                    let fields: Vec<String> = params.by_ref().take(4).collect();
+                    let [username, hostname, servername, realname]: [String; 4] =
+                        fields.try_into().map_err(|_| need_more_params())?;
+                    Command::User {
+                        username,
+                        hostname,
+                        servername,
+                        realname,
+                    }
+                }
+                "QUIT" => Command::Quit {
+                    message: params.next(),
+                },
+                "PRIVMSG" | "NOTICE" => {
+                    let targets = params
+                        .next()
+                        .filter(|targets| !targets.is_empty())
+                        .ok_or_else(|| CommandError::NoRecipient(command_name.clone()))?;
+                    let text = params
+                        .next()
+                        .filter(|text| !text.is_empty())
+                        .ok_or(CommandError::NoTextToSend)?;
+                    let targets = split_list(&targets);
+                    if command_name == "PRIVMSG" {
+                        Command::Privmsg { targets, text }
+                    } else {
+                        Command::Notice { targets, text }
+                    }
+                }
+                "JOIN" => Command::Join {
+                    channels: split_list(&params.next().ok_or_else(need_more_params)?),
+                    keys: params
+                        .next()
+                        .map(|keys| split_list(&keys))
+                        .unwrap_or_default(),
+                },
+                "PART" => Command::Part {
+                    channels: split_list(&params.next().ok_or_else(need_more_params)?),
+                    message: params.next(),
+                },
+                "OPER" => Command::Oper {
+                    name: params.next().ok_or_else(need_more_params)?,
+                    password: params.next().ok_or_else(need_more_params)?,
+                },
+                "KICK" => Command::Kick {
+                    channel: params.next().ok_or_else(need_more_params)?,
+                    nickname: params.next().ok_or_else(need_more_params)?,
+                    comment: params.next(),
+                },
+                "OPERCH" => Command::OperCh {
+                    channel: params.next().ok_or_else(need_more_params)?,
+                    username: params.next().ok_or_else(need_more_params)?,
+                    password: params.next().ok_or_else(need_more_params)?,
+                },
+                "INVITE" => Command::Invite {
+                    nickname: params.next().ok_or_else(need_more_params)?,
+                    channel: params.next().ok_or_else(need_more_params)?,
+                },
+                "NAMES" => Command::Names {
+                    channels: params.next().map(|c| split_list(&c)).unwrap_or_default(),
+                },
+                "LIST" => Command::List {
+                    channels: params.next().map(|c| split_list(&c)).unwrap_or_default(),
+                },
+                "WHOIS" => {
+                    let nicknames: Vec<String> = params.by_ref().collect();
+                    if nicknames.is_empty() {
+                        return Err(need_more_params());
+                    }
+                    Command::Whois { nicknames }
+                }
+                "WHO" => Command::Who {
+                    mask: params.next(),
+                    operators_only: params.next().as_deref() == Some("o"),
+                },
+                "TOPIC" => {
+                    let channel = params.next().ok_or_else(need_more_params)?;
+                    let mut topic = params.next();
+                    // entre servidores el tópico llega precedido por el momento en que se cambió
+                    let mut set_at = None;
+                    if let Some(text) = params.next().filter(|_| from_server) {
+                        set_at = topic.and_then(|time| time.parse().ok());
+                        topic = Some(text);
+                    }
+                    Command::Topic {
+                        channel,
+                        topic,
+                        set_at,
+                    }
+                }
+                "AWAY" => Command::Away {
+                    message: params.next(),
+                },
+                "MOTD" => Command::Motd {
+                    target: params.next(),
+                },
+                "PING" => Command::Ping {
+                    origin: params.next().ok_or(CommandError::NoOrigin)?,
+                    target: params.next(),
+                },
+                "PONG" => Command::Pong {
+                    origin: params.next().ok_or(CommandError::NoOrigin)?,
+                    target: params.next(),
+                },
+                "MODE" => {
+                    let target = params.next().ok_or_else(need_more_params)?;
+                    let changes: Vec<String> = params.by_ref().collect();
+                    Command::Mode { target, changes }
+                }
+                "SERVER" => Command::Server {
+                    parameters: params.by_ref().collect(),
+                },
+                "SQUIT" => Command::Squit {
+                    server: params.next().ok_or_else(need_more_params)?,
+                    comment: params.next(),
+                },
+                "SERVER_CONNECT" => Command::ServerConnect {
+                    host: params.next().ok_or_else(need_more_params)?,
+                    port: params.next().ok_or_else(need_more_params)?,
+                },
+                "SJOIN" => {
+                    let mut fields: Vec<String> = params.by_ref().collect();
+                    if fields.len() < 4 {
+                        return Err(need_more_params());
+                    }
+                    let members = fields.pop().unwrap_or_default();
+                    let mut fields = fields.into_iter();
+                    Command::Sjoin {
+                        created_at: fields
+                            .next()
+                            .and_then(|time| time.parse().ok())
+                            .ok_or_else(need_more_params)?,
+                        channel: fields.next().ok_or_else(need_more_params)?,
+                        modes: fields.collect(),
+                        members: split_words(&members),
+                    }
+                }
+                "BMASK" => {
+                    let fields: Vec<String> = params.by_ref().take(4).collect();
+                    let [created_at, channel, mode, masks]: [String; 4] =
+                        fields.try_into().map_err(|_| need_more_params())?;
+                    Command::Bmask {
+                        created_at: created_at.parse().map_err(|_| need_more_params())?,
+                        channel,
+                        mode: mode.chars().next().ok_or_else(need_more_params)?,
+                        masks: split_words(&masks),
+                    }
+                }
+                "EOB" => Command::Eob,
+                _ => return Err(CommandError::UnknownCommand(command_name)),
+            };
+        Ok(command)
+    }
+}
+
+impl From<Command> for Message {
+    ///Arma el mensaje, sin prefijo, que corresponde al comando.
+    fn from(command: Command) -> Message {
+        let (name, parameters) = match command {
+            Command::Pass { password } => ("PASS", vec![password]),
+            Command::Nick { nickname } => ("NICK", vec![nickname]),
+            Command::User {
+                username,
+                hostname,
+                servername,
+                realname,
+            } => ("USER", vec![username, hostname, servername, realname]),
+            Command::Quit { message } => ("QUIT", message.into_iter().collect()),
+            Command::Privmsg { targets, text } => ("PRIVMSG", vec![targets.join(","), text]),
+            Command::Notice { targets, text } => ("NOTICE", vec![targets.join(","), text]),
+            Command::Join { channels, keys } => {
+                let mut parameters = vec![channels.join(",")];
+                if !keys.is_empty() {
+                    parameters.push(keys.join(","));
+                }
+                ("JOIN", parameters)
+            }
+            Command::Part { channels, message } => {
+                let mut parameters = vec![channels.join(",")];
+                parameters.extend(message);
+                ("PART", parameters)
+            }
+            Command::Oper { name, password } => ("OPER", vec![name, password]),
+            Command::Kick {
+                channel,
+                nickname,
+                comment,
+            } => {
+                let mut parameters = vec![channel, nickname];
+                parameters.extend(comment);
+                ("KICK", parameters)
+            }
+            Command::OperCh {
+                channel,
+                username,
+                password,
+            } => ("OPERCH", vec![channel, username, password]),
+            Command::Invite { nickname, channel } => ("INVITE", vec![nickname, channel]),
+            Command::Names { channels } => ("NAMES", optional_list(channels)),
+            Command::List { channels } => ("LIST", optional_list(channels)),
+            Command::Whois { nicknames } => ("WHOIS", nicknames),
+            Command::Who {
+                mask,
+                operators_only,
+            } => {
+                let mut parameters: Vec<String> = mask.into_iter().collect();
+                if operators_only {
+                    parameters.push("o".to_string());
+                }
+                ("WHO", parameters)
+            }
+            Command::Topic {
+                channel,
+                topic,
+                set_at,
+            } => {
+                let mut parameters = vec![channel];
+                parameters.extend(set_at.map(|time| time.to_string()));
+                parameters.extend(topic);
+                ("TOPIC", parameters)
+            }
+            Command::Away { message } => ("AWAY", message.into_iter().collect()),
+            Command::Motd { target } => ("MOTD", target.into_iter().collect()),
+            Command::Ping { origin, target } => {
+                let mut parameters = vec![origin];
+                parameters.extend(target);
+                ("PING", parameters)
+            }
+            Command::Pong { origin, target } => {
+                let mut parameters = vec![origin];
+                parameters.extend(target);
+                ("PONG", parameters)
+            }
+            Command::Mode { target, changes } => {
+                let mut parameters = vec![target];
+                parameters.extend(changes);
+                ("MODE", parameters)
+            }
+            Command::Server { parameters } => ("SERVER", parameters),
+            Command::Squit { server, comment } => {
+                let mut parameters = vec![server];
+                parameters.extend(comment);
+                ("SQUIT", parameters)
+            }
+            Command::ServerConnect { host, port } => ("SERVER_CONNECT", vec![host, port]),
+            Command::RemoteUser {
+                nickname,
+                hopcount,
+                signed_on,
+                username,
+                hostname,
+                servername,
+                modes,
+                realname,
+            } => (
+                "NICK",
+                vec![
+                    nickname,
+                    hopcount.to_string(),
+                    signed_on.to_string(),
+                    username,
+                    hostname,
+                    servername,
+                    modes,
+                    realname,
+                ],
+            ),
+            Command::Sjoin {
+                created_at,
+                channel,
+                modes,
+                members,
+            } => {
+                let mut parameters = vec![created_at.to_string(), channel];
+                parameters.extend(modes);
+                parameters.push(members.join(" "));
+                ("SJOIN", parameters)
+            }
+            Command::Bmask {
+                created_at,
+                channel,
+                mode,
+                masks,
+            } => (
+                "BMASK",
+                vec![
+                    created_at.to_string(),
+                    channel,
+                    mode.to_string(),
+                    masks.join(" "),
+                ],
+            ),
+            Command::Eob => ("EOB", Vec::new()),
+        };
+        Message {
+            prefix: None,
+            command: name.to_string(),
+            parameters,
+        }
+    }
+}
+
+///Separa una lista de elementos separados por coma, como los destinatarios o los canales.
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',').map(|element| element.to_string()).collect()
+}
+
+///Separa una lista de elementos separados por espacios, como los miembros de un canal que se
+/// informan entre servidores.
+fn split_words(list: &str) -> Vec<String> {
+    list.split_whitespace()
+        .map(|word| word.to_string())
+        .collect()
+}
+
+fn optional_list(list: Vec<String>) -> Vec<String> {
+    if list.is_empty() {
+        Vec::new()
+    } else {
+        vec![list.join(",")]
+    }
+}
+
+#[cfg(test)]
+mod tests_command {
+    use crate::command::{Command, CommandError};
+    use crate::message::Message;
+
+    fn command_from(line: &str) -> Result<Command, CommandError> {
+        Command::try_from(Message::from(line.to_string()))
+    }
+
+    #[test]
+    fn test_privmsg_separa_destinatarios_y_texto() {
+        let command = command_from(":nick1 PRIVMSG nick2,#canal :hola a todos").unwrap();
+
+        assert_eq!(
+            Command::Privmsg {
+                targets: vec!["nick2".to_string(), "#canal".to_string()],
+                text: "hola a todos".to_string()
+            },
+            command
+        );
+    }
+
+    #[test]
+    fn test_privmsg_sin_destinatario_o_sin_texto() {
+        assert_eq!(
+            Err(CommandError::NoRecipient("PRIVMSG".to_string())),
+            command_from("PRIVMSG")
+        );
+        assert_eq!(
+            Err(CommandError::NoTextToSend),
+            command_from("PRIVMSG nick2")
+        );
+    }
+
+    #[test]
+    fn test_join_con_canales_y_claves() {
+        let command = command_from("JOIN #canal1,#canal2 clave1").unwrap();
+
+        assert_eq!(
+            Command::Join {
+                channels: vec!["#canal1".to_string(), "#canal2".to_string()],
+                keys: vec!["clave1".to_string()]
+            },
+            command
+        );
+    }
+
+    #[test]
+    fn test_comandos_con_parametros_insuficientes() {
+        for line in [
+            "JOIN",
+            "PART",
+            "OPER nick",
+            "KICK #canal",
+            "MODE",
+            "USER a b c",
+        ] {
+            let error = command_from(line).unwrap_err();
+            let reply = error.reply().unwrap();
+
+            assert_eq!("461", reply.command);
+        }
+        assert_eq!(Err(CommandError::NoNicknameGiven), command_from("NICK"));
+    }
+
+    #[test]
+    fn test_ping_sin_origen_devuelve_no_origin() {
+        let error = command_from("PING").unwrap_err();
+
+        assert_eq!(CommandError::NoOrigin, error);
+        assert_eq!("409", error.reply().unwrap().command);
+        assert_eq!(
+            Command::Pong {
+                origin: "sv1".to_string(),
+                target: None
+            },
+            command_from("PONG :sv1").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_comando_desconocido() {
+        let error = command_from("COMANDO param").unwrap_err();
+
+        assert_eq!(CommandError::UnknownCommand("COMANDO".to_string()), error);
+        assert!(error.reply().is_none());
+    }
+
+    #[test]
+    fn test_comando_se_convierte_en_mensaje() {
+        let command = Command::Privmsg {
+            targets: vec!["#canal".to_string()],
+            text: "nos vemos a las 12:30".to_string(),
+        };
+
+        let line: String = Message::from(command).into();
+
+        assert_eq!("PRIVMSG #canal :nos vemos a las 12:30", line);
+    }
+
+    #[test]
+    fn test_mode_conserva_los_cambios_y_argumentos() {
+        let message = Message::from("MODE #canal +o nick1".to_string());
+
+        let command = Command::try_from(message).unwrap();
+        let line: String = Message::from(command).into();
+
+        assert_eq!("MODE #canal +o nick1", line);
+    }
+
+    #[test]
+    fn test_part_y_kick_conservan_el_motivo() {
+        for line in ["PART #canal :me voy", "KICK #canal nick1 :por spam"] {
+            let command = Command::try_from(Message::from(line.to_string())).unwrap();
+            let converted: String = Message::from(command).into();
+
+            assert_eq!(line, converted);
+        }
+    }
+
+    #[test]
+    fn test_topic_entre_servidores_conserva_el_momento_del_cambio() {
+        let command = command_from(":nick1 TOPIC #canal 1700000000 :nuevo topic").unwrap();
+        assert_eq!(
+            Command::Topic {
+                channel: "#canal".to_string(),
+                topic: Some("nuevo topic".to_string()),
+                set_at: Some(1700000000),
+            },
+            command
+        );
+        let converted: String = Message::from(command).into();
+        assert_eq!("TOPIC #canal 1700000000 :nuevo topic", converted);
+
+        let command = command_from("TOPIC #canal :nuevo topic").unwrap();
+        assert!(matches!(command, Command::Topic { set_at: None, .. }));
+    }
+
+    #[test]
+    fn test_topic_de_cliente_con_varias_palabras_usa_el_primer_parametro() {
+        let command = command_from("TOPIC #canal hola mundo").unwrap();
+
+        assert_eq!(
+            Command::Topic {
+                channel: "#canal".to_string(),
+                topic: Some("hola".to_string()),
+                set_at: None,
+            },
+            command
+        );
+    }
+
+    #[test]
+    fn test_nick_entre_servidores_presenta_a_un_usuario() {
+        let line = "NICK juan 1 1700000000 juanp host1 server1 +iw :Juan Perez";
+        let command = command_from(&format!(":server1 {}", line)).unwrap();
+        assert_eq!(
+            Command::RemoteUser {
+                nickname: "juan".to_string(),
+                hopcount: 1,
+                signed_on: 1700000000,
+                username: "juanp".to_string(),
+                hostname: "host1".to_string(),
+                servername: "server1".to_string(),
+                modes: "+iw".to_string(),
+                realname: "Juan Perez".to_string(),
+            },
+            command
+        );
+        let converted: String = Message::from(command).into();
+        assert_eq!(line, converted);
+
+        let command = command_from(":server1 NICK juan 1").unwrap();
+        assert!(matches!(command, Command::Nick { .. }));
+        let result = command_from(":server1 NICK juan uno 1700000000 juanp host1 server1 + :Juan");
+        assert_eq!(
+            Err(CommandError::NeedMoreParams("NICK".to_string())),
+            result
+        );
+    }
+
+    #[test]
+    fn test_nick_de_cliente_ignora_los_parametros_de_mas() {
+        let line = "NICK juan 1 1700000000 juanp host1 server1 +iw :Juan Perez";
+        assert_eq!(
+            Ok(Command::Nick {
+                nickname: "juan".to_string()
+            }),
+            command_from(line)
+        );
+    }
+
+    #[test]
+    fn test_sjoin_conserva_modos_y_miembros_con_su_estado() {
+        let line = "SJOIN 1700000000 &canal +tlk 10 clave :@+juan +pedro maria";
+        let command = command_from(line).unwrap();
+        assert_eq!(
+            Command::Sjoin {
+                created_at: 1700000000,
+                channel: "&canal".to_string(),
+                modes: vec!["+tlk".to_string(), "10".to_string(), "clave".to_string()],
+                members: vec![
+                    "@+juan".to_string(),
+                    "+pedro".to_string(),
+                    "maria".to_string()
+                ],
+            },
+            command
+        );
+        let converted: String = Message::from(command).into();
+        assert_eq!(line, converted);
+
+        let result = command_from("SJOIN 1700000000 &canal :@juan");
+        assert_eq!(
+            Err(CommandError::NeedMoreParams("SJOIN".to_string())),
+            result
+        );
+    }
+
+    #[test]
+    fn test_bmask_y_eob_entre_servidores() {
+        let line = "BMASK 1700000000 &canal b :malo!*@* *!*@spam.com";
+        let command = command_from(line).unwrap();
+        assert_eq!(
+            Command::Bmask {
+                created_at: 1700000000,
+                channel: "&canal".to_string(),
+                mode: 'b',
+                masks: vec!["malo!*@*".to_string(), "*!*@spam.com".to_string()],
+            },
+            command
+        );
+        let converted: String = Message::from(command).into();
+        assert_eq!(line, converted);
+
+        assert_eq!(Ok(Command::Eob), command_from(":server1 EOB"));
+    }
+}
@@ -0,0 +1,580 @@
+use std::fs;
+
+use crate::hash::salted_sha256_hex;
+use crate::mask::{matches_casemapped, matches_user_mask};
+use crate::server_errors::ServerError;
+
+static DEFAULT_MAX_CLIENTS: usize = 1000;
+static DEFAULT_MAX_CONNECTIONS: usize = 1024;
+static DEFAULT_SENDQ: usize = 100_000;
+static DEFAULT_SERVER_SENDQ: usize = 10_000_000;
+static DEFAULT_PING_FREQUENCY: u64 = 120;
+static DEFAULT_PING_TIMEOUT: u64 = 60;
+static DEFAULT_NICKLEN: usize = 9;
+static DEFAULT_REGISTRATION_TIMEOUT: u64 = 60;
+
+///Privilegios que se le pueden otorgar a un operador: conectar servidores (SERVER_CONNECT)
+/// y desconectarlos (SQUIT).
+pub static OPER_PRIVILEGES: [&str; 2] = ["connect", "squit"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///Datos necesarios para establecer un vínculo con otro servidor de la red. La contraseña se
+/// envía con PASS al conectarse y se le exige al servidor cuando es él quien se conecta; si el
+/// bloque no tiene, se le exige la contraseña de conexión del servidor.
+pub struct LinkBlock {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub autoconnect: bool,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///Credenciales con las que un usuario puede convertirse en operador de la red. La contraseña
+/// se guarda como hash SHA-256 con sal (ver `salted_sha256_hex`) y solo es válida desde
+/// conexiones cuyo `usuario@host` coincida con alguna de las máscaras.
+pub struct OperatorBlock {
+    pub name: String,
+    pub salt: String,
+    pub password_hash: String,
+    pub hosts: Vec<String>,
+    pub privileges: Vec<String>,
+}
+
+impl OperatorBlock {
+    ///Evalúa si la contraseña pasada corresponde al hash del bloque.
+    pub fn check_password(&self, password: &str) -> bool {
+        salted_sha256_hex(&self.salt, password) == self.password_hash.to_lowercase()
+    }
+
+    ///Evalúa si el bloque puede usarse desde la conexión `username@host`.
+    pub fn allows_host(&self, username: &str, host: &str) -> bool {
+        let user_host = format!("{}@{}", username, host);
+        self.hosts
+            .iter()
+            .any(|mask| matches_casemapped(mask, &user_host))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///Ban del servidor: los usuarios cuyo `nick!user@host` coincida con la máscara no pueden
+/// registrarse.
+pub struct BanBlock {
+    pub mask: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///Límites de uso del servidor. `max_connections` acota las conexiones abiertas al mismo
+/// tiempo, registradas o no, ya que cada una se atiende en su propio hilo. Los tiempos de PING
+/// se expresan en segundos: cada cuánto se le envía PING a una conexión inactiva y cuánto se
+/// espera la respuesta antes de cerrarla. `sendq` y `server_sendq` son los bytes que pueden
+/// quedar pendientes de envío a un cliente o a un servidor antes de cortar la conexión.
+/// `nicklen` es el largo máximo de un nickname y `registration_timeout` los segundos que tiene
+/// una conexión para completar la registración.
+pub struct Limits {
+    pub max_clients: usize,
+    pub max_connections: usize,
+    pub sendq: usize,
+    pub server_sendq: usize,
+    pub ping_frequency: u64,
+    pub ping_timeout: u64,
+    pub nicklen: usize,
+    pub registration_timeout: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_clients: DEFAULT_MAX_CLIENTS,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            sendq: DEFAULT_SENDQ,
+            server_sendq: DEFAULT_SERVER_SENDQ,
+            ping_frequency: DEFAULT_PING_FREQUENCY,
+            ping_timeout: DEFAULT_PING_TIMEOUT,
+            nicklen: DEFAULT_NICKLEN,
+            registration_timeout: DEFAULT_REGISTRATION_TIMEOUT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+///Configuración de un servidor. Se obtiene de un archivo con un subconjunto del formato TOML:
+/// secciones `[server]` y `[limits]`, y bloques repetibles `[[link]]`, `[[operator]]` y `[[ban]]`.
+pub struct ServerConfig {
+    pub name: String,
+    pub description: String,
+    pub listen: Vec<String>,
+    pub links: Vec<LinkBlock>,
+    pub operators: Vec<OperatorBlock>,
+    pub bans: Vec<BanBlock>,
+    pub motd_path: Option<String>,
+    pub password: Option<String>,
+    pub limits: Limits,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Text(String),
+    Integer(usize),
+    Boolean(bool),
+    List(Vec<String>),
+}
+
+enum Section {
+    Server,
+    Limits,
+    Link,
+    Operator,
+    Ban,
+}
+
+impl ServerConfig {
+    ///Crea la configuración mínima para escuchar en una única dirección. El nombre del
+    /// servidor se arma concatenando host y puerto.
+    pub fn from_address(host: &str, port: u16) -> Self {
+        Self {
+            name: host.to_string() + &port.to_string(),
+            listen: vec![format!("{}:{}", host, port)],
+            ..Default::default()
+        }
+    }
+
+    ///Lee y valida el archivo de configuración ubicado en la ruta pasada.
+    pub fn load(path: &str) -> Result<Self, ServerError> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            ServerError::new(&format!("No se pudo leer la configuración {}: {}", path, e))
+        })?;
+        Self::parse(&content)
+    }
+
+    ///Interpreta el contenido de un archivo de configuración.
+    pub fn parse(content: &str) -> Result<Self, ServerError> {
+        let mut config = ServerConfig::default();
+        let mut section = None;
+
+        for (number, raw_line) in content.lines().enumerate() {
+            let line = raw_line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let line_error =
+                |msg: &str| ServerError::new(&format!("Línea {}: {}", number + 1, msg));
+
+            if let Some(name) = line.strip_prefix("[[").and_then(|l| l.strip_suffix("]]")) {
+                section = Some(match name.trim() {
+                    "link" => {
+                        config.links.push(LinkBlock {
+                            name: String::new(),
+                            host: String::new(),
+                            port: 0,
+                            autoconnect: false,
+                            password: None,
+                        });
+                        Section::Link
+                    }
+                    "operator" => {
+                        config.operators.push(OperatorBlock {
+                            name: String::new(),
+                            salt: String::new(),
+                            password_hash: String::new(),
+                            hosts: Vec::new(),
+                            privileges: Vec::new(),
+                        });
+                        Section::Operator
+                    }
+                    "ban" => {
+                        config.bans.push(BanBlock {
+                            mask: String::new(),
+                            reason: String::new(),
+                        });
+                        Section::Ban
+                    }
+                    _ => return Err(line_error("Bloque desconocido")),
+                });
+            } else if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = Some(match name.trim() {
+                    "server" => Section::Server,
+                    "limits" => Section::Limits,
+                    _ => return Err(line_error("Sección desconocida")),
+                });
+            } else {
+                let (key, value) = line
+                    .split_once('=')
+                    .ok_or_else(|| line_error("Se esperaba clave = valor"))?;
+                let value = parse_value(value.trim()).map_err(|e| line_error(&e.msg))?;
+                match section {
+                    Some(Section::Server) => config.set_server_key(key.trim(), value),
+                    Some(Section::Limits) => config.set_limits_key(key.trim(), value),
+                    Some(Section::Link) => config.set_link_key(key.trim(), value),
+                    Some(Section::Operator) => config.set_operator_key(key.trim(), value),
+                    Some(Section::Ban) => config.set_ban_key(key.trim(), value),
+                    None => Err(ServerError::new("Clave fuera de una sección")),
+                }
+                .map_err(|e| line_error(&e.msg))?;
+            }
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn set_server_key(&mut self, key: &str, value: Value) -> Result<(), ServerError> {
+        match key {
+            "name" => self.name = expect_text(key, value)?,
+            "description" => self.description = expect_text(key, value)?,
+            "listen" => {
+                self.listen = match value {
+                    Value::List(addresses) => addresses,
+                    other => vec![expect_text(key, other)?],
+                }
+            }
+            "motd" => self.motd_path = Some(expect_text(key, value)?),
+            "password" => self.password = Some(expect_text(key, value)?),
+            _ => return Err(unknown_key(key)),
+        }
+        Ok(())
+    }
+
+    fn set_limits_key(&mut self, key: &str, value: Value) -> Result<(), ServerError> {
+        match key {
+            "max_clients" => self.limits.max_clients = expect_integer(key, value)?,
+            "max_connections" => self.limits.max_connections = expect_integer(key, value)?,
+            "sendq" => self.limits.sendq = expect_integer(key, value)?,
+            "server_sendq" => self.limits.server_sendq = expect_integer(key, value)?,
+            "ping_frequency" => self.limits.ping_frequency = expect_integer(key, value)? as u64,
+            "ping_timeout" => self.limits.ping_timeout = expect_integer(key, value)? as u64,
+            "nicklen" => self.limits.nicklen = expect_integer(key, value)?,
+            "registration_timeout" => {
+                self.limits.registration_timeout = expect_integer(key, value)? as u64
+            }
+            _ => return Err(unknown_key(key)),
+        }
+        Ok(())
+    }
+
+    fn set_link_key(&mut self, key: &str, value: Value) -> Result<(), ServerError> {
+        let link = self
+            .links
+            .last_mut()
+            .ok_or_else(|| ServerError::new("Bloque link inexistente"))?;
+        match key {
+            "name" => link.name = expect_text(key, value)?,
+            "host" => link.host = expect_text(key, value)?,
+            "port" => {
+                link.port = u16::try_from(expect_integer(key, value)?)
+                    .map_err(|_| ServerError::new("Puerto inválido"))?
+            }
+            "autoconnect" => link.autoconnect = expect_boolean(key, value)?,
+            "password" => link.password = Some(expect_text(key, value)?),
+            _ => return Err(unknown_key(key)),
+        }
+        Ok(())
+    }
+
+    fn set_operator_key(&mut self, key: &str, value: Value) -> Result<(), ServerError> {
+        let operator = self
+            .operators
+            .last_mut()
+            .ok_or_else(|| ServerError::new("Bloque operator inexistente"))?;
+        match key {
+            "name" => operator.name = expect_text(key, value)?,
+            "salt" => operator.salt = expect_text(key, value)?,
+            "password_hash" => operator.password_hash = expect_text(key, value)?,
+            "hosts" => operator.hosts = expect_list(key, value)?,
+            "privileges" => operator.privileges = expect_list(key, value)?,
+            _ => return Err(unknown_key(key)),
+        }
+        Ok(())
+    }
+
+    fn set_ban_key(&mut self, key: &str, value: Value) -> Result<(), ServerError> {
+        let ban = self
+            .bans
+            .last_mut()
+            .ok_or_else(|| ServerError::new("Bloque ban inexistente"))?;
+        match key {
+            "mask" => ban.mask = expect_text(key, value)?,
+            "reason" => ban.reason = expect_text(key, value)?,
+            _ => return Err(unknown_key(key)),
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ServerError> {
+        if self.name.is_empty() || self.name.contains(' ') {
+            return Err(ServerError::new(
+                "El servidor necesita un nombre sin espacios",
+            ));
+        }
+        if self.listen.is_empty() {
+            return Err(ServerError::new(
+                "No se configuró ninguna dirección de escucha",
+            ));
+        }
+        if self.limits.ping_frequency == 0 || self.limits.ping_timeout == 0 {
+            return Err(ServerError::new(
+                "ping_frequency y ping_timeout deben ser mayores a cero",
+            ));
+        }
+        if self.limits.nicklen == 0 {
+            return Err(ServerError::new("nicklen debe ser mayor a cero"));
+        }
+        if self.limits.registration_timeout == 0 {
+            return Err(ServerError::new(
+                "registration_timeout debe ser mayor a cero",
+            ));
+        }
+        for link in &self.links {
+            if link.name.is_empty() || link.host.is_empty() || link.port == 0 {
+                return Err(ServerError::new(
+                    "Los bloques link necesitan name, host y port",
+                ));
+            }
+        }
+        for operator in &self.operators {
+            if operator.name.is_empty()
+                || operator.salt.is_empty()
+                || operator.password_hash.len() != 64
+                || operator.hosts.is_empty()
+            {
+                return Err(ServerError::new(
+                    "Los bloques operator necesitan name, salt, password_hash (SHA-256) y hosts",
+                ));
+            }
+            if let Some(privilege) = operator
+                .privileges
+                .iter()
+                .find(|privilege| !OPER_PRIVILEGES.contains(&privilege.as_str()))
+            {
+                return Err(ServerError::new(&format!(
+                    "Privilegio de operador desconocido: {}",
+                    privilege
+                )));
+            }
+        }
+        if self.bans.iter().any(|ban| ban.mask.is_empty()) {
+            return Err(ServerError::new("Los bloques ban necesitan mask"));
+        }
+        Ok(())
+    }
+
+    ///Busca el bloque link con el nombre pasado.
+    pub fn find_link(&self, name: &str) -> Option<&LinkBlock> {
+        self.links.iter().find(|link| link.name == name)
+    }
+
+    ///Busca un ban del servidor que coincida con el prefijo `nick!user@host` de un usuario.
+    pub fn find_ban(&self, user_prefix: &str) -> Option<&BanBlock> {
+        self.bans
+            .iter()
+            .find(|ban| matches_user_mask(&ban.mask, user_prefix))
+    }
+
+    ///Lee las líneas del mensaje del día. Se lee en cada pedido para que los cambios en el
+    /// archivo se vean sin reiniciar el servidor. Devuelve None si no hay archivo configurado
+    /// o no se puede leer.
+    pub fn read_motd(&self) -> Option<Vec<String>> {
+        let path = self.motd_path.as_ref()?;
+        let content = fs::read_to_string(path).ok()?;
+        Some(content.lines().map(|line| line.to_string()).collect())
+    }
+}
+
+fn parse_value(raw: &str) -> Result<Value, ServerError> {
+    if let Some(inner) = raw.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+        let mut items = Vec::new();
+        for item in inner.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            match parse_value(item)? {
+                Value::Text(text) => items.push(text),
+                _ => return Err(ServerError::new("Las listas solo admiten textos")),
+            }
+        }
+        return Ok(Value::List(items));
+    }
+    if let Some(text) = raw.strip_prefix('"').and_then(|r| r.strip_suffix('"')) {
+        return Ok(Value::Text(text.to_string()));
+    }
+    match raw {
+        "true" => Ok(Value::Boolean(true)),
+        "false" => Ok(Value::Boolean(false)),
+        _ => raw
+            .parse::<usize>()
+            .map(Value::Integer)
+            .map_err(|_| ServerError::new("Valor inválido")),
+    }
+}
+
+fn expect_text(key: &str, value: Value) -> Result<String, ServerError> {
+    match value {
+        Value::Text(text) => Ok(text),
+        _ => Err(ServerError::new(&format!("{} debe ser un texto", key))),
+    }
+}
+
+fn expect_list(key: &str, value: Value) -> Result<Vec<String>, ServerError> {
+    match value {
+        Value::List(items) => Ok(items),
+        _ => Err(ServerError::new(&format!("{} debe ser una lista", key))),
+    }
+}
+
+fn expect_integer(key: &str, value: Value) -> Result<usize, ServerError> {
+    match value {
+        Value::Integer(number) => Ok(number),
+        _ => Err(ServerError::new(&format!("{} debe ser un número", key))),
+    }
+}
+
+fn expect_boolean(key: &str, value: Value) -> Result<bool, ServerError> {
+    match value {
+        Value::Boolean(flag) => Ok(flag),
+        _ => Err(ServerError::new(&format!("{} debe ser true o false", key))),
+    }
+}
+
+fn unknown_key(key: &str) -> ServerError {
+    ServerError::new(&format!("Clave desconocida: {}", key))
+}
+
+#[cfg(test)]
+mod tests_config {
+    use crate::config::{LinkBlock, OperatorBlock, ServerConfig};
+
+    static FULL_CONFIG: &str = r#"
+# Servidor principal
+[server]
+name = "irc.fiuba.ar"
+description = "Servidor de la materia"
+listen = ["127.0.0.1:6667", "0.0.0.0:6697"]
+motd = "motd.txt"
+password = "secreta"
+
+[limits]
+max_clients = 50
+max_connections = 60
+sendq = 50000
+ping_frequency = 90
+ping_timeout = 30
+nicklen = 16
+registration_timeout = 20
+
+[[link]]
+name = "irc2.fiuba.ar"
+host = "127.0.0.1"
+port = 6668
+autoconnect = true
+password = "enlace"
+
+[[operator]]
+name = "admin"
+salt = "f1ub4s4l"
+password_hash = "6a2bc7767aa15f429e4d7d40b6473e308181153882e99bd002718df72bd36ccf"
+hosts = ["*@127.0.0.1", "admin@*.fi.uba.ar"]
+privileges = ["connect", "squit"]
+
+[[ban]]
+mask = "*!*@*.example.org"
+reason = "Spam"
+"#;
+
+    #[test]
+    fn test_configuracion_completa_se_interpreta_correctamente() {
+        let config = ServerConfig::parse(FULL_CONFIG).unwrap();
+
+        assert_eq!(config.name, "irc.fiuba.ar");
+        assert_eq!(config.description, "Servidor de la materia");
+        assert_eq!(config.listen, vec!["127.0.0.1:6667", "0.0.0.0:6697"]);
+        assert_eq!(config.motd_path, Some("motd.txt".to_string()));
+        assert_eq!(config.password, Some("secreta".to_string()));
+        assert_eq!(config.limits.max_clients, 50);
+        assert_eq!(config.limits.max_connections, 60);
+        assert_eq!(config.limits.sendq, 50000);
+        assert_eq!(config.limits.server_sendq, 10_000_000);
+        assert_eq!(config.limits.ping_frequency, 90);
+        assert_eq!(config.limits.ping_timeout, 30);
+        assert_eq!(config.limits.nicklen, 16);
+        assert_eq!(config.limits.registration_timeout, 20);
+        assert_eq!(
+            config.links,
+            vec![LinkBlock {
+                name: "irc2.fiuba.ar".to_string(),
+                host: "127.0.0.1".to_string(),
+                port: 6668,
+                autoconnect: true,
+                password: Some("enlace".to_string()),
+            }]
+        );
+        assert_eq!(
+            config.operators,
+            vec![OperatorBlock {
+                name: "admin".to_string(),
+                salt: "f1ub4s4l".to_string(),
+                password_hash: "6a2bc7767aa15f429e4d7d40b6473e308181153882e99bd002718df72bd36ccf"
+                    .to_string(),
+                hosts: vec!["*@127.0.0.1".to_string(), "admin@*.fi.uba.ar".to_string()],
+                privileges: vec!["connect".to_string(), "squit".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_bloque_operator_valida_contraseña_y_host() {
+        let config = ServerConfig::parse(FULL_CONFIG).unwrap();
+        let operator = &config.operators[0];
+
+        assert!(operator.check_password("1234"));
+        assert!(!operator.check_password("12345"));
+        assert!(operator.allows_host("juan", "127.0.0.1"));
+        assert!(operator.allows_host("admin", "pc1.fi.uba.ar"));
+        assert!(!operator.allows_host("juan", "pc1.fi.uba.ar"));
+    }
+
+    #[test]
+    fn test_bloque_ban_se_aplica_a_mascaras_de_usuario() {
+        let config = ServerConfig::parse(FULL_CONFIG).unwrap();
+
+        let ban = config.find_ban("juan!juan@pc1.EXAMPLE.org").unwrap();
+        assert_eq!("Spam", ban.reason);
+        assert!(config.find_ban("juan!juan@pc1.fi.uba.ar").is_none());
+    }
+
+    #[test]
+    fn test_bloque_operator_con_privilegio_desconocido_es_invalido() {
+        let config = ServerConfig::parse(
+            "[server]\nname = \"sv\"\nlisten = \"127.0.0.1:6667\"\n[[operator]]\nname = \"admin\"\nsalt = \"f1ub4s4l\"\npassword_hash = \"6a2bc7767aa15f429e4d7d40b6473e308181153882e99bd002718df72bd36ccf\"\nhosts = [\"*@*\"]\nprivileges = [\"kill\"]\n",
+        );
+
+        assert_eq!(
+            config.unwrap_err().msg,
+            "Privilegio de operador desconocido: kill".to_string()
+        );
+    }
+
+    #[test]
+    fn test_configuracion_sin_nombre_es_invalida() {
+        let config = ServerConfig::parse("[server]\nlisten = \"127.0.0.1:6667\"\n");
+
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn test_clave_desconocida_informa_la_linea() {
+        let config = ServerConfig::parse("[server]\nname = \"sv\"\ncolor = \"rojo\"\n");
+
+        assert_eq!(
+            config.unwrap_err().msg,
+            "Línea 3: Clave desconocida: color".to_string()
+        );
+    }
+
+    #[test]
+    fn test_configuracion_a_partir_de_direccion_arma_el_nombre() {
+        let config = ServerConfig::from_address("localhost", 8080);
+
+        assert_eq!(config.name, "localhost8080");
+        assert_eq!(config.listen, vec!["localhost:8080"]);
+        assert!(config.operators.is_empty());
+        assert!(config.password.is_none());
+    }
+}
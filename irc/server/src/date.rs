@@ -0,0 +1,67 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+static MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+///Devuelve la cantidad de segundos transcurridos desde el 1 de enero de 1970 (UTC).
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+///Convierte un timestamp Unix en una fecha legible en UTC, con el formato
+/// "Sat Jan 01 2000 at 12:30:00 UTC".
+pub fn format_date(timestamp: u64) -> String {
+    let days = timestamp / 86400;
+    let seconds = timestamp % 86400;
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{} {} {:02} {} at {:02}:{:02}:{:02} UTC",
+        WEEKDAYS[(days % 7) as usize],
+        MONTHS[(month - 1) as usize],
+        day,
+        year,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+// Algoritmo de Howard Hinnant para pasar de días desde 1970 a año, mes y día del calendario
+// gregoriano.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests_date {
+    use crate::date::format_date;
+
+    #[test]
+    fn test_formato_del_inicio_de_la_epoca() {
+        assert_eq!("Thu Jan 01 1970 at 00:00:00 UTC", format_date(0));
+    }
+
+    #[test]
+    fn test_formato_de_fecha_en_anio_bisiesto() {
+        assert_eq!("Tue Feb 29 2000 at 12:30:05 UTC", format_date(951827405));
+    }
+}
@@ -1,237 +1,416 @@
-use std::io::Write;
-use std::sync::{Arc, Mutex};
+use std::collections::HashSet;
+use std::thread;
 use std::vec;
 
+use crate::channel_modes::{mode_changes_parameters, parse_mode_changes, ModeChange};
+use crate::config::OperatorBlock;
+use crate::mask::matches_casemapped;
+use crate::registration::is_valid_nickname;
 use crate::server::attempt_server_conection;
-use crate::server_messages_interpreter::{server_msg, squit_msg};
+use crate::server_messages_interpreter::{burst_msg, propagate_user_msg, server_msg, squit_msg};
 
 use crate::channel::{
-    invite_msg, join_msg, list_msg, mode_msg, names_msg, oper_ch_msg, part_msg, topic_msg, Channel,
+    invite_msg, join_msg, list_msg, mode_msg, names_msg, oper_ch_msg, part_msg,
+    send_to_channel_members, topic_msg, user_event_msg, Channel,
 };
+use crate::command::{Command, CommandError};
+use crate::registry::{casefold, same_name, Registry};
 use crate::{message::Message, server::Server, user::User};
 
 use crate::replies::{
     err_already_registred, err_can_not_send_to_chan, err_chan_opriv_is_needed,
-    err_need_more_params, err_nickname_in_use, err_no_nickname_given, err_no_oper_host,
-    err_no_privileges, err_no_recpient, err_no_such_channel, err_no_such_nick, err_no_text_tosend,
-    error_no_such_nick, rpl_away, rpl_unaway, rpl_who_reply, rpl_whoisuser, rpl_you_are_oper,
+    err_erroneus_nickname, err_nickname_in_use, err_no_motd, err_no_oper_host, err_no_privileges,
+    err_no_such_channel, err_no_such_nick, err_passwd_mismatch, err_umode_unknown_flag,
+    err_user_not_in_channel, err_users_dont_match, error_no_such_nick, rpl_away, rpl_end_of_motd,
+    rpl_motd, rpl_motd_start, rpl_umode_is, rpl_unaway, rpl_who_reply, rpl_whoisuser,
+    rpl_you_are_oper,
 };
 use crate::server_errors::ServerError;
 ///Recibe el mensaje que fue emitido a un servidor y deriva su
 /// procesamiento a la función correspondiente.
 pub fn process_message(
     msg: Message,
-    server: Arc<Server>,
+    server: &mut Server,
     user_nickname: &mut String,
 ) -> Result<Vec<Message>, ServerError> {
-    match msg.command.as_str() {
-        "PASS" => password_msg(msg),
-        "USER" => user_msg(msg),
-        "NICK" => nick_msg(msg, server.users.clone(), user_nickname),
-
-        "PRIVMSG" => priv_msg(msg, server, user_nickname.to_string()),
-        "QUIT" => quit_msg(msg, user_nickname, server.users.clone()),
-        "NOTICE" => notice_msg(
-            msg,
-            server.users.clone(),
-            user_nickname.to_string(),
-            server.channels.clone(),
+    interpret_message(msg, server, user_nickname, "")
+}
+
+///Procesa un mensaje recibido por el vínculo con otro servidor. Los eventos de usuarios que
+/// se propagan a la red no se le devuelven a ese servidor.
+pub fn process_link_message(
+    msg: Message,
+    server: &mut Server,
+    link: &str,
+) -> Result<Vec<Message>, ServerError> {
+    interpret_message(msg, server, &mut String::new(), link)
+}
+
+fn interpret_message(
+    mut msg: Message,
+    server: &mut Server,
+    user_nickname: &mut String,
+    received_from: &str,
+) -> Result<Vec<Message>, ServerError> {
+    // Solo los servidores pueden indicar el origen del mensaje con un prefijo
+    if !user_nickname.is_empty() {
+        msg.prefix = None;
+    }
+    let prefix = msg.prefix.clone();
+    let command = match Command::try_from(msg) {
+        Ok(command) => command,
+        Err(CommandError::UnknownCommand(command)) => {
+            println!("Comando inválido: {}", command);
+            return Err(ServerError::new("Comando invalido"));
+        }
+        Err(error) => return Ok(error.reply().into_iter().collect()),
+    };
+    // Los servidores informan los eventos de sus usuarios con el nick como prefijo
+    let sender_nickname = match &prefix {
+        Some(prefix) if user_nickname.is_empty() => prefix.clone(),
+        _ => user_nickname.to_string(),
+    };
+    match command {
+        Command::Pass { .. } => password_msg(),
+        Command::User { .. } => user_msg(),
+        Command::Nick { nickname } => match prefix {
+            // Los servidores informan el cambio de nick de sus usuarios con el nick anterior
+            // como prefijo
+            Some(mut old_nickname) if user_nickname.is_empty() => {
+                change_nickname(&mut old_nickname, nickname, server, received_from)
+                    .map(|_| Vec::new())
+            }
+            _ => nick_msg(nickname, user_nickname, server),
+        },
+
+        Command::Privmsg { targets, text } => {
+            let sender = prefix.unwrap_or_else(|| user_nickname.to_string());
+            priv_msg(
+                targets,
+                text,
+                sender,
+                server,
+                user_nickname.to_string(),
+                received_from,
+            )
+        }
+        Command::Quit { message } => quit_msg(&sender_nickname, message, server, received_from),
+        Command::Notice { targets, text } => {
+            let sender = prefix.unwrap_or_else(|| user_nickname.to_string());
+            notice_msg(targets, text, sender, server, received_from)
+        }
+        Command::Join { channels, keys } => join_msg(
+            channels,
+            keys,
+            sender_nickname,
+            &mut server.users,
+            &mut server.channels,
+            &server.connected_servers,
+            received_from,
         ),
-        "JOIN" => join_msg(
-            msg,
-            user_nickname.to_string(),
-            server.users.clone(),
-            server.channels.clone(),
-            server.connected_servers.clone(),
-            server.name.clone(),
+        Command::Part { channels, message } => part_msg(
+            channels,
+            message,
+            sender_nickname,
+            &mut server.users,
+            &mut server.channels,
+            &server.connected_servers,
+            received_from,
         ),
-        "PART" => part_msg(
-            msg,
-            user_nickname.to_string(),
-            server.users.clone(),
-            server.channels.clone(),
+        Command::Oper { name, password } => oper_msg(
+            &name,
+            &password,
+            &mut server.users,
+            &user_nickname.to_string(),
+            &server.config.operators,
+            &server.connected_servers,
         ),
-        "OPER" => oper_msg(msg, server.users.clone(), &user_nickname.to_string()),
-        "KICK" => kick_msg(
-            msg,
-            server.users.clone(),
-            server.channels.clone(),
-            user_nickname,
+        Command::Kick {
+            channel,
+            nickname,
+            comment,
+        } => kick_msg(
+            &channel,
+            &nickname,
+            comment,
+            server,
+            &sender_nickname,
+            received_from,
         ),
-        "OPERCH" => oper_ch_msg(
-            msg,
-            server.users.clone(),
-            server.channels.clone(),
+        Command::OperCh {
+            username, password, ..
+        } => oper_ch_msg(
+            &username,
+            &password,
+            &server.users,
+            &mut server.channels,
             user_nickname.to_string(),
         ),
-        "INVITE" => invite_msg(
-            msg,
-            server.users.clone(),
+        Command::Invite { nickname, channel } => invite_msg(
+            &nickname,
+            &channel,
+            &mut server.users,
             user_nickname.to_string(),
-            server.channels.clone(),
+            &mut server.channels,
         ),
-        "NAMES" => names_msg(
-            msg,
-            server.users.clone(),
+        Command::Names { channels } => names_msg(
+            channels,
+            &server.users,
             user_nickname.to_string(),
-            server.channels.clone(),
+            &server.channels,
         ),
-        "LIST" => list_msg(
-            msg,
+        Command::List { channels } => list_msg(
+            channels,
             user_nickname.to_string(),
-            server.users.clone(),
-            server.channels.clone(),
+            &server.users,
+            &server.channels,
+        ),
+        Command::Whois { nicknames } => whois_msg(nicknames, &server.users),
+        Command::Who {
+            mask,
+            operators_only,
+        } => who_msg(mask, operators_only, user_nickname, &server.users),
+        // Solo se acepta el momento del cambio de tópico informado por otro servidor
+        Command::Topic {
+            channel,
+            topic,
+            set_at,
+        } => topic_msg(
+            &channel,
+            topic,
+            set_at.filter(|_| user_nickname.is_empty()),
+            sender_nickname,
+            server,
+            received_from,
+        ),
+        Command::Away { message } => away_msg(message, user_nickname, &mut server.users),
+        Command::Motd { .. } => motd_msg(server),
+        Command::Ping { origin, .. } => ping_msg(origin, server),
+        Command::Pong { .. } => Ok(Vec::new()),
+        Command::Mode { target, changes } if target.starts_with(['#', '&']) => mode_msg(
+            &target,
+            changes,
+            sender_nickname,
+            &server.users,
+            &mut server.channels,
+            &server.connected_servers,
+            received_from,
+        ),
+        Command::Mode { target, changes } => user_mode_msg(
+            &target,
+            changes,
+            &sender_nickname,
+            user_nickname.is_empty(),
+            server,
+            received_from,
         ),
-        "WHOIS" => whois_msg(msg, server.users.clone()),
-        "WHO" => who_msg(msg, server.users.clone()),
-        "TOPIC" => topic_msg(msg, user_nickname.to_string(), server.channels.clone()),
-        "AWAY" => away_msg(msg, server.users.clone()),
-        "MODE" => mode_msg(msg, user_nickname.to_string(), server.channels.clone()),
-        "SERVER" => server_msg(msg, server),
-        "SQUIT" => squit_msg(msg, server, &user_nickname.to_string()),
-        "SERVER_CONNECT" => attempt_sv_connection(msg, server, user_nickname.to_string()),
-        _ => {
-            println!("Comando inválido");
-            Err(ServerError::new("Comando invalido"))
+        // Un cliente registrado no puede volver a registrarse como servidor
+        Command::Server { .. } if !user_nickname.is_empty() => Ok(vec![err_already_registred()]),
+        Command::Server { parameters } => server_msg(parameters, prefix, server),
+        Command::RemoteUser { .. }
+        | Command::Sjoin { .. }
+        | Command::Bmask { .. }
+        | Command::Eob
+            if !user_nickname.is_empty() =>
+        {
+            Ok(vec![err_already_registred()])
+        }
+        Command::RemoteUser { .. }
+        | Command::Sjoin { .. }
+        | Command::Bmask { .. }
+        | Command::Eob => burst_msg(command, prefix, server),
+        Command::Squit { server: name, .. } => {
+            squit_msg(name, prefix, server, &user_nickname.to_string())
+        }
+        Command::ServerConnect { host, port } => {
+            attempt_sv_connection(host, port, server, user_nickname.to_string())
         }
     }
 }
 /// Se encarga de interpretar el mensaje de KICK enviado a un servidor
-/// y en caso de éxito quitar al usuario del canal.
+/// y en caso de éxito quitar al usuario del canal. El KICK, con el comentario o en su defecto
+/// el nick de quien expulsa, se les envía a todos los miembros del canal y al resto de la red.
 pub fn kick_msg(
-    msg: Message,
-    users: Arc<Mutex<Vec<User>>>,
-    channels: Arc<Mutex<Vec<Channel>>>,
-    nick: &mut String,
+    channel_name: &str,
+    kicked_nickname: &str,
+    comment: Option<String>,
+    server: &mut Server,
+    nick: &str,
+    received_from: &str,
 ) -> Result<Vec<Message>, ServerError> {
     let mut response_vector = Vec::new();
-    let mut lock = channels
-        .lock()
-        .map_err(|_e| ServerError::new("Cannot obtain channels list"))?;
-    let mut lock_users = users
-        .lock()
-        .map_err(|_e| ServerError::new("Cannot obtain users list"))?;
-    if msg.parameters.len() < 2 {
-        let need_more_params_message = err_need_more_params(msg.command.clone());
-        response_vector.push(need_more_params_message);
-    }
-    let current_user = lock_users
-        .iter_mut()
-        .find(|user| user.nickname == *nick)
+    let users = &mut server.users;
+    let channels = &mut server.channels;
+    let current_user = users
+        .get(nick)
         .ok_or_else(|| ServerError::new("Cannot obtain curren user"))?;
-    match lock
-        .iter_mut()
-        .find(|channel| channel.name == msg.parameters[0])
-        .ok_or(())
-    {
-        Ok(current_channel) => {
-            if current_channel.is_admin(&current_user.nickname) {
-                current_channel.remove_user(&msg.parameters[1]);
-            } else {
-                let chanopriv = err_chan_opriv_is_needed(current_channel.name.clone());
-                response_vector.push(chanopriv);
-            }
-        }
-        Err(()) => {
-            let no_channel = err_no_such_channel(msg.parameters[0].clone());
-            response_vector.push(no_channel);
-        }
+    let Some(current_channel) = channels.get_mut(channel_name) else {
+        response_vector.push(err_no_such_channel(channel_name.to_string()));
+        return Ok(response_vector);
+    };
+    // un KICK de otro servidor ya se validó allí y se aplica sin revisar
+    if received_from.is_empty() && !current_channel.is_admin(&current_user.nickname) {
+        let chanopriv = err_chan_opriv_is_needed(current_channel.name.clone());
+        response_vector.push(chanopriv);
+        return Ok(response_vector);
     }
+    if !current_channel.has_user(kicked_nickname) {
+        let not_in_channel =
+            err_user_not_in_channel(kicked_nickname.to_string(), current_channel.name.clone());
+        response_vector.push(not_in_channel);
+        return Ok(response_vector);
+    }
+
+    let command = Command::Kick {
+        channel: current_channel.name.clone(),
+        nickname: kicked_nickname.to_string(),
+        comment: Some(comment.unwrap_or_else(|| current_user.nickname.clone())),
+    };
+    let kick = user_event_msg(&current_user.prefix(), command);
+    send_to_channel_members(current_channel, users, &kick, nick);
+    if current_user.socket.is_some() {
+        response_vector.push(kick.clone());
+    }
+    current_channel.remove_user(kicked_nickname);
+    if let Some(kicked_user) = users.get_mut(kicked_nickname) {
+        kicked_user.leave_channel(&current_channel.name);
+    }
+    propagate_user_msg(kick, nick, received_from, &server.connected_servers);
 
     Ok(response_vector)
 }
 /// Se encarga de interpretar el mensaje de PASS enviado a un servidor
 /// y si se quiere cambiar la contraseña, notifica que el usuario ya está registrado.
-pub fn password_msg(msg: Message) -> Result<Vec<Message>, ServerError> {
-    let mut response_vector = Vec::new();
-    if msg.parameters.len() != 1 {
-        let need_more_params_message = err_need_more_params(msg.command);
-        response_vector.push(need_more_params_message);
-    } else {
-        let already_registered = err_already_registred();
-        response_vector.push(already_registered)
-    }
-    Ok(response_vector)
+pub fn password_msg() -> Result<Vec<Message>, ServerError> {
+    Ok(vec![err_already_registred()])
 }
 /// Se encarga de interpretar el mensaje de NICK enviado a un servidor
 /// y en caso de éxito permite que un usuario se cambie el nickname.
 pub fn nick_msg(
-    msg: Message,
-    users: Arc<Mutex<Vec<User>>>,
+    new_nickname: String,
     nick: &mut String,
+    server: &mut Server,
+) -> Result<Vec<Message>, ServerError> {
+    if !is_valid_nickname(&new_nickname, server.config.limits.nicklen) {
+        return Ok(vec![err_erroneus_nickname(new_nickname)]);
+    }
+    change_nickname(nick, new_nickname, server, "")
+}
+
+///Cambia el nick de un usuario en la lista de usuarios y en los canales de los que es miembro.
+/// El cambio, con la forma `:anterior!user@host NICK nuevo`, se les envía al propio usuario y a
+/// quienes comparten canal con él, y se propaga al resto de la red.
+pub fn change_nickname(
+    nick: &mut String,
+    new_nickname: String,
+    server: &mut Server,
+    received_from: &str,
 ) -> Result<Vec<Message>, ServerError> {
     let mut response_vector = Vec::new();
-    let mut lock = users.lock().unwrap();
-    let aux_lock = &lock.clone();
-    if msg.parameters.is_empty() {
-        let no_nick = err_no_nickname_given();
-        response_vector.push(no_nick);
+    let users = &mut server.users;
+    // El mismo cambio puede llegar por más de un servidor
+    let Some(prefix) = users.get(nick).map(User::prefix) else {
+        return Ok(response_vector);
+    };
+    if users.rename(nick, &new_nickname).is_err() {
+        response_vector.push(err_nickname_in_use(new_nickname));
         return Ok(response_vector);
     }
-    let mut nick_in_use = false;
-    for user in lock.iter() {
-        if user.nickname == msg.parameters[0] {
-            nick_in_use = true;
-            break;
-        }
-    }
-
-    let mut current_user = lock.iter_mut().find(|user| user.nickname == *nick).unwrap();
 
-    match nick_in_use {
-        true => {
-            let nick_repetido = err_nickname_in_use(msg.parameters[0].to_string());
-            response_vector.push(nick_repetido);
+    let nick_change = user_event_msg(
+        &prefix,
+        Command::Nick {
+            nickname: new_nickname.clone(),
+        },
+    );
+    let mut neighbours = HashSet::new();
+    for channel in server.channels.iter_mut() {
+        if channel.rename_user(nick, &new_nickname) {
+            neighbours.extend(
+                channel
+                    .members
+                    .iter()
+                    .map(|member| casefold(&member.nickname)),
+            );
         }
-        false => {
-            *nick = msg.parameters[0].to_string();
-            let mut server_users = String::new();
-            for u in aux_lock.iter() {
-                if u.nickname == current_user.nickname {
-                    server_users = server_users + &msg.parameters[0].to_string() + " ";
-                } else {
-                    server_users = server_users + &u.nickname + " ";
-                }
-            }
-            for u in aux_lock.iter() {
-                let _ = u.update_server_users(server_users.clone());
+    }
+    for u in users.iter() {
+        if same_name(&u.nickname, &new_nickname) {
+            if u.socket.is_some() {
+                response_vector.push(nick_change.clone());
             }
-            current_user.nickname = msg.parameters[0].to_string();
-            println!("Nickname changed to {}", msg.parameters[0]);
+        } else if neighbours.contains(&casefold(&u.nickname)) {
+            let _ = u.send_private_message(nick_change.clone());
         }
     }
+
+    let mut server_users = String::new();
+    for u in users.iter() {
+        server_users = server_users + &u.nickname + " ";
+    }
+    for u in users.iter() {
+        let _ = u.update_server_users(server_users.clone());
+    }
+    propagate_user_msg(nick_change, nick, received_from, &server.connected_servers);
+    println!("Nickname changed from {} to {}", nick, new_nickname);
+    *nick = new_nickname;
     Ok(response_vector)
 }
 /// Se encarga de interpretar el mensaje de USER enviado a un servidor
 /// y si se quiere cambiar el username, notifica que el usuario ya está registrado.
-pub fn user_msg(msg: Message) -> Result<Vec<Message>, ServerError> {
-    let mut response_vector = Vec::new();
-    if msg.parameters.len() != 4 {
-        let need_more_params_message = err_need_more_params(msg.command);
-        response_vector.push(need_more_params_message);
-    } else {
-        let already_registered = err_already_registred();
-        response_vector.push(already_registered)
-    }
-    Ok(response_vector)
+pub fn user_msg() -> Result<Vec<Message>, ServerError> {
+    Ok(vec![err_already_registred()])
 }
 /// Se encarga de interpretar el mensaje de QUIT enviado a un servidor
-/// y en caso de éxito desconecta al usuario del servidor.
+/// y en caso de éxito desconecta al usuario del servidor, informándolo al resto de la red.
 pub fn quit_msg(
-    msg: Message,
-    nickname: &String,
-    users: Arc<Mutex<Vec<User>>>,
+    nickname: &str,
+    message: Option<String>,
+    server: &mut Server,
+    received_from: &str,
 ) -> Result<Vec<Message>, ServerError> {
-    let mut users_list = users
-        .lock()
-        .map_err(|_e| ServerError::new("Cannot obtain users list"))?;
-    let index = users_list
-        .iter()
-        .position(|x| &x.nickname == nickname)
-        .unwrap();
-    users_list.remove(index);
+    let users_list = &mut server.users;
+    let user = match users_list.remove(nickname) {
+        Some(user) => user,
+        // Ya fue desconectado, por ejemplo al recibir el mismo QUIT por otro servidor
+        None => return Ok(Vec::new()),
+    };
+    let reason = message.unwrap_or_else(|| nickname.to_string());
+    let quit = user_event_msg(
+        &user.prefix(),
+        Command::Quit {
+            message: Some(reason.clone()),
+        },
+    );
+    if let Some(queue) = &user.send_queue {
+        let closing = format!(
+            "ERROR :Closing Link: {} ({})\n",
+            user.connection_host(),
+            reason
+        );
+        let _ = queue.push(closing);
+        queue.close();
+    }
     // Al borrarlo de la lista, se pierde el ownership y se dropea el usuario
-    println!("Usuario desconectado!: {:?}", msg.parameters[0]);
+    println!("Usuario desconectado!: {:?} ({})", nickname, reason);
+
+    let mut neighbours = HashSet::new();
+    for channel in server.channels.iter_mut() {
+        if channel.has_user(nickname) {
+            channel.remove_user(nickname);
+            neighbours.extend(
+                channel
+                    .members
+                    .iter()
+                    .map(|member| casefold(&member.nickname)),
+            );
+        }
+    }
+    for u in users_list.iter() {
+        if u.socket.is_some() && neighbours.contains(&casefold(&u.nickname)) {
+            let _ = u.send_private_message(quit.clone());
+        }
+    }
 
     let mut server_users = String::new();
 
@@ -243,91 +422,72 @@ pub fn quit_msg(
     for u in users_list.iter() {
         let _ = u.update_server_users(server_users.clone());
     }
+    propagate_user_msg(quit, nickname, received_from, &server.connected_servers);
 
     Ok(Vec::new())
 }
+/// Se encarga de interpretar el mensaje de PING enviado a un servidor
+/// y responde con un PONG que devuelve el origen recibido.
+pub fn ping_msg(origin: String, server: &Server) -> Result<Vec<Message>, ServerError> {
+    let mut pong = Message::from(Command::Pong {
+        origin: server.name.clone(),
+        target: Some(origin),
+    });
+    pong.prefix = Some(server.name.clone());
+    Ok(vec![pong])
+}
 /// Se encarga de interpretar el mensaje de PRIVMSG enviado a un servidor
 /// y en caso de éxito enviar un mensaje privado a un canal o a un usuario
 pub fn priv_msg(
-    msg: Message,
-    server: Arc<Server>,
+    targets: Vec<String>,
+    text: String,
+    sender: String,
+    server: &Server,
     nick: String,
+    received_from: &str,
 ) -> Result<Vec<Message>, ServerError> {
     let mut response_vector = Vec::new();
+    let mut msg = Message::from(Command::Privmsg {
+        targets: targets.clone(),
+        text,
+    });
+    msg.prefix = Some(sender.clone());
 
-    let users = server.users.clone();
-    let channels = server.channels.clone();
-
-    let users_lock = users.lock().unwrap();
+    let users = &server.users;
 
-    for u in users_lock.iter() {
+    for u in users.iter() {
         println!("Usuarios conectados (antes del privsmg): {}", u.nickname);
     }
 
-    let channel_lock = channels.lock().unwrap();
+    let sender_nickname = sender.split('!').next().unwrap_or_default();
+    let remote_line = remote_msg_line(&msg, sender_nickname, users);
 
-    if msg.parameters[0].is_empty() {
-        let no_recipient = err_no_recpient(msg.command);
-        response_vector.push(no_recipient);
-        return Ok(response_vector);
-    }
-
-    if msg.parameters[1].is_empty() {
-        let no_text = err_no_text_tosend();
-        response_vector.push(no_text);
-        return Ok(response_vector);
-    }
+    let channels = &server.channels;
 
-    for receiver in msg.parameters[0].split(',') {
+    for receiver in targets.iter().map(String::as_str) {
         // Receiver is a channel
         if receiver.starts_with('#') || receiver.starts_with('&') {
-            if let Some(channel) = channel_lock.iter().find(|channel| channel.name == receiver) {
-                //si el canal no puede recibir mensajes externos pero el usuario esta adentro, o si el canal es moderado y el usuario puede hablar o si no hay restricciones de quien manda mensajes, se manda el mensaje
-                if channel.is_no_msg_outside() && channel.has_user(&nick)
-                    || channel.is_moderated() && channel.can_send_msg(&nick)
-                    || channel.is_not_msg_restricted()
-                {
-                    for user_name in &channel.users {
-                        if let Some(recipient) =
-                            users_lock.iter().find(|user| &user.nickname == user_name)
-                        {
-                            if &recipient.nickname != msg.prefix.as_ref().unwrap() {
-                                if recipient.socket.is_some() {
-                                    send_message_to_user(
-                                        recipient,
-                                        &msg,
-                                        &nick,
-                                        &mut response_vector,
-                                    );
-                                } else {
-                                    for connected_server in
-                                        server.connected_servers.lock().unwrap().iter()
-                                    {
-                                        println!("{} {}", connected_server.name, recipient.server);
-
-                                        if recipient.server == connected_server.name
-                                            || connected_server.is_connected_to(&recipient.server)
-                                        {
-                                            println!("Camino hacia usuario");
-                                            let message = String::from(msg.clone());
-
-                                            let _ = connected_server
-                                                .socket
-                                                .as_ref()
-                                                .unwrap()
-                                                .as_ref()
-                                                .write((message + "\n").as_bytes());
-                                            break;
-                                        }
-                                    }
-                                }
-                                // let _ = recipient.send_private_message(msg.clone());
+            if let Some(channel) = channels.get(receiver) {
+                if can_send_to_channel(channel, sender_nickname) {
+                    // Cada servidor vecino recibe el mensaje una sola vez, aunque tenga detrás
+                    // a varios miembros del canal
+                    let mut links = HashSet::new();
+                    for user_name in channel.nicknames() {
+                        if let Some(recipient) = users.get(&user_name) {
+                            if same_name(&recipient.nickname, sender_nickname) {
+                                continue;
+                            }
+                            if recipient.socket.is_some() {
+                                send_message_to_user(recipient, &msg, &nick, &mut response_vector);
+                            } else if let Some(link) = next_hop(server, &recipient.server) {
+                                links.insert(link.name.clone());
                             }
                         } else {
                             let no_such_nick_msg = error_no_such_nick(receiver.to_string());
                             response_vector.push(no_such_nick_msg)
                         }
                     }
+                    send_to_links(server, &links, received_from, &remote_line);
                 } else {
                     let not_send_to_chan = err_can_not_send_to_chan(channel.name.clone());
                     response_vector.push(not_send_to_chan);
@@ -338,28 +498,12 @@ pub fn priv_msg(
             }
         }
         // Receiver is a user
-        else if let Some(recipient) = users_lock.iter().find(|user| user.nickname == receiver) {
+        else if let Some(recipient) = users.get(receiver) {
             if recipient.socket.is_some() {
                 send_message_to_user(recipient, &msg, &nick, &mut response_vector);
-            } else {
-                for connected_server in server.connected_servers.lock().unwrap().iter() {
-                    println!("{} {}", connected_server.name, recipient.server);
-
-                    if recipient.server == connected_server.name
-                        || connected_server.is_connected_to(&recipient.server)
-                    {
-                        println!("Camino hacia usuario");
-                        let message = String::from(msg.clone());
-
-                        let _ = connected_server
-                            .socket
-                            .as_ref()
-                            .unwrap()
-                            .as_ref()
-                            .write((message + "\n").as_bytes());
-                        break;
-                    }
-                }
+            } else if let Some(link) = next_hop(server, &recipient.server) {
+                println!("Camino hacia usuario");
+                let _ = link.send_message(remote_line.clone());
             }
         } else {
             let no_such_nick_msg = error_no_such_nick(receiver.to_string());
@@ -370,6 +514,41 @@ pub fn priv_msg(
     Ok(response_vector)
 }
 
+///Evalúa si un usuario puede enviarle mensajes a un canal: si el canal no recibe mensajes
+/// externos el usuario tiene que estar adentro, y si es moderado tiene que poder hablar.
+fn can_send_to_channel(channel: &Channel, nickname: &str) -> bool {
+    channel.is_no_msg_outside() && channel.has_user(nickname)
+        || channel.is_moderated() && channel.can_send_msg(nickname)
+        || channel.is_not_msg_restricted()
+}
+
+///Arma la línea con la que un mensaje se reenvía a otros servidores, con el prefijo completo
+/// `nick!user@host` del remitente.
+fn remote_msg_line(msg: &Message, sender_nickname: &str, users: &Registry<User>) -> String {
+    let mut remote_msg = msg.clone();
+    if let Some(sender) = users.get(sender_nickname) {
+        remote_msg.prefix = Some(sender.prefix());
+    }
+    String::from(remote_msg)
+}
+
+///Envía una línea a los servidores vecinos indicados, salvo al servidor del que se recibió.
+fn send_to_links(server: &Server, links: &HashSet<String>, received_from: &str, line: &str) {
+    for connected_server in server.connected_servers.iter() {
+        if links.contains(&connected_server.name) && connected_server.name != received_from {
+            let _ = connected_server.send_message(line.to_string());
+        }
+    }
+}
+
+///Obtiene el servidor vecino a través del cual se llega al servidor indicado.
+fn next_hop<'a>(server: &'a Server, servername: &str) -> Option<&'a Server> {
+    server.connected_servers.iter().find(|connected_server| {
+        connected_server.name == servername
+            || connected_server.is_connected_to(&servername.to_string())
+    })
+}
+
 fn send_message_to_user(
     recipient: &User,
     msg: &Message,
@@ -401,46 +580,112 @@ fn autoreply(
 /// y en caso de éxito enviar un mensaje privado a un canal o a un usuario,
 /// sin recibir respuestas automáticas.
 pub fn notice_msg(
-    msg: Message,
-    users: Arc<Mutex<Vec<User>>>,
-    nick: String,
-    channels: Arc<Mutex<Vec<Channel>>>,
+    targets: Vec<String>,
+    text: String,
+    sender: String,
+    server: &Server,
+    received_from: &str,
 ) -> Result<Vec<Message>, ServerError> {
     let response_vector = Vec::new();
-    let users_lock = users.lock().unwrap();
-    let channel_lock = channels.lock().unwrap();
-    if msg.parameters[0].is_empty() {
-        return Ok(response_vector);
-    }
-
-    if msg.parameters[1].is_empty() {
-        return Ok(response_vector);
-    }
-    for receiver in msg.parameters[0].split(',') {
+    let mut msg = Message::from(Command::Notice {
+        targets: targets.clone(),
+        text,
+    });
+    msg.prefix = Some(sender.clone());
+    let users = &server.users;
+    let sender_nickname = sender.split('!').next().unwrap_or_default();
+    let remote_line = remote_msg_line(&msg, sender_nickname, users);
+    for receiver in targets.iter().map(String::as_str) {
         if receiver.starts_with('#') || receiver.starts_with('&') {
-            if let Some(channel) = channel_lock.iter().find(|channel| channel.name == receiver) {
-                //si el canal no puede recibir mensajes externos pero el usuario esta adentro, o si el canal es moderado y el usuario puede hablar o si no hay restricciones de quien manda mensajes, se manda el mensaje
-                if channel.is_no_msg_outside() && channel.has_user(&nick)
-                    || channel.is_moderated() && channel.can_send_msg(&nick)
-                    || channel.is_not_msg_restricted()
-                {
-                    for user_name in &channel.users {
-                        if let Some(recipient) =
-                            users_lock.iter().find(|user| &user.nickname == user_name)
-                        {
-                            let _ = recipient.send_private_message(msg.clone());
+            if let Some(channel) = server.channels.get(receiver) {
+                if can_send_to_channel(channel, sender_nickname) {
+                    let mut links = HashSet::new();
+                    for user_name in channel.nicknames() {
+                        if let Some(recipient) = users.get(&user_name) {
+                            if same_name(&recipient.nickname, sender_nickname) {
+                                continue;
+                            }
+                            if recipient.socket.is_some() {
+                                let _ = recipient.send_private_message(msg.clone());
+                            } else if let Some(link) = next_hop(server, &recipient.server) {
+                                links.insert(link.name.clone());
+                            }
                         }
                     }
+                    send_to_links(server, &links, received_from, &remote_line);
                 }
             }
-        } else if let Some(recipient) = users_lock.iter().find(|user| user.nickname == receiver) {
-            let _ = recipient.send_private_message(msg.clone());
+        } else if let Some(recipient) = users.get(receiver) {
+            if recipient.socket.is_some() {
+                let _ = recipient.send_private_message(msg.clone());
+            } else if let Some(link) = next_hop(server, &recipient.server) {
+                let _ = link.send_message(remote_line.clone());
+            }
         }
     }
 
     Ok(response_vector)
 }
 
+/// Se encarga de interpretar el mensaje de MODE dirigido a un usuario: sin modos informa los
+/// modos del usuario y, si no, los activa o desactiva. Cada usuario solo ve y cambia sus
+/// propios modos y no puede darse `+o`, que se obtiene con OPER; los servidores sí informan
+/// el `+o` de sus usuarios.
+pub fn user_mode_msg(
+    target: &str,
+    changes: Vec<String>,
+    nick: &str,
+    from_server: bool,
+    server: &mut Server,
+    received_from: &str,
+) -> Result<Vec<Message>, ServerError> {
+    let mut response_vector = Vec::new();
+    let Some(user) = server.users.get_mut(target) else {
+        response_vector.push(error_no_such_nick(target.to_string()));
+        return Ok(response_vector);
+    };
+    if !same_name(target, nick) {
+        response_vector.push(err_users_dont_match());
+        return Ok(response_vector);
+    }
+    if changes.is_empty() {
+        response_vector.push(rpl_umode_is(user.modes_string()));
+        return Ok(response_vector);
+    }
+    let mut applied = Vec::new();
+    let mut unknown_flag = false;
+    for change in parse_mode_changes(&changes) {
+        match (change.adding, change.mode) {
+            (true, 'o') if !from_server => {}
+            (adding, mode @ ('i' | 'w' | 's' | 'o')) => {
+                if user.modes.set(mode, adding) {
+                    applied.push(ModeChange {
+                        adding,
+                        mode,
+                        argument: None,
+                    });
+                }
+            }
+            _ => unknown_flag = true,
+        }
+    }
+    if unknown_flag {
+        response_vector.push(err_umode_unknown_flag());
+    }
+    if !applied.is_empty() {
+        let command = Command::Mode {
+            target: user.nickname.clone(),
+            changes: mode_changes_parameters(&applied),
+        };
+        let mode = user_event_msg(&user.prefix(), command);
+        if user.socket.is_some() {
+            response_vector.push(mode.clone());
+        }
+        propagate_user_msg(mode, nick, received_from, &server.connected_servers);
+    }
+    Ok(response_vector)
+}
+
 /*pub fn notice_msg() -> Result<(), ()> {
     println!("notice");
     Ok(())
@@ -448,35 +693,43 @@ pub fn notice_msg(
 /// Se encarga de interpretar el mensaje de OPER enviado a un servidor
 /// y en caso de éxito le otorga a un usuario privilegios de operador sobre la red de servidores.
 pub fn oper_msg(
-    msg: Message,
-    users: Arc<Mutex<Vec<User>>>,
-    nickname: &String,
+    name: &str,
+    password: &str,
+    users: &mut Registry<User>,
+    nickname: &str,
+    operators: &[OperatorBlock],
+    connected_servers: &[Server],
 ) -> Result<Vec<Message>, ServerError> {
     let mut response_vector = Vec::new();
 
-    if msg.parameters.is_empty() {
-        let more_params = err_need_more_params(msg.command);
-        response_vector.push(more_params);
-        println!("Incorrect number of parameters in message");
-        return Ok(response_vector);
-    }
-
-    let username = msg.parameters[0].as_str();
-    let password = msg.parameters[1].as_str();
-    if username == "admin" && password == "1234" {
-        let mut users_list = users
-            .lock()
-            .map_err(|_e| ServerError::new("Cannot filter users"))?;
-        let user = users_list
-            .iter_mut()
-            .find(|u| &u.nickname == nickname)
-            .ok_or_else(|| ServerError::new("Cannot get user"))?;
+    let user = users
+        .get_mut(nickname)
+        .ok_or_else(|| ServerError::new("Cannot get user"))?;
 
-        user.become_admin();
-        response_vector.push(rpl_you_are_oper());
-    } else {
-        println!("User or password incorrect for operator");
-        response_vector.push(err_no_oper_host());
+    match operators.iter().find(|operator| operator.name == name) {
+        Some(operator) if !operator.allows_host(&user.username, &user.connection_host()) => {
+            println!("Host not allowed for operator {}", name);
+            response_vector.push(err_no_oper_host());
+        }
+        Some(operator) if !operator.check_password(password) => {
+            println!("Password incorrect for operator {}", name);
+            response_vector.push(err_passwd_mismatch());
+        }
+        Some(operator) => {
+            user.become_oper(operator.privileges.clone());
+            response_vector.push(rpl_you_are_oper());
+            // el resto de la red se entera del nuevo operador por su modo +o
+            let command = Command::Mode {
+                target: user.nickname.clone(),
+                changes: vec!["+o".to_string()],
+            };
+            let mode = user_event_msg(&user.prefix(), command);
+            propagate_user_msg(mode, &user.nickname, "", connected_servers);
+        }
+        None => {
+            println!("Operator {} not found", name);
+            response_vector.push(err_no_oper_host());
+        }
     }
 
     Ok(response_vector)
@@ -486,21 +739,16 @@ pub fn oper_msg(
 //mismo nick en un mismo server)
 /// Se encarga de interpretar el mensaje de WHOIS enviado a un servidor
 /// y en caso de éxito brinda la información de un determinado usuario.
-pub fn whois_msg(msg: Message, users: Arc<Mutex<Vec<User>>>) -> Result<Vec<Message>, ServerError> {
+pub fn whois_msg(
+    nicknames: Vec<String>,
+    users: &Registry<User>,
+) -> Result<Vec<Message>, ServerError> {
     let mut response_vector = Vec::new();
-    let lock_users = users.lock().unwrap();
-
-    if msg.parameters.is_empty() {
-        println!("Incorrect number of parameters in message");
 
-        let more_params = err_need_more_params(msg.command);
-        response_vector.push(more_params);
-        return Ok(response_vector);
-    }
     let mut no_matches = true;
-    for user in lock_users.iter() {
-        for param in msg.parameters.clone() {
-            if user.nickname == param {
+    for user in users.iter() {
+        for param in nicknames.iter() {
+            if same_name(&user.nickname, param) {
                 no_matches = false;
                 let own_string: String = "".to_owned();
                 let aux = stringfy_user_info(own_string, user);
@@ -539,51 +787,46 @@ fn stringfy_user_info(mut own_string: String, user_requested: &User) -> String {
 //si no devuelve algùn match si lo hay
 /// Se encarga de interpretar el mensaje de WHO enviado a un servidor.
 /// Si  no recibe parámetros, se devuelve la información de todos los usuarios. En caso contrario
-/// brinda la información sobre el usuario solicitado, si este existe.
-pub fn who_msg(msg: Message, users: Arc<Mutex<Vec<User>>>) -> Result<Vec<Message>, ServerError> {
+/// brinda la información de los usuarios cuyo nick, username, host, servidor o nombre real
+/// coincidan con la máscara, que puede tener `*` y `?`.
+pub fn who_msg(
+    mask: Option<String>,
+    operators_only: bool,
+    nick: &str,
+    users: &Registry<User>,
+) -> Result<Vec<Message>, ServerError> {
     let mut response_vector = Vec::new();
-    let lock_user = users.lock().unwrap();
     let mut own_string: String = " ".to_owned();
-
-    if msg.parameters.is_empty() {
-        for user in lock_user.iter() {
-            own_string.push_str(&user.nickname);
-            own_string.push(' ');
-        }
-        println!("usuarios listados{:?}", users);
-        let who_reply = rpl_who_reply(own_string);
-        response_vector.push(who_reply);
-    } else {
-        let users_iter = lock_user.iter();
-        let mut users_to_display: Vec<&User> = vec![];
-
-        if msg.parameters.len() == 2 {
-            match msg.parameters[1].as_str() {
-                "o" => {
-                    for user in users_iter {
-                        if (user.realname == msg.parameters[0]
-                            || user.server == msg.parameters[0]
-                            || user.hostname == msg.parameters[0]
-                            || user.nickname == msg.parameters[0]
-                            || user.username == msg.parameters[0])
-                            && (user.is_admin)
-                        {
-                            users_to_display.push(user);
-                        }
-                    }
-                    response_vector = find_users(users_to_display, response_vector);
-                }
-                _ => {
-                    println!("wrong parameter");
-                }
+    // los usuarios invisibles no se listan a quienes no comparten canales con ellos
+    let is_visible = |user: &User| match users.get(nick) {
+        Some(requester) => user.is_visible_to(requester),
+        None => !user.modes.i,
+    };
+
+    match mask {
+        None => {
+            for user in users.iter().filter(|user| is_visible(user)) {
+                own_string.push_str(&user.nickname);
+                own_string.push(' ');
             }
-        } else {
-            for user in users_iter {
-                if user.username == msg.parameters[0]
-                    || user.nickname == msg.parameters[0]
-                    || user.hostname == msg.parameters[0]
-                    || user.server == msg.parameters[0]
-                    || user.realname == msg.parameters[0]
+            println!("usuarios listados{:?}", users);
+            let who_reply = rpl_who_reply(own_string);
+            response_vector.push(who_reply);
+        }
+        Some(mask) => {
+            let mut users_to_display: Vec<&User> = vec![];
+            for user in users.iter().filter(|user| is_visible(user)) {
+                let host = user.connection_host();
+                if [
+                    &user.nickname,
+                    &user.username,
+                    &host,
+                    &user.server,
+                    &user.realname,
+                ]
+                .iter()
+                .any(|field| matches_casemapped(&mask, field))
+                    && (!operators_only || user.is_admin())
                 {
                     users_to_display.push(user);
                 }
@@ -614,79 +857,105 @@ fn find_users(users_to_display: Vec<&User>, mut response_vector: Vec<Message>) -
 /// Se encarga de interpretar el mensaje de AWAY enviado a un servidor
 /// y en caso de éxito setea la respuesta automática que dará cuando alguien
 /// se contacte con ese usuario.
-pub fn away_msg(msg: Message, users: Arc<Mutex<Vec<User>>>) -> Result<Vec<Message>, ServerError> {
-    let nickname = msg.prefix.ok_or_else(|| ServerError::new("Unknown user"))?;
-    let mut away_message: Option<String> = None;
+pub fn away_msg(
+    away_message: Option<String>,
+    nickname: &String,
+    users: &mut Registry<User>,
+) -> Result<Vec<Message>, ServerError> {
     let mut response_vec = Vec::new();
-    if !msg.parameters.is_empty() {
-        away_message = Some(msg.parameters[0].to_string());
+    if away_message.is_some() {
         println!("User {nickname} is now AFK");
         response_vec.push(rpl_away());
     } else {
         println!("User {nickname} is not AFK any more");
         response_vec.push(rpl_unaway());
     }
-    let mut users_lock = users
-        .lock()
-        .map_err(|_e| ServerError::new("Cannot obtain user"))?;
-    let user = users_lock
-        .iter_mut()
-        .find(|u| u.nickname == nickname)
+    let user = users
+        .get_mut(nickname)
         .ok_or_else(|| ServerError::new("Unknown user"))?;
     user.set_away_message(away_message);
     Ok(response_vec)
 }
+/// Se encarga de interpretar el mensaje de MOTD enviado a un servidor
+/// y devuelve el mensaje del día configurado.
+pub fn motd_msg(server: &Server) -> Result<Vec<Message>, ServerError> {
+    Ok(motd_messages(server))
+}
+
+///Arma las respuestas con el mensaje del día del servidor, o ERR_NOMOTD si no tiene uno.
+pub fn motd_messages(server: &Server) -> Vec<Message> {
+    match server.config.read_motd() {
+        Some(lines) => {
+            let mut response_vector = vec![rpl_motd_start(server.name.clone())];
+            for line in lines {
+                response_vector.push(rpl_motd(line));
+            }
+            response_vector.push(rpl_end_of_motd());
+            response_vector
+        }
+        None => vec![err_no_motd()],
+    }
+}
 ///Verifica que se cumplan las condiciones de spanning tree para aceptar la conexión de
 /// un nuevo servidor.
 fn attempt_sv_connection(
-    msg: Message,
-    server: Arc<Server>,
+    host: String,
+    port: String,
+    server: &Server,
     user_nickname: String,
 ) -> Result<Vec<Message>, ServerError> {
     let mut response_vector = Vec::new();
-    let input = msg.parameters[0].clone() + " " + &msg.parameters[1];
+    let input = host + " " + &port;
 
-    let mut lock_users = server
+    let current_user = server
         .users
-        .lock()
-        .map_err(|_e| ServerError::new("Cannot obtain users list"))?;
-
-    let current_user = lock_users
-        .iter_mut()
-        .find(|user| user.nickname == *user_nickname)
+        .get(&user_nickname)
         .ok_or_else(|| ServerError::new("Cannot obtain current user"))?;
 
-    if !current_user.is_admin {
+    if !current_user.has_oper_privilege("connect") {
         let err_no_privileges = err_no_privileges();
         response_vector.push(err_no_privileges);
         return Ok(response_vector);
     }
 
-    attempt_server_conection(server.clone(), input, server.name.clone());
+    // La conexión se establece en otro hilo para no frenar al resto del servidor
+    if let Some(state) = server.state() {
+        let servername = server.name.clone();
+        thread::spawn(move || attempt_server_conection(state, input, servername));
+    }
 
     Ok(response_vector)
 }
 
 #[cfg(test)]
 mod tests_interpreter {
-    use crate::interpreter::process_message;
+    use crate::config::OperatorBlock;
+    use crate::hash::salted_sha256_hex;
+    use crate::interpreter::{oper_msg, process_link_message, process_message};
+    use crate::registry::Registry;
+    use crate::sendq::SendQueue;
     use crate::{channel::Channel, message::Message, server::Server, user::User};
-    use std::sync::{Arc, Mutex};
+    use std::io::{BufRead, BufReader};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
 
-    use super::away_msg;
+    use super::{away_msg, user_mode_msg, who_msg};
 
     #[test]
     fn test_set_away_message() {
-        let msg = Message::from(":leo AWAY :me fui al kiosco".to_string());
         let mut user = User::new(None);
         user.nickname = "leo".to_string();
-        let users = Arc::new(Mutex::new(vec![user]));
+        let mut users = Registry::from(vec![user]);
 
-        let result = away_msg(msg, users);
+        let result = away_msg(
+            Some("me fui al kiosco".to_string()),
+            &"leo".to_string(),
+            &mut users,
+        );
 
         assert!(result.is_ok());
         let vector = result.unwrap();
-        assert_eq!((&vector).len(), 1);
+        assert_eq!(vector.len(), 1);
         let message = String::from(vector[0].clone());
         assert_eq!(
             message,
@@ -696,16 +965,15 @@ mod tests_interpreter {
 
     #[test]
     fn test_unset_away_message() {
-        let msg = Message::from(":leo AWAY".to_string());
         let mut user = User::new(None);
         user.nickname = "leo".to_string();
-        let users = Arc::new(Mutex::new(vec![user]));
+        let mut users = Registry::from(vec![user]);
 
-        let result = away_msg(msg, users);
+        let result = away_msg(None, &"leo".to_string(), &mut users);
 
         assert!(result.is_ok());
         let vector = result.unwrap();
-        assert_eq!((&vector).len(), 1);
+        assert_eq!(vector.len(), 1);
         let message = String::from(vector[0].clone());
         assert_eq!(
             message,
@@ -715,11 +983,10 @@ mod tests_interpreter {
 
     #[test]
     fn test_cannot_set_away_message_if_nickname_is_not_found() {
-        let msg = Message::from(":leo AWAY".to_string());
         let user = User::new(None);
-        let users = Arc::new(Mutex::new(vec![user]));
+        let mut users = Registry::from(vec![user]);
 
-        let result = away_msg(msg, users);
+        let result = away_msg(None, &"leo".to_string(), &mut users);
 
         assert!(result.is_err());
         let error = result.unwrap_err();
@@ -732,30 +999,12 @@ mod tests_interpreter {
         user.nickname = "nick1".to_string();
         let aux_user = user.clone();
         let mut_nickname = &mut user.nickname;
-        let users = Arc::new(Mutex::new(vec![aux_user]));
+        let users = Registry::from(vec![aux_user]);
         let msg = Message::from("NICK nick2".to_string());
         let mut server = Server::new();
         server.users = users;
-        let arc_server = Arc::new(server);
-        let _ = process_message(msg, arc_server.clone(), mut_nickname);
-        let aux = arc_server.users.clone();
-        let mut found_nickname = String::new();
-        match aux.lock().map_err(|_e| ()) {
-            Ok(lock) => {
-                match lock
-                    .iter()
-                    .find(|x| x.nickname == "nick2".to_string())
-                    .ok_or_else(|| ())
-                {
-                    Ok(current_user) => {
-                        found_nickname = current_user.nickname.clone();
-                    }
-                    Err(()) => {}
-                }
-            }
-            Err(()) => {}
-        }
-        assert_eq!(found_nickname, "nick2".to_string());
+        let _ = process_message(msg, &mut server, mut_nickname);
+        assert!(server.users.contains("nick2"));
     }
     #[test]
     fn test_repeated_nick() {
@@ -766,30 +1015,372 @@ mod tests_interpreter {
         let mut user2 = User::new(None);
         user2.nickname = "nick2".to_string();
         let aux_user2 = user2.clone();
-        let users = Arc::new(Mutex::new(vec![aux_user, aux_user2]));
+        let users = Registry::from(vec![aux_user, aux_user2]);
         let msg = Message::from("NICK nick2".to_string());
         let mut server = Server::new();
         server.users = users;
-        let arc_server = Arc::new(server);
-        let _ = process_message(msg, arc_server.clone(), mut_nickname);
-        let aux = arc_server.users.clone();
-        let mut found_nickname = String::new();
-        match aux.lock().map_err(|_e| ()) {
-            Ok(lock) => {
-                match lock
-                    .iter()
-                    .find(|x| x.nickname == "nick1".to_string())
-                    .ok_or_else(|| ())
-                {
-                    Ok(_) => {
-                        found_nickname = "cant change".to_string();
-                    }
-                    Err(()) => {}
-                }
-            }
-            Err(()) => {}
+        let _ = process_message(msg, &mut server, mut_nickname);
+        assert!(server.users.contains("nick1"));
+    }
+
+    #[test]
+    fn test_nick_que_difiere_solo_en_mayusculas_esta_en_uso() {
+        let mut user = User::new(None);
+        user.nickname = "nick1".to_string();
+        let mut user2 = User::new(None);
+        user2.nickname = "nick{2}".to_string();
+        let mut server = Server::new();
+        server.users = Registry::from(vec![user, user2]);
+        let mut nickname = "nick1".to_string();
+
+        let replies = process_message(
+            Message::from("NICK NICK[2]".to_string()),
+            &mut server,
+            &mut nickname,
+        )
+        .unwrap();
+
+        assert_eq!("433", replies[0].command);
+        assert_eq!("nick1", nickname);
+        assert!(server.users.contains("NICK1"));
+    }
+
+    #[test]
+    fn test_nick_invalido_devuelve_432() {
+        let mut user = User::new(None);
+        user.nickname = "nick1".to_string();
+        let mut server = Server::new();
+        server.users = Registry::from(vec![user]);
+        let mut nickname = "nick1".to_string();
+
+        for line in ["NICK nick,2", "NICK 1nick", "NICK nicknamelargo"] {
+            let replies =
+                process_message(Message::from(line.to_string()), &mut server, &mut nickname)
+                    .unwrap();
+            assert_eq!("432", replies[0].command);
+        }
+        assert_eq!("nick1", nickname);
+    }
+
+    #[test]
+    fn test_cambio_de_nick_se_aplica_en_los_canales() {
+        let mut user = User::new(None);
+        user.nickname = "nick1".to_string();
+        user.add_channel("#canal");
+        let mut channel = Channel::new(&"#canal".to_string());
+        channel.add_user("nick1".to_string());
+        channel.add_admin("nick1".to_string());
+        let mut server = Server::new();
+        server.users = Registry::from(vec![user]);
+        server.channels = Registry::from(vec![channel]);
+        let mut nickname = "nick1".to_string();
+
+        let replies = process_message(
+            Message::from("NICK nuevo".to_string()),
+            &mut server,
+            &mut nickname,
+        )
+        .unwrap();
+
+        assert!(replies.is_empty());
+        assert_eq!("nuevo", nickname);
+        let channels = &server.channels;
+        let channel = channels.get("#canal").unwrap();
+        assert!(channel.has_user("nuevo") && !channel.has_user("nick1"));
+        assert!(channel.is_admin("nuevo"));
+    }
+
+    #[test]
+    fn test_nick_con_prefijo_de_servidor_renombra_usuario_remoto() {
+        let mut user = User::new(None);
+        user.nickname = "remoto".to_string();
+        let mut server = Server::new();
+        server.users = Registry::from(vec![user]);
+        let mut servername = String::new();
+
+        let replies = process_message(
+            Message::from(":remoto NICK renombrado".to_string()),
+            &mut server,
+            &mut servername,
+        )
+        .unwrap();
+
+        assert!(replies.is_empty());
+        let users = &server.users;
+        assert!(users.contains("renombrado"));
+        assert!(!users.contains("remoto"));
+    }
+
+    #[test]
+    fn test_join_con_prefijo_de_servidor_agrega_usuario_remoto_al_canal() {
+        let mut user = User::new(None);
+        user.nickname = "remoto".to_string();
+        let mut server = Server::new();
+        server.users = Registry::from(vec![user]);
+
+        for line in [":remoto JOIN #canal", ":remoto JOIN #canal"] {
+            let _ = process_message(
+                Message::from(line.to_string()),
+                &mut server,
+                &mut String::new(),
+            );
+        }
+
+        let channels = &server.channels;
+        assert_eq!(
+            vec!["remoto".to_string()],
+            channels.get("#canal").unwrap().nicknames()
+        );
+        let users = &server.users;
+        assert!(users.get("remoto").unwrap().is_in_channel("#canal"));
+    }
+
+    fn linked_server(name: &str) -> (Server, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let remote_side = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (socket, _) = listener.accept().unwrap();
+        let mut link = Server::new();
+        link.set_name(name.to_string());
+        link.set_send_queue(SendQueue::new(Arc::new(socket), 1024));
+        (link, remote_side)
+    }
+
+    #[test]
+    fn test_eventos_de_otro_servidor_no_se_le_devuelven() {
+        let mut user = User::new(None);
+        user.nickname = "remoto".to_string();
+        let (server2, remote2) = linked_server("server2");
+        let (server3, remote3) = linked_server("server3");
+        let mut server = Server::new();
+        server.users = Registry::from(vec![user]);
+        server.connected_servers = vec![server2, server3];
+
+        let msg = Message::from(":remoto JOIN #canal".to_string());
+        process_link_message(msg, &mut server, "server2").unwrap();
+        for link in server.connected_servers.iter() {
+            link.send_queue.as_ref().unwrap().close();
+        }
+
+        let lines = |remote: TcpStream| -> Vec<String> {
+            BufReader::new(remote)
+                .lines()
+                .map_while(Result::ok)
+                .collect()
+        };
+        assert!(lines(remote2).is_empty());
+        assert_eq!(vec![":remoto JOIN #canal"], lines(remote3));
+    }
+
+    #[test]
+    fn test_privmsg_a_canal_se_reenvia_una_vez_por_servidor_vecino() {
+        let mut sender = User::new(None);
+        sender.nickname = "juan".to_string();
+        sender.username = "juanp".to_string();
+        sender.hostname = "host1".to_string();
+        let mut channel = Channel::new(&"#canal".to_string());
+        channel.add_user("juan".to_string());
+        let mut users = vec![sender];
+        for (nickname, servername) in [("r1", "server2"), ("r2", "server2"), ("r3", "server3")] {
+            let mut user = User::new(None);
+            user.nickname = nickname.to_string();
+            user.username = "user".to_string();
+            user.hostname = "host2".to_string();
+            user.server = servername.to_string();
+            channel.add_user(nickname.to_string());
+            users.push(user);
+        }
+        let (server2, remote2) = linked_server("server2");
+        let (server3, remote3) = linked_server("server3");
+        let mut server = Server::new();
+        server.users = Registry::from(users);
+        server.channels = Registry::from(vec![channel]);
+        server.connected_servers = vec![server2, server3];
+
+        let msg = Message::from("PRIVMSG #canal :hola".to_string());
+        process_message(msg, &mut server, &mut "juan".to_string()).unwrap();
+        let msg = Message::from(":r1 PRIVMSG #canal :chau".to_string());
+        process_link_message(msg, &mut server, "server2").unwrap();
+        for link in server.connected_servers.iter() {
+            link.send_queue.as_ref().unwrap().close();
+        }
+
+        let lines = |remote: TcpStream| -> Vec<String> {
+            BufReader::new(remote)
+                .lines()
+                .map_while(Result::ok)
+                .collect()
+        };
+        assert_eq!(
+            vec![":juan!juanp@host1 PRIVMSG #canal hola"],
+            lines(remote2)
+        );
+        assert_eq!(
+            vec![
+                ":juan!juanp@host1 PRIVMSG #canal hola",
+                ":r1!user@host2 PRIVMSG #canal chau"
+            ],
+            lines(remote3)
+        );
+    }
+
+    #[test]
+    fn test_mensajes_de_un_miembro_remoto_a_canal_n_se_reenvian() {
+        let mut channel = Channel::new(&"#canal".to_string());
+        channel.mode.activate_n();
+        let mut users = Vec::new();
+        for (nickname, servername) in [("r1", "server2"), ("r2", "server2"), ("r3", "server3")] {
+            let mut user = User::new(None);
+            user.nickname = nickname.to_string();
+            user.username = "user".to_string();
+            user.hostname = "host2".to_string();
+            user.server = servername.to_string();
+            channel.add_user(nickname.to_string());
+            users.push(user);
+        }
+        let (server2, remote2) = linked_server("server2");
+        let (server3, remote3) = linked_server("server3");
+        let mut server = Server::new();
+        server.users = Registry::from(users);
+        server.channels = Registry::from(vec![channel]);
+        server.connected_servers = vec![server2, server3];
+
+        for line in [":r1 PRIVMSG #canal :hola", ":r1 NOTICE #canal :aviso"] {
+            let msg = Message::from(line.to_string());
+            process_link_message(msg, &mut server, "server2").unwrap();
+        }
+        let msg = Message::from(":r3 NOTICE #canal :chau".to_string());
+        process_link_message(msg, &mut server, "server3").unwrap();
+        for link in server.connected_servers.iter() {
+            link.send_queue.as_ref().unwrap().close();
+        }
+
+        let lines = |remote: TcpStream| -> Vec<String> {
+            BufReader::new(remote)
+                .lines()
+                .map_while(Result::ok)
+                .collect()
+        };
+        assert_eq!(vec![":r3!user@host2 NOTICE #canal chau"], lines(remote2));
+        assert_eq!(
+            vec![
+                ":r1!user@host2 PRIVMSG #canal hola",
+                ":r1!user@host2 NOTICE #canal aviso"
+            ],
+            lines(remote3)
+        );
+    }
+
+    #[test]
+    fn test_kick_y_mode_de_otro_servidor_se_aplican_sin_ser_operador_local() {
+        let mut channel = Channel::new(&"#canal".to_string());
+        let mut users = Vec::new();
+        for nickname in ["remoto", "nick1"] {
+            let mut user = User::new(None);
+            user.nickname = nickname.to_string();
+            channel.add_user(nickname.to_string());
+            users.push(user);
+        }
+        let mut server = Server::new();
+        server.users = Registry::from(users);
+        server.channels = Registry::from(vec![channel]);
+
+        for line in [":remoto MODE #canal +t", ":remoto KICK #canal nick1 :chau"] {
+            let msg = Message::from(line.to_string());
+            let replies = process_link_message(msg, &mut server, "server2").unwrap();
+            assert!(replies.is_empty());
+        }
+
+        let channel = &server.channels[0];
+        assert!(channel.mode.t);
+        assert!(!channel.has_user("nick1"));
+    }
+
+    #[test]
+    fn test_kick_a_usuario_fuera_del_canal_devuelve_441() {
+        let mut user = User::new(None);
+        user.nickname = "nick1".to_string();
+        let mut channel = Channel::new(&"#canal1".to_string());
+        channel.add_user("nick1".to_string());
+        channel.add_admin("nick1".to_string());
+        let mut server = Server::new();
+        server.users = Registry::from(vec![user]);
+        server.channels = Registry::from(vec![channel]);
+
+        let replies = process_message(
+            Message::from("KICK #canal1 nick2".to_string()),
+            &mut server,
+            &mut "nick1".to_string(),
+        )
+        .unwrap();
+
+        assert_eq!("441", replies[0].command);
+    }
+
+    #[test]
+    fn test_who_con_mascara_filtra_usuarios() {
+        let mut users = Vec::new();
+        for (nickname, realname) in [
+            ("juan", "Juan Perez"),
+            ("JuLiA", "Julia"),
+            ("pedro", "Pedro"),
+        ] {
+            let mut user = User::new(None);
+            user.nickname = nickname.to_string();
+            user.realname = realname.to_string();
+            users.push(user);
+        }
+        let users = Registry::from(users);
+
+        let replies = who_msg(Some("ju*".to_string()), false, "", &users).unwrap();
+        assert_eq!(vec![" juan JuLiA "], replies[0].parameters);
+        let replies = who_msg(Some("*perez".to_string()), false, "", &users).unwrap();
+        assert_eq!(vec![" juan "], replies[0].parameters);
+    }
+
+    #[test]
+    fn test_who_no_lista_usuarios_invisibles_fuera_de_sus_canales() {
+        let mut users = Vec::new();
+        for nickname in ["juan", "julia", "jose"] {
+            let mut user = User::new(None);
+            user.nickname = nickname.to_string();
+            users.push(user);
         }
-        assert_eq!(found_nickname, "cant change".to_string());
+        users[1].modes.i = true;
+        users[1].add_channel("#canal");
+        users[2].add_channel("#canal");
+        let users = Registry::from(users);
+
+        let replies = who_msg(Some("j*".to_string()), false, "juan", &users).unwrap();
+        assert_eq!(vec![" juan jose "], replies[0].parameters);
+        let replies = who_msg(Some("j*".to_string()), false, "jose", &users).unwrap();
+        assert_eq!(vec![" juan julia jose "], replies[0].parameters);
+    }
+
+    #[test]
+    fn test_mode_de_usuario_informa_y_cambia_sus_propios_modos() {
+        let mut user = User::new(None);
+        user.nickname = "juan".to_string();
+        let mut other = User::new(None);
+        other.nickname = "pedro".to_string();
+        let mut server = Server::new();
+        server.users = Registry::from(vec![user, other]);
+        let mode = |server: &mut Server, target: &str, changes: &[&str], from_server: bool| {
+            let changes = changes.iter().map(|change| change.to_string()).collect();
+            user_mode_msg(target, changes, "juan", from_server, server, "").unwrap()
+        };
+
+        assert_eq!("502", mode(&mut server, "pedro", &["+i"], false)[0].command);
+        assert_eq!("401", mode(&mut server, "nadie", &[], false)[0].command);
+        assert!(mode(&mut server, "juan", &["+iwo"], false).is_empty());
+        assert_eq!("501", mode(&mut server, "juan", &["+x"], false)[0].command);
+        let replies = mode(&mut server, "JUAN", &[], false);
+        assert_eq!("221", replies[0].command);
+        assert_eq!(vec!["+iw"], replies[0].parameters);
+
+        assert!(mode(&mut server, "juan", &["+o"], true).is_empty());
+        assert!(server.users[0].is_admin());
+        assert!(mode(&mut server, "juan", &["-o"], false).is_empty());
+        assert!(!server.users[0].is_admin());
     }
 
     #[test]
@@ -800,31 +1391,86 @@ mod tests_interpreter {
         user.username = "admin".to_string();
         let aux_user = user.clone();
         let mut_nickname = &mut user.nickname;
-        let users = Arc::new(Mutex::new(vec![aux_user]));
+        let users = Registry::from(vec![aux_user]);
         let msg = Message::from("OPER admin 1234".to_string());
         let mut server = Server::new();
+        server.config.operators.push(OperatorBlock {
+            name: "admin".to_string(),
+            salt: "sal".to_string(),
+            password_hash: salted_sha256_hex("sal", "1234"),
+            hosts: vec!["*@*".to_string()],
+            privileges: Vec::new(),
+        });
         server.users = users;
-        let arc_server = Arc::new(server);
-        let _ = process_message(msg, arc_server.clone(), mut_nickname);
-        let mut is_oper = false;
-        let aux = arc_server.users.clone();
-        match aux.lock().map_err(|_e| ()) {
-            Ok(lock) => {
-                match lock
-                    .iter()
-                    .find(|x| x.nickname == "nick1".to_string())
-                    .ok_or_else(|| ())
-                {
-                    Ok(current_user) => {
-                        is_oper = current_user.is_admin;
-                    }
-                    Err(()) => {}
-                }
-            }
-            Err(()) => {}
+        let _ = process_message(msg, &mut server, mut_nickname);
+        assert!(server.users.get("nick1").unwrap().is_admin());
+    }
+    fn local_user(nickname: &str) -> (User, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client_side = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (socket, _) = listener.accept().unwrap();
+        let socket = Arc::new(socket);
+        let mut user = User::new(Some(socket.clone()));
+        user.nickname = nickname.to_string();
+        user.set_send_queue(SendQueue::new(socket, 1024));
+        (user, client_side)
+    }
+
+    #[test]
+    fn test_sjoin_de_canal_mas_antiguo_anuncia_todos_los_cambios_de_modo() {
+        let (user, client) = local_user("local");
+        let mut channel = Channel::new(&"#canal".to_string());
+        channel.created_at = 200;
+        channel.add_admin("local".to_string());
+        channel.ban_masks.push("malo!*@*".to_string());
+        let mut server = Server::new();
+        server.set_name("server1".to_string());
+        server.users = Registry::from(vec![user]);
+        server.channels = Registry::from(vec![channel]);
+
+        let msg = Message::from(":server2 SJOIN 100 #canal +n :@remoto".to_string());
+        process_link_message(msg, &mut server, "server2").unwrap();
+        server.users[0].send_queue.as_ref().unwrap().close();
+
+        let modes: Vec<String> = BufReader::new(client)
+            .lines()
+            .map_while(Result::ok)
+            .filter(|line| line.contains(" MODE "))
+            .collect();
+        assert_eq!(
+            vec![":server1 MODE #canal -bo+no malo!*@* local remoto"],
+            modes
+        );
+    }
+
+    #[test]
+    fn test_sjoin_avisa_a_los_miembros_locales_el_join_de_cada_miembro_remoto() {
+        let (user, client) = local_user("local");
+        let mut channel = Channel::new(&"#canal".to_string());
+        channel.created_at = 100;
+        channel.add_user("local".to_string());
+        let mut server = Server::new();
+        server.set_name("server1".to_string());
+        server.users = Registry::from(vec![user]);
+        server.channels = Registry::from(vec![channel]);
+
+        for line in [
+            ":server2 NICK remoto 1 1700000000 rem host2 server2 + :Usuario Remoto",
+            ":server2 SJOIN 100 #canal + :remoto local",
+        ] {
+            let msg = Message::from(line.to_string());
+            process_link_message(msg, &mut server, "server2").unwrap();
         }
-        assert_eq!(is_oper, true);
+        server.users[0].send_queue.as_ref().unwrap().close();
+
+        let joins: Vec<String> = BufReader::new(client)
+            .lines()
+            .map_while(Result::ok)
+            .filter(|line| line.contains(" JOIN "))
+            .collect();
+        assert_eq!(vec![":remoto!rem@host2 JOIN #canal"], joins);
     }
+
     #[test]
     fn test_cant_make_oper_wrong_pass() {
         let mut user = User::new(None);
@@ -833,30 +1479,54 @@ mod tests_interpreter {
         user.username = "user1".to_string();
         let aux_user = user.clone();
         let mut_nickname = &mut user.nickname;
-        let users = Arc::new(Mutex::new(vec![aux_user]));
+        let users = Registry::from(vec![aux_user]);
         let msg = Message::from("OPER incorrect_password user1".to_string());
         let mut server = Server::new();
         server.users = users;
-        let arc_server = Arc::new(server);
-        let _ = process_message(msg, arc_server.clone(), mut_nickname);
-        let mut is_oper = false;
-        let aux = arc_server.users.clone();
-        match aux.lock().map_err(|_e| ()) {
-            Ok(lock) => {
-                match lock
-                    .iter()
-                    .find(|x| x.nickname == "nick1".to_string())
-                    .ok_or_else(|| ())
-                {
-                    Ok(current_user) => {
-                        is_oper = current_user.is_admin;
-                    }
-                    Err(()) => {}
-                }
-            }
-            Err(()) => {}
-        }
-        assert_eq!(is_oper, false);
+        let _ = process_message(msg, &mut server, mut_nickname);
+        assert!(!server.users.get("nick1").unwrap().is_admin());
+    }
+
+    #[test]
+    fn test_oper_with_wrong_password_returns_password_mismatch() {
+        let mut user = User::new(None);
+        user.nickname = "nick1".to_string();
+        user.username = "user1".to_string();
+        user.hostname = "localhost".to_string();
+        let mut users = Registry::from(vec![user]);
+        let operators = vec![OperatorBlock {
+            name: "admin".to_string(),
+            salt: "sal".to_string(),
+            password_hash: salted_sha256_hex("sal", "1234"),
+            hosts: vec!["*@localhost".to_string()],
+            privileges: Vec::new(),
+        }];
+
+        let result = oper_msg("admin", "4321", &mut users, "nick1", &operators, &[]).unwrap();
+
+        assert_eq!(result[0].command, "464");
+        assert!(!users[0].is_admin());
+    }
+
+    #[test]
+    fn test_oper_from_not_allowed_host_returns_no_oper_host() {
+        let mut user = User::new(None);
+        user.nickname = "nick1".to_string();
+        user.username = "user1".to_string();
+        user.hostname = "otherhost".to_string();
+        let mut users = Registry::from(vec![user]);
+        let operators = vec![OperatorBlock {
+            name: "admin".to_string(),
+            salt: "sal".to_string(),
+            password_hash: salted_sha256_hex("sal", "1234"),
+            hosts: vec!["*@localhost".to_string()],
+            privileges: Vec::new(),
+        }];
+
+        let result = oper_msg("admin", "1234", &mut users, "nick1", &operators, &[]).unwrap();
+
+        assert_eq!(result[0].command, "491");
+        assert!(!users[0].is_admin());
     }
 
     #[test]
@@ -865,29 +1535,24 @@ mod tests_interpreter {
         user.nickname = "nick_oper".to_string();
         user.password = "password1".to_string();
         user.username = "user1".to_string();
-        user.is_admin = true;
+        user.modes.o = true;
         let kicked_user = "kicked_nick".to_string();
         let aux_user = user.clone();
         let mut_nickname = &mut user.nickname;
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_admin(aux_user.clone().nickname);
         channel.add_user(kicked_user.clone());
-        let channels = Arc::new(Mutex::new(vec![channel.clone()]));
-        let users = Arc::new(Mutex::new(vec![aux_user]));
+        let channels = Registry::from(vec![channel.clone()]);
+        let users = Registry::from(vec![aux_user]);
         let msg = Message::from("KICK #canal1 kicked_nick".to_string());
         let mut server = Server::new();
         server.users = users;
         server.channels = channels;
-        let arc_server = Arc::new(server);
-        let arc_server_aux = arc_server.clone();
 
-        let _ = process_message(msg, arc_server.clone(), mut_nickname);
-        let lock = arc_server_aux.channels.lock().map_err(|_e| ()).unwrap();
-        let canal_server = lock
-            .iter()
-            .find(|canal| canal.name == "#canal1".to_string())
-            .unwrap();
-        assert_eq!(canal_server.has_user(&kicked_user), false);
+        let _ = process_message(msg, &mut server, mut_nickname);
+        let lock = &server.channels;
+        let canal_server = lock.iter().find(|canal| canal.name == "#canal1").unwrap();
+        assert!(!canal_server.has_user(&kicked_user));
     }
 
     #[test]
@@ -896,28 +1561,23 @@ mod tests_interpreter {
         user.nickname = "nick_oper".to_string();
         user.password = "password1".to_string();
         user.username = "user1".to_string();
-        user.is_admin = true;
+        user.modes.o = true;
         let kicked_user = "kicked_nick".to_string();
         let aux_user = user.clone();
         let mut_nickname = &mut user.nickname;
         let mut channel = Channel::new(&"#canal1".to_string());
         channel.add_user(aux_user.clone().nickname);
         channel.add_user(kicked_user.clone());
-        let channels = Arc::new(Mutex::new(vec![channel.clone()]));
-        let users = Arc::new(Mutex::new(vec![aux_user]));
+        let channels = Registry::from(vec![channel.clone()]);
+        let users = Registry::from(vec![aux_user]);
         let msg = Message::from("KICK #canal1 kicked_nick".to_string());
         let mut server = Server::new();
         server.users = users;
         server.channels = channels;
-        let arc_server = Arc::new(server);
-        let arc_server_aux = arc_server.clone();
-
-        let _ = process_message(msg, arc_server.clone(), mut_nickname);
-        let lock = arc_server_aux.channels.lock().map_err(|_e| ()).unwrap();
-        let canal_server = lock
-            .iter()
-            .find(|canal| canal.name == "#canal1".to_string())
-            .unwrap();
-        assert_eq!(canal_server.has_user(&kicked_user), true);
+
+        let _ = process_message(msg, &mut server, mut_nickname);
+        let lock = &server.channels;
+        let canal_server = lock.iter().find(|canal| canal.name == "#canal1").unwrap();
+        assert!(canal_server.has_user(&kicked_user));
     }
 }
@@ -0,0 +1,158 @@
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use crate::config::Limits;
+
+///Resultado de esperar la siguiente línea de una conexión.
+pub enum ReadEvent {
+    Line(String),
+    Idle,
+    Closed,
+}
+
+///Lector de líneas de un socket con timeout de lectura. A diferencia de `BufReader::lines`,
+/// conserva la parte de una línea ya recibida cuando la lectura se corta por el timeout.
+pub struct LineReader<'a> {
+    reader: BufReader<&'a TcpStream>,
+    pending: Vec<u8>,
+}
+
+impl<'a> LineReader<'a> {
+    ///Crea el lector. Las lecturas se cortan cada `poll_interval` para poder revisar el
+    /// estado de la conexión aunque el otro extremo no envíe nada.
+    pub fn new(socket: &'a TcpStream, poll_interval: Duration) -> Self {
+        let _ = socket.set_read_timeout(Some(poll_interval));
+        LineReader {
+            reader: BufReader::new(socket),
+            pending: Vec::new(),
+        }
+    }
+
+    ///Espera la siguiente línea, sin el fin de línea. Devuelve `Idle` si venció el timeout de
+    /// lectura sin completar una línea y `Closed` si la conexión se cerró o falló.
+    pub fn next_event(&mut self) -> ReadEvent {
+        match self.reader.read_until(b'\n', &mut self.pending) {
+            Ok(0) => ReadEvent::Closed,
+            Ok(_) => {
+                let line = String::from_utf8_lossy(&self.pending)
+                    .trim_end_matches(['\r', '\n'])
+                    .to_string();
+                self.pending.clear();
+                ReadEvent::Line(line)
+            }
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                ) =>
+            {
+                ReadEvent::Idle
+            }
+            Err(_) => ReadEvent::Closed,
+        }
+    }
+}
+
+///Acción que corresponde tomar con una conexión según su actividad.
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeepaliveAction {
+    Wait,
+    SendPing,
+    Timeout,
+}
+
+///Lleva el registro de actividad de una conexión para decidir cuándo enviarle PING y
+/// cuándo darla por muerta. Cualquier línea recibida cuenta como respuesta.
+pub struct Keepalive {
+    frequency: Duration,
+    timeout: Duration,
+    last_activity: Instant,
+    ping_sent: Option<Instant>,
+}
+
+impl Keepalive {
+    ///Crea el registro con los tiempos configurados en los límites del servidor.
+    pub fn new(limits: &Limits) -> Self {
+        Keepalive {
+            frequency: Duration::from_secs(limits.ping_frequency),
+            timeout: Duration::from_secs(limits.ping_timeout),
+            last_activity: Instant::now(),
+            ping_sent: None,
+        }
+    }
+
+    ///Cada cuánto conviene revisar la conexión mientras no llegan mensajes.
+    pub fn poll_interval(&self) -> Duration {
+        self.frequency.min(self.timeout)
+    }
+
+    ///Registra que se recibió un mensaje de la conexión.
+    pub fn activity(&mut self, now: Instant) {
+        self.last_activity = now;
+        self.ping_sent = None;
+    }
+
+    ///Evalúa la conexión en el instante pasado. Si corresponde enviar PING, lo registra como
+    /// enviado.
+    pub fn check(&mut self, now: Instant) -> KeepaliveAction {
+        match self.ping_sent {
+            Some(sent) if now.duration_since(sent) >= self.timeout => KeepaliveAction::Timeout,
+            Some(_) => KeepaliveAction::Wait,
+            None if now.duration_since(self.last_activity) >= self.frequency => {
+                self.ping_sent = Some(now);
+                KeepaliveAction::SendPing
+            }
+            None => KeepaliveAction::Wait,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_keepalive {
+    use std::time::{Duration, Instant};
+
+    use crate::config::Limits;
+    use crate::keepalive::{Keepalive, KeepaliveAction};
+
+    fn keepalive() -> Keepalive {
+        let limits = Limits {
+            ping_frequency: 120,
+            ping_timeout: 60,
+            ..Default::default()
+        };
+        Keepalive::new(&limits)
+    }
+
+    #[test]
+    fn test_conexion_inactiva_recibe_ping_y_sin_respuesta_vence() {
+        let mut keepalive = keepalive();
+        let start = Instant::now();
+
+        assert_eq!(KeepaliveAction::Wait, keepalive.check(start));
+        let ping_time = start + Duration::from_secs(120);
+        assert_eq!(KeepaliveAction::SendPing, keepalive.check(ping_time));
+        assert_eq!(
+            KeepaliveAction::Wait,
+            keepalive.check(ping_time + Duration::from_secs(59))
+        );
+        assert_eq!(
+            KeepaliveAction::Timeout,
+            keepalive.check(ping_time + Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn test_actividad_despues_del_ping_evita_el_timeout() {
+        let mut keepalive = keepalive();
+        let ping_time = Instant::now() + Duration::from_secs(120);
+        assert_eq!(KeepaliveAction::SendPing, keepalive.check(ping_time));
+
+        keepalive.activity(ping_time + Duration::from_secs(10));
+
+        assert_eq!(
+            KeepaliveAction::Wait,
+            keepalive.check(ping_time + Duration::from_secs(60))
+        );
+    }
+}
@@ -1,11 +1,21 @@
 pub mod channel;
 mod channel_modes;
+pub mod command;
+pub mod config;
+mod date;
+mod hash;
 pub mod interpreter;
+mod keepalive;
+mod mask;
+mod member;
 pub mod message;
 pub mod registration;
+pub mod registry;
 pub mod replies;
+mod sendq;
 pub mod server;
 mod server_errors;
 pub mod server_messages_interpreter;
-pub mod threadpool;
+pub mod state;
 pub mod user;
+mod user_modes;
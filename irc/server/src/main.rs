@@ -1,49 +1,85 @@
 mod channel;
 mod channel_modes;
+mod command;
+mod config;
+mod date;
+mod hash;
 mod interpreter;
+mod keepalive;
+mod mask;
+mod member;
 mod message;
 mod registration;
+mod registry;
 mod replies;
+mod sendq;
 pub mod server;
 mod server_errors;
 mod server_messages_interpreter;
-mod threadpool;
+mod state;
 mod user;
+mod user_modes;
 
 use std::env::args;
 use std::io::{stdin, BufRead, BufReader};
-use std::sync::Arc;
+use std::process;
 use std::thread;
 
-use ::server::server::{attempt_server_conection, run, show_spanning_tree, Server};
+use ::server::config::ServerConfig;
+use ::server::server::{attempt_server_conection, connect_configured_links, run, Server};
+use ::server::state::StateHandle;
 
 static SERVER_ARGS: usize = 3;
 
-fn main() {
-    let argv = args().collect::<Vec<String>>();
+///Obtiene la configuración a partir de los argumentos: `--config <archivo>` o `<host> <port>`.
+/// Si los argumentos o la configuración son inválidos, devuelve el error a informar.
+fn load_config(argv: &[String]) -> Result<ServerConfig, String> {
     if argv.len() != SERVER_ARGS {
-        panic!("Cantidad de argumentos inválida");
+        return Err("Uso: server --config <archivo> | server <host> <port>".to_string());
     }
-    let host = argv[1].to_owned();
-    let port = argv[2].parse::<u16>().expect("Puerto inválido");
-    let servername = host.clone() + &port.to_string();
+    if argv[1] == "--config" {
+        ServerConfig::load(&argv[2]).map_err(|error| format!("Configuración inválida: {}", error))
+    } else {
+        let port = argv[2]
+            .parse::<u16>()
+            .map_err(|_| format!("Puerto inválido: {}", argv[2]))?;
+        Ok(ServerConfig::from_address(&argv[1], port))
+    }
+}
+
+fn main() {
+    let argv = args().collect::<Vec<String>>();
+    let config = match load_config(&argv) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    };
+    let servername = config.name.clone();
 
     let stream = stdin();
     let reader = BufReader::new(stream);
 
-    let mut server = Server::new();
-    server.set_name(servername.clone());
-    let server_ref = Arc::new(server);
-    let sv_thread_ref = server_ref.clone();
+    let state = StateHandle::spawn(Server::from_config(config));
+    let sv_thread_ref = state.clone();
 
     let _ = thread::spawn(move || {
-        for line in reader.lines().flatten() {
+        connect_configured_links(sv_thread_ref.clone());
+        for line in reader.lines().map_while(Result::ok) {
             match line.as_str() {
-                "show_net()" => show_spanning_tree(&sv_thread_ref, 0),
-                _ => attempt_server_conection(sv_thread_ref.clone(), line, servername.clone()),
+                "show_net()" => sv_thread_ref.show_network(),
+                _ => {
+                    // Se acepta el nombre de un bloque link o directamente "host port"
+                    let input = match sv_thread_ref.config.find_link(&line) {
+                        Some(link) => format!("{} {}", link.host, link.port),
+                        None => line,
+                    };
+                    attempt_server_conection(sv_thread_ref.clone(), input, servername.clone())
+                }
             };
         }
     });
 
-    run(server_ref, host, port).expect("No se pudo inicializar el servidor");
+    run(state).expect("No se pudo inicializar el servidor");
 }
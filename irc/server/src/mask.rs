@@ -0,0 +1,154 @@
+use crate::registry::casefold;
+
+///Evalúa si un texto coincide con una máscara. En la máscara, `*` representa cualquier
+/// secuencia de caracteres (incluso vacía) y `?` exactamente un caracter.
+pub fn matches_mask(mask: &str, text: &str) -> bool {
+    let mask: Vec<char> = mask.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut m, mut t) = (0, 0);
+    // Última posición de '*' en la máscara y del texto en ese momento, para retroceder
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if m < mask.len() && (mask[m] == '?' || mask[m] == text[t]) {
+            m += 1;
+            t += 1;
+        } else if m < mask.len() && mask[m] == '*' {
+            backtrack = Some((m, t));
+            m += 1;
+        } else if let Some((star, star_text)) = backtrack {
+            m = star + 1;
+            t = star_text + 1;
+            backtrack = Some((star, star_text + 1));
+        } else {
+            return false;
+        }
+    }
+    mask[m..].iter().all(|c| *c == '*')
+}
+
+///Igual que `matches_mask`, pero sin distinguir mayúsculas de minúsculas según el casemapping
+/// de RFC 1459.
+pub fn matches_casemapped(mask: &str, text: &str) -> bool {
+    matches_mask(&casefold(mask), &casefold(text))
+}
+
+///Evalúa si un texto tiene comodines, es decir, si debe tratarse como máscara.
+pub fn has_wildcards(text: &str) -> bool {
+    text.contains(['*', '?'])
+}
+
+///Completa una máscara de usuario a la forma `nick!user@host`. Las partes que faltan o están
+/// vacías se reemplazan por `*`: `nick` queda `nick!*@*`, `user@host` queda `*!user@host` y un
+/// texto con puntos y sin `!` ni `@` se toma como host.
+pub fn normalize_mask(mask: &str) -> String {
+    let (nickname, user_host) = match mask.split_once('!') {
+        Some((nickname, user_host)) => (nickname, user_host),
+        None if mask.contains('@') => ("*", mask),
+        None if mask.contains('.') => ("*", mask),
+        None => (mask, ""),
+    };
+    let (username, host) = match user_host.split_once('@') {
+        Some((username, host)) => (username, host),
+        None if mask.contains('!') => (user_host, ""),
+        None => ("", user_host),
+    };
+    let or_any = |part: &str| {
+        if part.is_empty() {
+            "*".to_string()
+        } else {
+            part.to_string()
+        }
+    };
+    format!("{}!{}@{}", or_any(nickname), or_any(username), or_any(host))
+}
+
+///Evalúa si el prefijo `nick!user@host` de un usuario coincide con una máscara de usuario,
+/// que puede estar incompleta.
+pub fn matches_user_mask(mask: &str, prefix: &str) -> bool {
+    matches_casemapped(&normalize_mask(mask), prefix)
+}
+
+#[cfg(test)]
+mod tests_mask {
+    use crate::mask::{matches_casemapped, matches_mask, matches_user_mask, normalize_mask};
+
+    #[test]
+    fn test_mascara_sin_comodines_compara_igualdad() {
+        assert!(matches_mask("admin@localhost", "admin@localhost"));
+        assert!(!matches_mask("admin@localhost", "admin@otrohost"));
+    }
+
+    #[test]
+    fn test_asterisco_coincide_con_cualquier_secuencia() {
+        assert!(matches_mask("*@127.0.0.1", "admin@127.0.0.1"));
+        assert!(matches_mask("*@*.fi.uba.ar", "admin@pc1.fi.uba.ar"));
+        assert!(matches_mask("*", ""));
+        assert!(!matches_mask("*@*.fi.uba.ar", "admin@fi.uba.ar"));
+    }
+
+    #[test]
+    fn test_signo_de_pregunta_coincide_con_un_caracter() {
+        assert!(matches_mask("adm?n@*", "admin@host"));
+        assert!(!matches_mask("adm?n@*", "admn@host"));
+    }
+
+    #[test]
+    fn test_comodines_en_cualquier_posicion() {
+        for (mask, text, expected) in [
+            ("a*b*c", "abc", true),
+            ("a*b*c", "aXXbYYc", true),
+            ("a*b*c", "aXXbYY", false),
+            ("*abc", "abcabc", true),
+            ("**", "x", true),
+            ("?", "", false),
+            ("??", "ab", true),
+            ("*?", "", false),
+            ("a?c*", "abcdef", true),
+            ("*.example.org", "host.example.org", true),
+            ("*.example.org", "example.org", false),
+        ] {
+            assert_eq!(expected, matches_mask(mask, text), "{} ~ {}", mask, text);
+        }
+    }
+
+    #[test]
+    fn test_comparacion_segun_casemapping() {
+        assert!(matches_casemapped("NICK[1]*", "nick{1}abc"));
+        assert!(matches_casemapped("a|b^", "A\\B~"));
+        assert!(!matches_mask("NICK", "nick"));
+    }
+
+    #[test]
+    fn test_mascaras_incompletas_se_completan() {
+        for (mask, expected) in [
+            ("nick", "nick!*@*"),
+            ("user@host", "*!user@host"),
+            ("nick!user", "nick!user@*"),
+            ("*.example.org", "*!*@*.example.org"),
+            ("!user@", "*!user@*"),
+            ("", "*!*@*"),
+            ("bad?nick!*@*", "bad?nick!*@*"),
+        ] {
+            assert_eq!(expected, normalize_mask(mask), "{}", mask);
+        }
+    }
+
+    #[test]
+    fn test_mascaras_de_usuario() {
+        let prefix = "BadNick!juan@pc1.example.org";
+        for (mask, expected) in [
+            ("*!*@*.example.org", true),
+            ("bad?ick!*@*", true),
+            ("badnick", true),
+            ("*!juan@*", true),
+            ("juan@pc?.example.org", true),
+            ("*!*@*.example.com", false),
+            ("otro!*@*", false),
+            ("*!pedro@*", false),
+        ] {
+            assert_eq!(expected, matches_user_mask(mask, prefix), "{}", mask);
+        }
+    }
+}
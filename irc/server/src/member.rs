@@ -0,0 +1,97 @@
+use crate::date::unix_time;
+use crate::registry::Registry;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+///Estado de un miembro dentro de un canal: si es operador del canal y si tiene voz.
+pub struct MemberStatus {
+    pub op: bool,
+    pub voice: bool,
+}
+
+impl MemberStatus {
+    ///Obtiene los prefijos con los que se muestra el estado del miembro: `@` para los
+    /// operadores y `+` para quienes tienen voz.
+    pub fn prefixes(&self) -> String {
+        [(self.op, '@'), (self.voice, '+')]
+            .iter()
+            .filter(|(active, _)| *active)
+            .map(|(_, prefix)| *prefix)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+///Miembro de un canal: su nick, su estado en el canal y el momento en que se unió.
+pub struct Member {
+    pub nickname: String,
+    pub status: MemberStatus,
+    pub joined_at: u64,
+}
+
+impl Member {
+    ///Crea un miembro sin privilegios que se une al canal en este momento.
+    pub fn new(nickname: String) -> Self {
+        Self {
+            nickname,
+            status: MemberStatus::default(),
+            joined_at: unix_time(),
+        }
+    }
+}
+
+///Convierte los miembros de un canal al formato con el que se envían a otros servidores: el
+/// nick de cada miembro precedido por los prefijos de su estado (`@+nick1`, `+nick2`, `nick3`).
+pub fn members_to_string(members: &Registry<Member>) -> Vec<String> {
+    members
+        .iter()
+        .map(|member| format!("{}{}", member.status.prefixes(), member.nickname))
+        .collect()
+}
+
+///Lee los miembros de un canal enviados por otro servidor con `members_to_string`.
+pub fn members_from_string(list: &[String]) -> Registry<Member> {
+    let mut members = Registry::new();
+    for entry in list.iter().filter(|entry| !entry.is_empty()) {
+        let nickname = entry.trim_start_matches(['@', '+']);
+        let prefixes = &entry[..entry.len() - nickname.len()];
+        let mut member = Member::new(nickname.to_string());
+        member.status.op = prefixes.contains('@');
+        member.status.voice = prefixes.contains('+');
+        let _ = members.insert(member);
+    }
+    members
+}
+
+#[cfg(test)]
+mod tests_member {
+    use crate::member::{members_from_string, members_to_string, Member};
+    use crate::registry::Registry;
+
+    #[test]
+    fn test_miembros_se_serializan_con_su_estado() {
+        let mut members = Registry::new();
+        let mut operator = Member::new("nick1".to_string());
+        operator.status.op = true;
+        operator.status.voice = true;
+        let mut voiced = Member::new("nick2".to_string());
+        voiced.status.voice = true;
+        for member in [operator, voiced, Member::new("nick3".to_string())] {
+            let _ = members.insert(member);
+        }
+
+        let list = members_to_string(&members);
+        assert_eq!(vec!["@+nick1", "+nick2", "nick3"], list);
+        let members = members_from_string(&list);
+        assert_eq!(3, members.len());
+        assert!(members[0].status.op && members[0].status.voice);
+        assert!(!members[1].status.op && members[1].status.voice);
+        assert_eq!("nick3", members[2].nickname);
+    }
+
+    #[test]
+    fn test_lista_de_miembros_vacia_no_falla() {
+        assert!(members_to_string(&Registry::new()).is_empty());
+        assert!(members_from_string(&[]).is_empty());
+        assert!(members_from_string(&[String::new()]).is_empty());
+    }
+}
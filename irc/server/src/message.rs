@@ -1,4 +1,9 @@
-use std::str::Chars;
+use std::str::FromStr;
+
+///Longitud máxima de un mensaje, incluyendo el CR-LF final.
+pub static MAX_MESSAGE_LENGTH: usize = 512;
+///Cantidad máxima de parámetros de un mensaje.
+pub static MAX_PARAMETERS: usize = 15;
 
 #[derive(Debug)]
 /// Error que notifica que el mensaje tiene formato inválido.
@@ -6,6 +11,14 @@ pub struct InvalidMessageError {
     pub error_message: String,
 }
 
+impl InvalidMessageError {
+    fn new(error_message: &str) -> Self {
+        Self {
+            error_message: error_message.to_owned(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 ///Mecanismo de comunicación cliente-servidor y servidor-servidor. Consiste de cero o un prefijo,
 /// un comando y una lista de parámetros.
@@ -16,9 +29,20 @@ pub struct Message {
 }
 
 impl From<String> for Message {
-    ///Permite convertir el contenido de un string en un struct Message.
+    ///Permite convertir el contenido de un string en un struct Message, sin validar los
+    /// límites del protocolo. Se usa para las líneas que arma el propio sistema; las líneas
+    /// recibidas de un cliente deben interpretarse con `parse_line`.
     fn from(string: String) -> Message {
-        parse_line(&string)
+        split_line(&string)
+    }
+}
+
+impl FromStr for Message {
+    type Err = InvalidMessageError;
+
+    ///Interpreta una línea recibida por la red validando el formato del RFC 1459.
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        parse_line(line)
     }
 }
 
@@ -35,124 +59,122 @@ impl Message {
     fn marshal(self) -> String {
         let mut string = String::new();
         // Marshal prefix
-        if self.prefix.is_some() {
-            string = string + ":" + &self.prefix.unwrap() + " ";
+        if let Some(prefix) = self.prefix {
+            string = string + ":" + &prefix + " ";
         }
         // Marshal command
-        string = string + &self.command + " ";
+        string += &self.command;
         // Marshal parameters
         let vec_length = self.parameters.len();
-        for i in 0..vec_length {
-            let param = &self.parameters[i];
-            if param.contains(' ') {
-                // Si el parametro tiene "SPACES", debe indicarse con dos puntos
+        for (i, param) in self.parameters.iter().enumerate() {
+            string += " ";
+            if i == vec_length - 1 && needs_trailing_mark(param) {
+                // El último parámetro se indica con dos puntos si no puede leerse como una palabra
                 string += ":";
             }
-            string = string + param;
-            // Agrego un espacio si hay más palabras
-            if i != vec_length - 1 {
-                string += " ";
-            }
+            string += param;
         }
         string
     }
 }
 
-// una mensaje consiste de un prefijo (opcional), un comando, y sus parámetros.
-//usando characters.next() me voy quedando con el resto de la cadena de caracteres
+///Evalúa si un parámetro debe enviarse como trailing, es decir precedido por ':'.
+fn needs_trailing_mark(param: &str) -> bool {
+    param.is_empty() || param.contains(' ') || param.starts_with(':')
+}
+
 ///Dada una línea en forma de string, se parsea el contenido y se convierte
 /// en una estructura de tipo Message, separando el prefijo, el comando y los parámetros.
-fn parse_line(line: &str) -> Message {
+/// Devuelve error si la línea no respeta el formato del RFC 1459: largo máximo de 512 bytes,
+/// un comando de letras o de tres dígitos y hasta 15 parámetros.
+pub fn parse_line(line: &str) -> Result<Message, InvalidMessageError> {
+    let content = strip_line_ending(line);
+    if content.len() + 2 > MAX_MESSAGE_LENGTH {
+        return Err(InvalidMessageError::new("Message too long"));
+    }
+    if content.contains(['\r', '\n', '\0']) {
+        return Err(InvalidMessageError::new("Invalid character in message"));
+    }
+    let message = split_line(content);
+    if message.prefix.as_deref() == Some("") {
+        return Err(InvalidMessageError::new("Empty prefix"));
+    }
+    if !is_valid_command(&message.command) {
+        return Err(InvalidMessageError::new("Invalid command"));
+    }
+    if message.parameters.len() > MAX_PARAMETERS {
+        return Err(InvalidMessageError::new("Too many parameters"));
+    }
+    Ok(message)
+}
+
+fn strip_line_ending(line: &str) -> &str {
+    line.trim_end_matches(['\r', '\n'])
+}
+
+// Un comando es una serie de letras o un número de tres dígitos. Se aceptan además '_' para
+// los comandos propios del sistema (ej: SERVER_CONNECT).
+fn is_valid_command(command: &str) -> bool {
+    let is_word = command
+        .chars()
+        .all(|character| character.is_ascii_alphabetic() || character == '_');
+    let is_numeric = command.len() == 3 && command.chars().all(|c| c.is_ascii_digit());
+    !command.is_empty() && (is_word || is_numeric)
+}
+
+// una mensaje consiste de un prefijo (opcional), un comando, y sus parámetros.
+// Los elementos se separan con uno o más espacios.
+fn split_line(line: &str) -> Message {
+    let mut rest = strip_line_ending(line).trim_start_matches(' ');
+
+    //si empieza con prefijo
     let mut prefix = None;
-    let mut command = String::new();
-    let mut parameters = Vec::new();
-
-    let mut characters: Chars = line.chars();
-    while let Some(character) = characters.next() {
-        match character {
-            //si empieza con prefijo
-            ':' => prefix = Some(get_prefix(&mut characters)),
-
-            character => {
-                (command, parameters) = get_command_and_parameters(&mut characters, character);
-                break;
-            }
-        }
+    if let Some(stripped) = rest.strip_prefix(':') {
+        let (word, remaining) = next_word(stripped);
+        prefix = Some(word.to_string());
+        rest = remaining;
     }
+
+    //la primera palabra es el comando y el resto son los parametros
+    let (command, remaining) = next_word(rest);
+    let parameters = get_parameters(remaining);
     let message = Message {
         prefix,
-        command,
+        command: command.to_string(),
         parameters,
     };
     println!("{:?}", message);
     message
 }
 
-//un prefijo empieza con ':', lo sigue una serie de caracteres y termina cuando hay un espacio
-fn get_prefix(characters: &mut Chars) -> String {
-    let mut prefix = String::new();
-    for character in characters {
-        match character {
-            ' ' => break,
-            _ => prefix.push(character),
-        }
+///Separa la primera palabra del resto de la línea, descartando los espacios intermedios.
+fn next_word(line: &str) -> (&str, &str) {
+    match line.find(' ') {
+        Some(position) => (&line[..position], line[position..].trim_start_matches(' ')),
+        None => (line, ""),
     }
-    prefix
 }
 
-fn get_command_and_parameters(
-    characters: &mut Chars,
-    first_character: char,
-) -> (String, Vec<String>) {
-    //la primera palabra es el comando y el resto son los parametros
-    let command = get_command(characters, first_character);
-    let parameters = get_parameters(characters);
-    (command, parameters)
-}
-
-fn get_command(characters: &mut Chars, first_character: char) -> String {
-    let mut command = String::new();
-    command.push(first_character);
-    for character in characters {
-        match character {
-            ' ' => break,
-            _ => command.push(character),
-        }
-    }
-    command
-}
 ///Dada una cadena de caracteres se obtienen los parámetros de un mensaje.
-fn get_parameters(characters: &mut Chars) -> Vec<String> {
+// los parametros estan separados por espacio, salvo el último si empieza con ':', que
+// se extiende hasta el final de la línea. Un ':' en otra posición es parte del parámetro.
+fn get_parameters(mut rest: &str) -> Vec<String> {
     let mut parameters: Vec<String> = Vec::new();
-    loop {
-        let parameter = get_parameter(characters);
-        if parameter.is_empty() {
+    while !rest.is_empty() {
+        if let Some(trailing) = rest.strip_prefix(':') {
+            parameters.push(trailing.to_string());
             break;
         }
-        parameters.push(parameter);
+        let (parameter, remaining) = next_word(rest);
+        parameters.push(parameter.to_string());
+        rest = remaining;
     }
     parameters
 }
 
-// los parametros estan separados por espacio, salvo que lo preceda el caracter ':'
-fn get_parameter(characters: &mut Chars) -> String {
-    // si hay ':' se activa el flag para seguir tomando el resto de los
-    //caracteres como un mismo parametro
-    let mut exists_colon = false;
-    let mut parameter = String::new();
-    for character in characters {
-        match character {
-            ' ' if !exists_colon => break,
-            ':' => exists_colon = true,
-            _ => parameter.push(character),
-        }
-    }
-    parameter
-}
-
 #[cfg(test)]
 mod tests_message {
-    use crate::message::Message;
+    use crate::message::{parse_line, Message};
 
     #[test]
     fn test_linea_vacia_devuelve_prefijo_vacio_comando_vacio_y_parametros_vacios() {
@@ -162,9 +184,9 @@ mod tests_message {
         let command = message.command;
         let parameters = message.parameters;
 
-        assert_eq!(prefix.is_none(), true);
-        assert_eq!(command.is_empty(), true);
-        assert_eq!(parameters.is_empty(), true);
+        assert!(prefix.is_none());
+        assert!(command.is_empty());
+        assert!(parameters.is_empty());
     }
     #[test]
     fn test_linea_solo_prefijo_devuelve_prefijo_comando_vacio_y_parametros_vacios() {
@@ -176,8 +198,8 @@ mod tests_message {
         let parameters = message.parameters;
 
         assert_eq!(Some("user1".to_string()), prefix);
-        assert_eq!(command.is_empty(), true);
-        assert_eq!(parameters.is_empty(), true);
+        assert!(command.is_empty());
+        assert!(parameters.is_empty());
     }
     #[test]
     fn test_linea_solo_comando_devuelve_prefijo_vacio_comando_y_parametros_vacios() {
@@ -188,9 +210,9 @@ mod tests_message {
         let command = message.command;
         let parameters = message.parameters;
 
-        assert_eq!(prefix.is_none(), true);
+        assert!(prefix.is_none());
         assert_eq!("COMMAND", command);
-        assert_eq!(parameters.is_empty(), true);
+        assert!(parameters.is_empty());
     }
     #[test]
     fn test_linea_comando_y_un_parametro_devuelve_prefijo_vacio_comando_y_un_parametro() {
@@ -201,7 +223,7 @@ mod tests_message {
         let command = message.command;
         let parameters = message.parameters;
 
-        assert_eq!(prefix.is_none(), true);
+        assert!(prefix.is_none());
         assert_eq!("COMMAND", command);
         assert_eq!(vec!["param1"], parameters);
     }
@@ -215,7 +237,7 @@ mod tests_message {
         let command = message.command;
         let parameters = message.parameters;
 
-        assert_eq!(prefix.is_none(), true);
+        assert!(prefix.is_none());
         assert_eq!("COMMAND", command);
         assert_eq!(vec!["param1 tiene muchas palabras"], parameters);
     }
@@ -230,7 +252,7 @@ mod tests_message {
         let command = message.command;
         let parameters = message.parameters;
 
-        assert_eq!(prefix.is_none(), true);
+        assert!(prefix.is_none());
         assert_eq!("COMMAND", command);
         assert_eq!(vec!["param1", "tiene", "muchos", "parametros"], parameters);
     }
@@ -245,7 +267,7 @@ mod tests_message {
         let command = message.command;
         let parameters = message.parameters;
 
-        assert_eq!(prefix.is_none(), true);
+        assert!(prefix.is_none());
         assert_eq!("COMMAND", command);
         assert_eq!(vec!["param1", "tiene", "muchos parametros"], parameters);
     }
@@ -319,4 +341,77 @@ mod tests_message {
 
         assert_eq!("PRIVMSG Hola, cómo estás?", string);
     }
+
+    #[test]
+    fn test_dos_puntos_dentro_de_un_parametro_no_inician_trailing() {
+        let message = parse_line("PRIVMSG #canal :nos vemos a las 12:30 en http://x.com").unwrap();
+
+        assert_eq!(
+            vec!["#canal", "nos vemos a las 12:30 en http://x.com"],
+            message.parameters
+        );
+
+        let message = parse_line("PRIVMSG nick http://x.com").unwrap();
+
+        assert_eq!(vec!["nick", "http://x.com"], message.parameters);
+    }
+
+    #[test]
+    fn test_linea_con_crlf_y_espacios_repetidos() {
+        let message = parse_line(":nick1   PRIVMSG  nick2   :hola  mundo\r\n").unwrap();
+
+        assert_eq!(Some("nick1".to_string()), message.prefix);
+        assert_eq!("PRIVMSG", message.command);
+        assert_eq!(vec!["nick2", "hola  mundo"], message.parameters);
+    }
+
+    #[test]
+    fn test_trailing_vacio_es_un_parametro() {
+        let message = parse_line("TOPIC #canal :").unwrap();
+
+        assert_eq!(vec!["#canal", ""], message.parameters);
+    }
+
+    #[test]
+    fn test_linea_de_mas_de_512_bytes_es_invalida() {
+        let line = format!("PRIVMSG nick :{}", "a".repeat(500));
+
+        assert!(parse_line(&line).is_err());
+        assert!(parse_line(&line[..510]).is_ok());
+    }
+
+    #[test]
+    fn test_linea_con_mas_de_15_parametros_es_invalida() {
+        let params = vec!["p"; 16].join(" ");
+
+        assert!(parse_line(&format!("COMMAND {}", params)).is_err());
+        assert!(parse_line(&format!("COMMAND {}", &params[2..])).is_ok());
+    }
+
+    #[test]
+    fn test_comando_o_prefijo_invalido() {
+        assert!(parse_line("").is_err());
+        assert!(parse_line(":nick").is_err());
+        assert!(parse_line(": PRIVMSG nick hola").is_err());
+        assert!(parse_line("PRIV-MSG nick hola").is_err());
+        assert!(parse_line("0001 nick").is_err());
+        assert!(parse_line("001 nick :Welcome").is_ok());
+    }
+
+    #[test]
+    fn test_marshal_y_parseo_conservan_el_mensaje() {
+        let lines = [
+            ":nick1 PRIVMSG #canal :hola a todos",
+            ":nick1 PRIVMSG #canal http://x.com",
+            ":nick1 PRIVMSG #canal ::)",
+            "TOPIC #canal :",
+            "JOIN #canal1,#canal2 clave",
+        ];
+        for line in lines {
+            let message: Message = line.parse().unwrap();
+            let string: String = message.into();
+
+            assert_eq!(line, string);
+        }
+    }
 }
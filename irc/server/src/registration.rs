@@ -1,35 +1,287 @@
-use std::io::{BufRead, BufReader};
-use std::net::TcpStream;
+use std::io::Write;
+use std::net::{Shutdown, TcpStream};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::config::ServerConfig;
+use crate::date::format_date;
+use crate::interpreter::motd_messages;
+use crate::keepalive::{LineReader, ReadEvent};
 use crate::message::{InvalidMessageError, Message};
+use crate::replies::{
+    err_already_registred, err_erroneus_nickname, err_need_more_params, err_nickname_in_use,
+    err_not_registered, err_passwd_mismatch, rpl_created, rpl_isupport, rpl_my_info, rpl_welcome,
+    rpl_your_host, ReplyBuilder,
+};
 use crate::server::Server;
 use crate::server_errors::ServerError;
 use crate::user::User;
+
+///Versión del servidor informada en RPL_YOURHOST y RPL_MYINFO.
+pub static SERVER_VERSION: &str = concat!("server-", env!("CARGO_PKG_VERSION"));
+static USER_MODES: &str = "iosw";
+static CHANNEL_MODES: &str = "beIiklmnopstv";
+///Características del servidor que se informan a los clientes en RPL_ISUPPORT.
+/// NICKLEN se agrega según la configuración.
+static ISUPPORT_TOKENS: [&str; 6] = [
+    "CHANTYPES=#&",
+    "PREFIX=(ov)@+",
+    "CHANMODES=beI,k,l,imnpst",
+    "EXCEPTS=e",
+    "INVEX=I",
+    "CASEMAPPING=rfc1459",
+];
+///Caracteres especiales permitidos en un nickname, además de letras y dígitos.
+static NICKNAME_SPECIALS: &str = "[]\\`_^{|}";
+
+///Etapa de la registración en la que se encuentra una conexión entrante.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationState {
+    ///Todavía no envió NICK, USER ni SERVER.
+    Unregistered,
+    ///Envió NICK o USER, pero le faltan datos para registrarse como cliente.
+    ClientPending,
+    ///Completó la registración como cliente.
+    Client,
+    ///Completó la registración como servidor.
+    Server,
+    ///Cerró la conexión con QUIT antes de registrarse.
+    Quit,
+}
+
+///Registración de una conexión entrante. Acumula los datos que envía la conexión hasta que
+/// se registra como cliente o como servidor. Mientras tanto, el resto de los comandos reciben
+/// ERR_NOTREGISTERED, y los datos del otro tipo de conexión, ERR_ALREADYREGISTRED.
+pub struct Registration {
+    user: User,
+    server: Server,
+    state: RegistrationState,
+    socket: Option<Arc<TcpStream>>,
+}
+
+impl Registration {
+    ///Crea la registración de la conexión con el socket pasado.
+    pub fn new(socket: Option<Arc<TcpStream>>) -> Self {
+        Registration {
+            user: User::new(socket.clone()),
+            server: Server::new(),
+            state: RegistrationState::Unregistered,
+            socket,
+        }
+    }
+
+    ///Evalúa si la registración terminó, ya sea registrando la conexión o por QUIT.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.state,
+            RegistrationState::Client | RegistrationState::Server | RegistrationState::Quit
+        )
+    }
+
+    ///Procesa un mensaje recibido durante la registración y devuelve las respuestas para
+    /// la conexión.
+    pub fn handle_message(
+        &mut self,
+        message: Message,
+        nicklen: usize,
+        nick_in_use: &dyn Fn(&str) -> bool,
+    ) -> Vec<Message> {
+        let is_client_command = matches!(message.command.as_str(), "NICK" | "USER");
+        match (self.state, message.command.as_str()) {
+            (_, "QUIT") => {
+                self.state = RegistrationState::Quit;
+                return Vec::new();
+            }
+            (_, "PONG") => return Vec::new(),
+            (RegistrationState::ClientPending, "SERVER") => return vec![err_already_registred()],
+            (_, "PASS" | "NICK" | "USER" | "SERVER") => {}
+            _ => return vec![err_not_registered()],
+        }
+        if message.parameters.len() < required_parameters(&message.command) {
+            return vec![err_need_more_params(message.command)];
+        }
+        let replies = register_data_for_connection(
+            message,
+            &mut self.user,
+            &mut self.server,
+            self.socket.clone(),
+            nicklen,
+            nick_in_use,
+        )
+        .unwrap_or_else(|e| {
+            println!("Invalid registration message: {}", e.error_message);
+            Vec::new()
+        });
+        if self.user.is_registered() {
+            self.state = RegistrationState::Client;
+        } else if self.server.is_registered() {
+            self.state = RegistrationState::Server;
+        } else if is_client_command {
+            self.state = RegistrationState::ClientPending;
+        }
+        replies
+    }
+}
+
 ///Verifica que la información de registración
-///provista por la conexión entrante es correcta.
-pub fn validate_connection(socket: Arc<TcpStream>) -> Result<(User, Server), ServerError> {
-    let reader = BufReader::new(socket.as_ref());
-    let mut lines = reader.lines();
-    let mut user = User::new(Some(socket.clone()));
-    let mut server = Server::new();
-    while !user.is_registered() && !server.is_registered() {
-        if let Some(Ok(line)) = lines.next() {
-            let message = Message::from(line);
-            let _ =
-                register_data_for_connection(message, &mut user, &mut server, Some(socket.clone()))
-                    .map_err(|e| println!("Invalid registration message: {}", e.error_message));
+///provista por la conexión entrante es correcta. `nick_in_use` indica si un nickname ya
+/// pertenece a otro usuario de la red. Si la conexión se cierra, envía QUIT o no termina de
+/// registrarse en el tiempo configurado, se devuelve un error. Los servidores solo se aceptan
+/// si tienen un bloque link y enviaron su contraseña.
+pub fn validate_connection(
+    socket: Arc<TcpStream>,
+    reader: &mut LineReader,
+    config: &ServerConfig,
+    nick_in_use: &dyn Fn(&str) -> bool,
+) -> Result<(User, Server), ServerError> {
+    let deadline = Instant::now() + Duration::from_secs(config.limits.registration_timeout);
+    let mut registration = Registration::new(Some(socket.clone()));
+    while !registration.is_finished() {
+        let event = reader.next_event();
+        if Instant::now() >= deadline {
+            close_unregistered(&socket, "Registration timed out");
+            return Err(ServerError::new("La conexión no se registró a tiempo"));
+        }
+        match event {
+            ReadEvent::Line(line) => {
+                let replies = match line.parse::<Message>() {
+                    Ok(message) => {
+                        registration.handle_message(message, config.limits.nicklen, nick_in_use)
+                    }
+                    Err(e) => {
+                        println!("Invalid registration message: {}", e.error_message);
+                        Vec::new()
+                    }
+                };
+                let reply_builder = ReplyBuilder::new(&config.name, &registration.user.nickname);
+                for reply in replies {
+                    let line: String = reply_builder.build(reply).into();
+                    let _ = socket.as_ref().write((line + "\n").as_bytes());
+                }
+            }
+            ReadEvent::Idle => {}
+            ReadEvent::Closed => {
+                return Err(ServerError::new("Conexión cerrada durante la registración"))
+            }
+        }
+    }
+    if registration.state == RegistrationState::Quit {
+        close_unregistered(&socket, "Client Quit");
+        return Err(ServerError::new(
+            "La conexión envió QUIT antes de registrarse",
+        ));
+    }
+    let Registration { user, server, .. } = registration;
+    if user.is_registered() {
+        if let Err(error) = check_connection_password(&user, &config.password) {
+            reject_password(&socket, config, &user.nickname);
+            return Err(error);
+        }
+    } else if server.is_registered() {
+        let Some(link) = config.find_link(&server.name) else {
+            close_unregistered(&socket, "No link block");
+            return Err(ServerError::new(&format!(
+                "El servidor {} no tiene un bloque link",
+                server.name
+            )));
+        };
+        let password = link.password.clone().or_else(|| config.password.clone());
+        if let Err(error) = check_connection_password(&user, &password) {
+            reject_password(&socket, config, &server.name);
+            return Err(error);
         }
     }
     Ok((user, server))
 }
 
+///Le responde ERR_PASSWDMISMATCH a una conexión que envió una contraseña incorrecta y la cierra.
+fn reject_password(socket: &TcpStream, config: &ServerConfig, target: &str) {
+    let reply = ReplyBuilder::new(&config.name, target).build(err_passwd_mismatch());
+    let line: String = reply.into();
+    let mut writer = socket;
+    let _ = writer.write((line + "\n").as_bytes());
+    close_unregistered(socket, "Bad password");
+}
+
+///Cierra una conexión que no llegó a registrarse, informándole el motivo.
+fn close_unregistered(socket: &TcpStream, reason: &str) {
+    let mut socket = socket;
+    let _ = socket.write(format!("ERROR :Closing Link: {}\n", reason).as_bytes());
+    let _ = socket.shutdown(Shutdown::Both);
+}
+
+///Verifica que el usuario haya enviado la contraseña de conexión del servidor, si es que
+/// se configuró una. Sin contraseña configurada se acepta cualquier valor, incluso no enviar PASS.
+pub fn check_connection_password(
+    user: &User,
+    password: &Option<String>,
+) -> Result<(), ServerError> {
+    match password {
+        Some(password) if &user.password != password => Err(ServerError::new(&format!(
+            "Contraseña de conexión incorrecta para {}",
+            user.nickname
+        ))),
+        _ => Ok(()),
+    }
+}
+
+///Arma las respuestas que recibe un usuario al completar la registración: RPL_WELCOME,
+/// RPL_YOURHOST, RPL_CREATED, RPL_MYINFO, RPL_ISUPPORT y el mensaje del día.
+pub fn welcome_messages(user: &User, server: &Server) -> Vec<Message> {
+    let mut messages = vec![
+        rpl_welcome(
+            user.nickname.clone(),
+            user.username.clone(),
+            user.connection_host(),
+        ),
+        rpl_your_host(server.name.clone(), SERVER_VERSION.to_string()),
+        rpl_created(format_date(server.created)),
+        rpl_my_info(
+            server.name.clone(),
+            SERVER_VERSION.to_string(),
+            USER_MODES.to_string(),
+            CHANNEL_MODES.to_string(),
+        ),
+        rpl_isupport(isupport_tokens(server)),
+    ];
+    messages.extend(motd_messages(server));
+    messages
+}
+
+fn isupport_tokens(server: &Server) -> Vec<String> {
+    let mut tokens: Vec<String> = ISUPPORT_TOKENS
+        .iter()
+        .map(|token| token.to_string())
+        .collect();
+    tokens.push(format!("NICKLEN={}", server.config.limits.nicklen));
+    tokens
+}
+
+///Evalúa si un nickname respeta la gramática de RFC 1459: empieza con una letra o un
+/// caracter especial, sigue con letras, dígitos, especiales o '-', y no supera `nicklen`
+/// caracteres. Se aceptan también '_' y '|', como en RFC 2812.
+pub fn is_valid_nickname(nickname: &str, nicklen: usize) -> bool {
+    let is_special = |c: char| NICKNAME_SPECIALS.contains(c);
+    let mut chars = nickname.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || is_special(first) => {}
+        _ => return false,
+    }
+    nickname.chars().count() <= nicklen
+        && chars.all(|c| c.is_ascii_alphanumeric() || is_special(c) || c == '-')
+}
+
+///Guarda en la conexión los datos de un mensaje de registración. Devuelve las respuestas
+/// de error para la conexión, como un nickname inválido o en uso.
 fn register_data_for_connection(
     message: Message,
     user: &mut User,
     server: &mut Server,
     socket: Option<Arc<TcpStream>>,
-) -> Result<(), InvalidMessageError> {
+    nicklen: usize,
+    nick_in_use: &dyn Fn(&str) -> bool,
+) -> Result<Vec<Message>, InvalidMessageError> {
+    let mut replies = Vec::new();
     match message.command.as_str() {
         "PASS" => {
             check_params_lenght(&message, 1)?;
@@ -37,7 +289,14 @@ fn register_data_for_connection(
         }
         "NICK" => {
             check_params_lenght(&message, 1)?;
-            user.nickname = message.parameters[0].to_owned();
+            let nickname = &message.parameters[0];
+            if !is_valid_nickname(nickname, nicklen) {
+                replies.push(err_erroneus_nickname(nickname.clone()));
+            } else if nick_in_use(nickname) {
+                replies.push(err_nickname_in_use(nickname.clone()));
+            } else {
+                user.nickname = nickname.to_owned();
+            }
         }
         "USER" => {
             check_params_lenght(&message, 4)?;
@@ -47,7 +306,12 @@ fn register_data_for_connection(
             user.realname = message.parameters[3].to_owned();
         }
         "SERVER" => {
-            server.name = message.parameters[0].to_owned();
+            let Some(name) = message.parameters.first() else {
+                return Err(InvalidMessageError {
+                    error_message: "Invalid parameters length".to_owned(),
+                });
+            };
+            server.name = name.to_owned();
             server.socket = socket;
         }
         _ => {
@@ -56,14 +320,25 @@ fn register_data_for_connection(
             })
         }
     };
-    Ok(())
+    Ok(replies)
+}
+
+///Cantidad mínima de parámetros de cada comando de registración.
+fn required_parameters(command: &str) -> usize {
+    match command {
+        "USER" => 4,
+        "PASS" | "NICK" | "SERVER" => 1,
+        _ => 0,
+    }
 }
 
+///Verifica que el mensaje tenga al menos la cantidad de parámetros indicada; los que sobran
+/// se ignoran.
 fn check_params_lenght(
     message: &Message,
     expected_lenght: usize,
 ) -> Result<(), InvalidMessageError> {
-    if message.parameters.len() != expected_lenght {
+    if message.parameters.len() < expected_lenght {
         return Err(InvalidMessageError {
             error_message: "Invalid parameters length".to_owned(),
         });
@@ -75,7 +350,13 @@ fn check_params_lenght(
 mod tests_registration {
 
     use crate::{
-        message::Message, registration::register_data_for_connection, server::Server, user::User,
+        message::Message,
+        registration::{
+            check_connection_password, is_valid_nickname, register_data_for_connection,
+            welcome_messages, Registration, RegistrationState, SERVER_VERSION,
+        },
+        server::Server,
+        user::User,
     };
 
     #[test]
@@ -88,7 +369,8 @@ mod tests_registration {
             parameters: Vec::new(),
         };
 
-        let result = register_data_for_connection(message, &mut user, &mut server, None);
+        let result =
+            register_data_for_connection(message, &mut user, &mut server, None, 9, &|_| false);
 
         assert!(result.is_err());
         assert_eq!(
@@ -108,7 +390,8 @@ mod tests_registration {
             parameters: Vec::new(),
         };
 
-        let result = register_data_for_connection(message, &mut user, &mut server, None);
+        let result =
+            register_data_for_connection(message, &mut user, &mut server, None, 9, &|_| false);
 
         assert!(result.is_err());
         assert_eq!(
@@ -127,7 +410,7 @@ mod tests_registration {
             parameters: ["pass123".to_string()].to_vec(),
         };
 
-        let _ = register_data_for_connection(message, &mut user, &mut server, None);
+        let _ = register_data_for_connection(message, &mut user, &mut server, None, 9, &|_| false);
 
         assert_eq!(user.password, "pass123".to_string());
     }
@@ -139,12 +422,123 @@ mod tests_registration {
         let message = Message {
             prefix: None,
             command: "NICK".to_string(),
-            parameters: ["my_nickname".to_string()].to_vec(),
+            parameters: ["my_nick".to_string()].to_vec(),
         };
 
-        let _ = register_data_for_connection(message, &mut user, &mut server, None);
+        let _ = register_data_for_connection(message, &mut user, &mut server, None, 9, &|_| false);
+
+        assert_eq!(user.nickname, "my_nick".to_string());
+    }
+
+    #[test]
+    fn test_nick_con_hopcount_ignora_los_parametros_de_mas() {
+        let mut user = User::new(None);
+        let mut server = Server::new();
+        let message = Message::from("NICK my_nick 0".to_string());
+
+        let result =
+            register_data_for_connection(message, &mut user, &mut server, None, 9, &|_| false);
+
+        assert!(result.is_ok());
+        assert_eq!(user.nickname, "my_nick".to_string());
+    }
+
+    #[test]
+    fn test_gramatica_de_nicknames() {
+        for nickname in ["nick", "Nick_2", "[away]", "a-b|c^", "`x"] {
+            assert!(is_valid_nickname(nickname, 9), "{}", nickname);
+        }
+        for nickname in [
+            "",
+            "2nick",
+            "-nick",
+            "#canal",
+            ":nick",
+            "ni ck",
+            "a,b",
+            "nick@host",
+        ] {
+            assert!(!is_valid_nickname(nickname, 9), "{}", nickname);
+        }
+        assert!(!is_valid_nickname(&"a".repeat(10), 9));
+        assert!(is_valid_nickname(&"a".repeat(10), 10));
+    }
+
+    #[test]
+    fn test_nick_invalido_o_en_uso_no_se_registra() {
+        let mut user = User::new(None);
+        let mut server = Server::new();
+        let nick_in_use = |nickname: &str| nickname == "usado";
+
+        let invalid = Message::from("NICK #nick".to_string());
+        let replies =
+            register_data_for_connection(invalid, &mut user, &mut server, None, 9, &nick_in_use)
+                .unwrap();
+        assert_eq!("432", replies[0].command);
+
+        let taken = Message::from("NICK usado".to_string());
+        let replies =
+            register_data_for_connection(taken, &mut user, &mut server, None, 9, &nick_in_use)
+                .unwrap();
+        assert_eq!("433", replies[0].command);
+        assert!(user.nickname.is_empty());
+    }
+
+    fn registration_message(line: &str) -> Message {
+        Message::from(line.to_string())
+    }
+
+    #[test]
+    fn test_comandos_antes_de_registrarse_devuelven_451() {
+        let mut registration = Registration::new(None);
+
+        let replies =
+            registration.handle_message(registration_message("JOIN #canal"), 9, &|_| false);
 
-        assert_eq!(user.nickname, "my_nickname".to_string());
+        assert_eq!("451", replies[0].command);
+        assert_eq!(RegistrationState::Unregistered, registration.state);
+    }
+
+    #[test]
+    fn test_nick_y_user_completan_la_registracion_de_cliente() {
+        let mut registration = Registration::new(None);
+
+        registration.handle_message(registration_message("NICK nick"), 9, &|_| false);
+        assert_eq!(RegistrationState::ClientPending, registration.state);
+        let replies =
+            registration.handle_message(registration_message("SERVER sv2 1 :otro"), 9, &|_| false);
+        assert_eq!("462", replies[0].command);
+        registration.handle_message(registration_message("USER user host sv :Real"), 9, &|_| {
+            false
+        });
+
+        assert_eq!(RegistrationState::Client, registration.state);
+        assert!(registration.is_finished());
+    }
+
+    #[test]
+    fn test_registracion_con_parametros_insuficientes_devuelve_461() {
+        let mut registration = Registration::new(None);
+
+        let server_replies =
+            registration.handle_message(registration_message("SERVER"), 9, &|_| false);
+        let user_replies =
+            registration.handle_message(registration_message("USER user host"), 9, &|_| false);
+
+        assert_eq!("461", server_replies[0].command);
+        assert_eq!("461", user_replies[0].command);
+        assert_eq!(RegistrationState::Unregistered, registration.state);
+    }
+
+    #[test]
+    fn test_quit_antes_de_registrarse_termina_la_registracion() {
+        let mut registration = Registration::new(None);
+
+        registration.handle_message(registration_message("NICK nick"), 9, &|_| false);
+        registration.handle_message(registration_message("QUIT :chau"), 9, &|_| false);
+
+        assert_eq!(RegistrationState::Quit, registration.state);
+        assert!(registration.is_finished());
     }
 
     #[test]
@@ -163,11 +557,94 @@ mod tests_registration {
             .to_vec(),
         };
 
-        let _ = register_data_for_connection(message, &mut user, &mut server, None);
+        let _ = register_data_for_connection(message, &mut user, &mut server, None, 9, &|_| false);
 
         assert_eq!(user.username, "my_username".to_string());
         assert_eq!(user.hostname, "host".to_string());
         assert_eq!(user.server, "server".to_string());
         assert_eq!(user.realname, "Pablo D.".to_string());
     }
+
+    #[test]
+    fn test_usuario_sin_pass_se_registra_si_el_servidor_no_tiene_contraseña() {
+        let mut user = User::new(None);
+        user.nickname = "nick".to_string();
+        user.username = "user".to_string();
+        user.hostname = "host".to_string();
+        user.server = "server".to_string();
+        user.realname = "real".to_string();
+
+        assert!(user.is_registered());
+        assert!(check_connection_password(&user, &None).is_ok());
+    }
+
+    #[test]
+    fn test_contraseña_de_conexion_incorrecta_devuelve_error() {
+        let mut user = User::new(None);
+        user.password = "otra".to_string();
+
+        let result = check_connection_password(&user, &Some("secreta".to_string()));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_contraseña_de_conexion_correcta_es_aceptada() {
+        let mut user = User::new(None);
+        user.password = "secreta".to_string();
+
+        let result = check_connection_password(&user, &Some("secreta".to_string()));
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_bienvenida_envia_001_a_005_y_el_mensaje_del_dia() {
+        let motd_path = std::env::temp_dir().join("test_bienvenida_motd.txt");
+        std::fs::write(&motd_path, "Bienvenidos\nNo hacer spam\n").unwrap();
+        let mut server = Server::new();
+        server.set_name("sv1".to_string());
+        server.config.motd_path = Some(motd_path.to_string_lossy().to_string());
+        let mut user = User::new(None);
+        user.nickname = "nick".to_string();
+        user.username = "user".to_string();
+        user.hostname = "host".to_string();
+
+        let messages = welcome_messages(&user, &server);
+
+        let commands: Vec<&str> = messages.iter().map(|m| m.command.as_str()).collect();
+        assert_eq!(
+            vec!["001", "002", "003", "004", "005", "375", "372", "372", "376"],
+            commands
+        );
+        assert_eq!(
+            vec!["Welcome to the Internet Relay Network nick!user@host"],
+            messages[0].parameters
+        );
+        assert_eq!(
+            vec!["sv1", SERVER_VERSION, "iosw", "beIiklmnopstv"],
+            messages[3].parameters
+        );
+        for token in ["CHANMODES=beI,k,l,imnpst", "EXCEPTS=e", "INVEX=I"] {
+            assert!(messages[4].parameters.contains(&token.to_string()));
+        }
+        assert_eq!("- No hacer spam", messages[7].parameters[0]);
+        let _ = std::fs::remove_file(motd_path);
+    }
+
+    #[test]
+    fn test_bienvenida_sin_mensaje_del_dia_envia_422() {
+        let server = Server::new();
+        let mut user = User::new(None);
+        user.nickname = "nick".to_string();
+
+        let messages = welcome_messages(&user, &server);
+
+        assert_eq!(6, messages.len());
+        assert!(messages[4].parameters.contains(&"NICKLEN=9".to_string()));
+        assert!(messages[4]
+            .parameters
+            .contains(&"CASEMAPPING=rfc1459".to_string()));
+        assert_eq!("422", messages[5].command);
+    }
 }
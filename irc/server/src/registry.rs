@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+
+use crate::channel::Channel;
+use crate::member::Member;
+use crate::server_errors::ServerError;
+use crate::user::User;
+
+///Normaliza un nick o nombre de canal según el casemapping de RFC 1459: las letras se pasan a
+/// minúscula y `{}|^` se consideran equivalentes a `[]\~`.
+pub fn casefold(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '{' => '[',
+            '}' => ']',
+            '|' => '\\',
+            '^' => '~',
+            c => c.to_ascii_lowercase(),
+        })
+        .collect()
+}
+
+///Evalúa si dos nombres son iguales según el casemapping de RFC 1459.
+pub fn same_name(a: &str, b: &str) -> bool {
+    a.len() == b.len() && casefold(a) == casefold(b)
+}
+
+///Elemento que se guarda en un registro bajo su nombre.
+pub trait Named {
+    fn name(&self) -> &str;
+    fn set_name(&mut self, name: String);
+}
+
+impl Named for User {
+    fn name(&self) -> &str {
+        &self.nickname
+    }
+
+    fn set_name(&mut self, name: String) {
+        self.nickname = name;
+    }
+}
+
+impl Named for Member {
+    fn name(&self) -> &str {
+        &self.nickname
+    }
+
+    fn set_name(&mut self, name: String) {
+        self.nickname = name;
+    }
+}
+
+impl Named for Channel {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+}
+
+#[derive(Debug, Clone)]
+///Registro de usuarios o canales indexado por su nombre normalizado, por lo que `Nick` y
+/// `nick` son el mismo usuario. Cada elemento conserva el nombre con el que se registró para
+/// mostrarlo, y se recorren en el orden en que fueron agregados.
+pub struct Registry<T: Named> {
+    items: Vec<T>,
+    index: HashMap<String, usize>,
+}
+
+impl<T: Named> Registry<T> {
+    ///Crea un registro vacío.
+    pub fn new() -> Self {
+        Registry {
+            items: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    ///Busca un elemento por su nombre.
+    pub fn get(&self, name: &str) -> Option<&T> {
+        self.index
+            .get(&casefold(name))
+            .map(|position| &self.items[*position])
+    }
+
+    ///Busca un elemento por su nombre para modificarlo. Para cambiarle el nombre se debe usar
+    /// `rename`.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut T> {
+        match self.index.get(&casefold(name)) {
+            Some(position) => Some(&mut self.items[*position]),
+            None => None,
+        }
+    }
+
+    ///Evalúa si hay un elemento registrado con el nombre pasado.
+    pub fn contains(&self, name: &str) -> bool {
+        self.index.contains_key(&casefold(name))
+    }
+
+    ///Agrega un elemento. Si su nombre ya estaba registrado no lo agrega y lo devuelve.
+    pub fn insert(&mut self, item: T) -> Result<(), T> {
+        let key = casefold(item.name());
+        if self.index.contains_key(&key) {
+            return Err(item);
+        }
+        self.index.insert(key, self.items.len());
+        self.items.push(item);
+        Ok(())
+    }
+
+    ///Quita el elemento con el nombre pasado y lo devuelve.
+    pub fn remove(&mut self, name: &str) -> Option<T> {
+        let position = self.index.remove(&casefold(name))?;
+        let item = self.items.remove(position);
+        self.reindex();
+        Some(item)
+    }
+
+    ///Conserva solo los elementos que cumplen la condición.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, condition: F) {
+        self.items.retain(condition);
+        self.reindex();
+    }
+
+    ///Cambia el nombre de un elemento. Falla si no existe o si el nuevo nombre ya pertenece a
+    /// otro elemento; cambiar solo mayúsculas y minúsculas del propio nombre está permitido.
+    pub fn rename(&mut self, name: &str, new_name: &str) -> Result<(), ServerError> {
+        let key = casefold(name);
+        let new_key = casefold(new_name);
+        let position = *self
+            .index
+            .get(&key)
+            .ok_or_else(|| ServerError::new("No existe el elemento a renombrar"))?;
+        if key != new_key && self.index.contains_key(&new_key) {
+            return Err(ServerError::new("El nombre ya está registrado"));
+        }
+        self.index.remove(&key);
+        self.index.insert(new_key, position);
+        self.items[position].set_name(new_name.to_string());
+        Ok(())
+    }
+
+    ///Recorre los elementos para modificarlos. No se les debe cambiar el nombre.
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.items.iter_mut()
+    }
+
+    fn reindex(&mut self) {
+        self.index = self
+            .items
+            .iter()
+            .enumerate()
+            .map(|(position, item)| (casefold(item.name()), position))
+            .collect();
+    }
+}
+
+impl<T: Named> Default for Registry<T> {
+    fn default() -> Self {
+        Registry::new()
+    }
+}
+
+impl<T: Named> Deref for Registry<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.items
+    }
+}
+
+impl<T: Named> From<Vec<T>> for Registry<T> {
+    ///Arma un registro con los elementos pasados. Si hay nombres repetidos, queda el primero.
+    fn from(items: Vec<T>) -> Self {
+        let mut registry = Registry::new();
+        for item in items {
+            let _ = registry.insert(item);
+        }
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests_registry {
+    use crate::channel::Channel;
+    use crate::registry::{casefold, Registry};
+    use crate::user::User;
+
+    fn user(nickname: &str) -> User {
+        let mut user = User::new(None);
+        user.nickname = nickname.to_string();
+        user
+    }
+
+    #[test]
+    fn test_casefold_sigue_rfc1459() {
+        assert_eq!("nick[]\\~", casefold("NICK{}|^"));
+        assert_eq!(casefold("#Rust"), casefold("#rust"));
+    }
+
+    #[test]
+    fn test_nombres_equivalentes_no_se_registran_dos_veces() {
+        let mut users = Registry::new();
+        users.insert(user("Nick")).unwrap();
+
+        assert!(users.insert(user("nick")).is_err());
+        assert!(users.insert(user("Nick2")).is_ok());
+        assert_eq!("Nick", users.get("NICK").unwrap().nickname);
+        assert_eq!(2, users.len());
+    }
+
+    #[test]
+    fn test_busqueda_de_canales_ignora_mayusculas() {
+        let channels = Registry::from(vec![Channel::new(&"#Rust".to_string())]);
+
+        assert!(channels.contains("#rust"));
+        assert_eq!("#Rust", channels.get("#RUST").unwrap().name);
+    }
+
+    #[test]
+    fn test_renombrar_actualiza_el_indice() {
+        let mut users = Registry::from(vec![user("nick{1}"), user("nick2")]);
+
+        users.rename("NICK[1]", "Nuevo").unwrap();
+        assert!(users.rename("nuevo", "NICK2").is_err());
+        users.rename("nuevo", "NUEVO").unwrap();
+
+        assert!(!users.contains("nick{1}"));
+        assert_eq!("NUEVO", users.get("nuevo").unwrap().nickname);
+        assert_eq!("NUEVO", users[0].nickname);
+        users.remove("NUEVO");
+        assert_eq!("nick2", users.get("Nick2").unwrap().nickname);
+    }
+}
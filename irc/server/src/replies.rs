@@ -1,5 +1,38 @@
 use crate::message::Message;
 
+///Completa las respuestas numéricas con el prefijo del servidor que las envía y el nick del
+/// usuario al que van dirigidas, con la forma ":servidor NNN nick parámetros...". Las funciones
+/// de este módulo arman solamente el código y los parámetros de cada respuesta.
+pub struct ReplyBuilder {
+    servername: String,
+    nick: String,
+}
+
+impl ReplyBuilder {
+    ///Crea el builder para un destinatario. Si el usuario todavía no eligió nick se usa '*'.
+    pub fn new(servername: &str, nick: &str) -> Self {
+        let nick = if nick.is_empty() { "*" } else { nick };
+        ReplyBuilder {
+            servername: servername.to_string(),
+            nick: nick.to_string(),
+        }
+    }
+
+    ///Agrega prefijo y destinatario a una respuesta numérica. El resto de los mensajes se
+    /// devuelven sin cambios.
+    pub fn build(&self, mut reply: Message) -> Message {
+        if is_numeric(&reply.command) && reply.prefix.is_none() {
+            reply.prefix = Some(self.servername.clone());
+            reply.parameters.insert(0, self.nick.clone());
+        }
+        reply
+    }
+}
+
+fn is_numeric(command: &str) -> bool {
+    command.len() == 3 && command.chars().all(|c| c.is_ascii_digit())
+}
+
 ///Mensaje de error que informa que se necesitan más parámetros.
 pub fn err_need_more_params(command: String) -> Message {
     let line = format!("461 {} :Not enough parameters", command);
@@ -10,12 +43,15 @@ pub fn rpl_topic(channel: String, topic: String) -> Message {
     let line = format!("332 {} :{}", channel, topic);
     Message::from(line)
 }
-///Mensaje de respuesta con los miembros de un canal.
-pub fn rpl_name_rply(channel: String, nicks: Vec<String>) -> Message {
-    let mut line = format!("353 {} :", channel);
-    for nick in nicks {
-        line = line + " " + &nick;
-    }
+///Mensaje de respuesta con quién cambió el tópico de un canal y cuándo.
+pub fn rpl_topic_who_time(channel: String, set_by: String, set_at: u64) -> Message {
+    let line = format!("333 {} {} {}", channel, set_by, set_at);
+    Message::from(line)
+}
+///Mensaje de respuesta con los miembros de un canal. El tipo de canal es `=` si es público,
+/// `*` si es privado y `@` si es secreto.
+pub fn rpl_name_rply(channel_type: char, channel: String, nicks: Vec<String>) -> Message {
+    let line = format!("353 {} {} :{}", channel_type, channel, nicks.join(" "));
     Message::from(line)
 }
 ///Mensaje de fin de respuesta de NAMES.
@@ -33,14 +69,39 @@ pub fn err_not_on_channel(channel: String) -> Message {
     let line = format!("442 {} :You're not on that channel", channel);
     Message::from(line)
 }
+///Mensaje de error que informa que el usuario indicado no se encuentra en el canal.
+pub fn err_user_not_in_channel(nickname: String, channel: String) -> Message {
+    let line = format!("441 {} {} :They aren't on that channel", nickname, channel);
+    Message::from(line)
+}
+///Mensaje de respuesta con una máscara de excepción de invitación (+I) del canal.
+pub fn rpl_invite_list(channel: String, invite_mask: String) -> Message {
+    let line = format!("346 {} {}", channel, invite_mask);
+    Message::from(line)
+}
+///Mensaje de respuesta que indica el fin de la lista de excepciones de invitación.
+pub fn rpl_end_of_invite_list(channel: String) -> Message {
+    let line = format!("347 {} :End of channel invite list", channel);
+    Message::from(line)
+}
+///Mensaje de respuesta con una máscara de excepción de ban (+e) del canal.
+pub fn rpl_except_list(channel: String, exception_mask: String) -> Message {
+    let line = format!("348 {} {}", channel, exception_mask);
+    Message::from(line)
+}
+///Mensaje de respuesta que indica el fin de la lista de excepciones de ban.
+pub fn rpl_end_of_except_list(channel: String) -> Message {
+    let line = format!("349 {} :End of channel exception list", channel);
+    Message::from(line)
+}
 ///Mensaje de respuesta inicio mensaje LIST.
 pub fn rpl_list_start() -> Message {
     let line = ("321 Channel :Users  Name").to_string();
     Message::from(line)
 }
-///Mensaje de respuesta con el topic de un canal.
-pub fn rpl_list(channel: String, topic: String) -> Message {
-    let line = format!("322 {} # :{}", channel, topic);
+///Mensaje de respuesta con la cantidad de usuarios visibles y el topic de un canal.
+pub fn rpl_list(channel: String, visible_users: usize, topic: String) -> Message {
+    let line = format!("322 {} {} :{}", channel, visible_users, topic);
     Message::from(line)
 }
 ///Mensaje de respuesta fin mensaje LIST.
@@ -53,6 +114,11 @@ pub fn error_no_such_nick(nick: String) -> Message {
     let line = format!("401 {} :No such nick/channel", nick);
     Message::from(line)
 }
+///Mensaje de error que informa que no existe el servidor pasado.
+pub fn err_no_such_server(server: String) -> Message {
+    let line = format!("402 {} :No such server", server);
+    Message::from(line)
+}
 ///Mensaje de error que informa que el usuario ya se encuentra en el canal.
 pub fn err_user_on_channel(nick: String, channel: String) -> Message {
     let line = format!("443 {} {} :is already on channel", nick, channel);
@@ -78,6 +144,11 @@ pub fn err_no_text_tosend() -> Message {
     let line = ("412 :No text to send").to_string();
     Message::from(line)
 }
+///Mensaje de error que informa que a un PING o PONG le falta el origen.
+pub fn err_no_origin() -> Message {
+    let line = "409 :No origin specified".to_string();
+    Message::from(line)
+}
 ///Mensaje de error que informa que no se puede mandar el mensaje al canal.
 pub fn err_can_not_send_to_chan(channel: String) -> Message {
     let line = format!("404 {} :Cannot send to channel", channel);
@@ -88,6 +159,11 @@ pub fn err_nickname_in_use(nick: String) -> Message {
     let line = format!("433 :{} is already in use", nick);
     Message::from(line)
 }
+///Mensaje de error que informa que el nickname enviado no respeta la gramática de nicknames.
+pub fn err_erroneus_nickname(nick: String) -> Message {
+    let line = format!("432 {} :Erroneous nickname", nick);
+    Message::from(line)
+}
 ///Mensaje de error que informa que no se especificó un nickname.
 pub fn err_no_nickname_given() -> Message {
     let line = ("431 :No nickname given").to_string();
@@ -98,6 +174,16 @@ pub fn rpl_you_are_oper() -> Message {
     let line = ("381 :You are now an IRC operator").to_string();
     Message::from(line)
 }
+///Mensaje de error que informa que la contraseña es incorrecta.
+pub fn err_passwd_mismatch() -> Message {
+    let line = ("464 :Password incorrect").to_string();
+    Message::from(line)
+}
+///Mensaje de error que informa que la conexión está banneada del servidor.
+pub fn err_you_are_banned_creep() -> Message {
+    let line = ("465 :You are banned from this server").to_string();
+    Message::from(line)
+}
 ///Mensaje de error que informa que no se es operador.
 pub fn err_no_oper_host() -> Message {
     let line = ("491 :No O-lines for your host").to_string();
@@ -120,6 +206,11 @@ pub fn rpl_who_reply(usuarios: String) -> Message {
     line.push_str(&usuarios);
     Message::from(line)
 }
+///Mensaje de error que informa que el comando requiere completar la registración.
+pub fn err_not_registered() -> Message {
+    let line = "451 :You have not registered".to_string();
+    Message::from(line)
+}
 ///Mensaje de error que informa que ya se está registrado.
 pub fn err_already_registred() -> Message {
     let line = ("462 :You may not reregister").to_string();
@@ -143,6 +234,21 @@ pub fn rpl_unaway() -> Message {
     let line = "305 :You are no longer marked as being away".to_string();
     Message::from(line)
 }
+///Mensaje de respuesta con los modos de un usuario.
+pub fn rpl_umode_is(modes: String) -> Message {
+    let line = format!("221 {}", modes);
+    Message::from(line)
+}
+///Mensaje de error que informa que el modo de usuario especificado es desconocido.
+pub fn err_umode_unknown_flag() -> Message {
+    let line = "501 :Unknown MODE flag".to_string();
+    Message::from(line)
+}
+///Mensaje de error que informa que no se pueden ver ni cambiar los modos de otro usuario.
+pub fn err_users_dont_match() -> Message {
+    let line = "502 :Cant change mode for other users".to_string();
+    Message::from(line)
+}
 ///Mensaje de error que informa que el modo especificado es desconocido.
 pub fn err_unknown_mode(char: char) -> Message {
     let line = format!("472 {} :is unknown mode char to me", char);
@@ -153,6 +259,16 @@ pub fn err_channel_is_full(channel: String) -> Message {
     let line = format!("471 {} :Cannot join channel (+l)", channel);
     Message::from(line)
 }
+///Mensaje de respuesta con los modos activos de un canal y sus parámetros.
+pub fn rpl_channel_mode_is(channel: String, parameters: Vec<String>) -> Message {
+    let line = format!("324 {} {}", channel, parameters.join(" "));
+    Message::from(line)
+}
+///Mensaje de respuesta con el momento de creación de un canal.
+pub fn rpl_creation_time(channel: String, created_at: u64) -> Message {
+    let line = format!("329 {} {}", channel, created_at);
+    Message::from(line)
+}
 ///Mensaje de respuesta con las ban masks.
 pub fn rpl_banlist(channel: String, ban_mask: String) -> Message {
     let line = format!("367 {} {}", channel, ban_mask);
@@ -165,7 +281,7 @@ pub fn rpl_end_of_ban_list(channel: String) -> Message {
 }
 ///Mensaje de error que informa que ya se estableció una contraseña.
 pub fn err_key_set(channel: String) -> Message {
-    let line = format!("467 {} ::Channel key already set", channel);
+    let line = format!("467 {} :Channel key already set", channel);
     Message::from(line)
 }
 ///Mensaje de error que informa que no se puede ingresar al canal porque es invite only.
@@ -189,3 +305,93 @@ pub fn err_no_privileges() -> Message {
     let line = ("481 :Permission Denied- You're not an IRC operator").to_string();
     Message::from(line)
 }
+///Mensaje de bienvenida a la red, primera respuesta luego de la registración.
+pub fn rpl_welcome(nick: String, username: String, host: String) -> Message {
+    let line = format!(
+        "001 :Welcome to the Internet Relay Network {}!{}@{}",
+        nick, username, host
+    );
+    Message::from(line)
+}
+///Mensaje de respuesta con el nombre y la versión del servidor.
+pub fn rpl_your_host(servername: String, version: String) -> Message {
+    let line = format!(
+        "002 :Your host is {}, running version {}",
+        servername, version
+    );
+    Message::from(line)
+}
+///Mensaje de respuesta con la fecha de inicio del servidor.
+pub fn rpl_created(date: String) -> Message {
+    let line = format!("003 :This server was created {}", date);
+    Message::from(line)
+}
+///Mensaje de respuesta con la versión y los modos de usuario y de canal disponibles.
+pub fn rpl_my_info(
+    servername: String,
+    version: String,
+    user_modes: String,
+    channel_modes: String,
+) -> Message {
+    let line = format!(
+        "004 {} {} {} {}",
+        servername, version, user_modes, channel_modes
+    );
+    Message::from(line)
+}
+///Mensaje de respuesta con las características soportadas por el servidor (ISUPPORT).
+pub fn rpl_isupport(tokens: Vec<String>) -> Message {
+    let line = format!("005 {} :are supported by this server", tokens.join(" "));
+    Message::from(line)
+}
+///Mensaje de inicio del mensaje del día.
+pub fn rpl_motd_start(servername: String) -> Message {
+    let line = format!("375 :- {} Message of the day - ", servername);
+    Message::from(line)
+}
+///Mensaje con una línea del mensaje del día.
+pub fn rpl_motd(text: String) -> Message {
+    let line = format!("372 :- {}", text);
+    Message::from(line)
+}
+///Mensaje de fin del mensaje del día.
+pub fn rpl_end_of_motd() -> Message {
+    let line = "376 :End of /MOTD command".to_string();
+    Message::from(line)
+}
+///Mensaje de error que informa que el servidor no tiene mensaje del día.
+pub fn err_no_motd() -> Message {
+    let line = "422 :MOTD File is missing".to_string();
+    Message::from(line)
+}
+
+#[cfg(test)]
+mod tests_replies {
+    use crate::message::Message;
+    use crate::replies::{err_no_motd, rpl_list, ReplyBuilder};
+
+    #[test]
+    fn test_respuesta_numerica_lleva_prefijo_del_servidor_y_nick() {
+        let builder = ReplyBuilder::new("sv1", "nick1");
+        let reply = builder.build(rpl_list("#canal".to_string(), 3, "topic".to_string()));
+        assert_eq!(Some("sv1".to_string()), reply.prefix);
+        assert_eq!("322", reply.command);
+        assert_eq!(vec!["nick1", "#canal", "3", "topic"], reply.parameters);
+    }
+
+    #[test]
+    fn test_respuesta_a_usuario_sin_nick_usa_asterisco() {
+        let builder = ReplyBuilder::new("sv1", "");
+        let line: String = builder.build(err_no_motd()).into();
+        assert_eq!(":sv1 422 * :MOTD File is missing", line);
+    }
+
+    #[test]
+    fn test_mensaje_no_numerico_no_se_modifica() {
+        let builder = ReplyBuilder::new("sv1", "nick1");
+        let msg = Message::from(":nick2 PRIVMSG nick1 :hola".to_string());
+        let reply = builder.build(msg);
+        assert_eq!(Some("nick2".to_string()), reply.prefix);
+        assert_eq!(vec!["nick1", "hola"], reply.parameters);
+    }
+}
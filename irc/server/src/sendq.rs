@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+#[derive(Debug, Default)]
+struct QueueState {
+    lines: VecDeque<String>,
+    queued_bytes: usize,
+    closed: bool,
+    exceeded: bool,
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<QueueState>,
+    available: Condvar,
+    socket: Arc<TcpStream>,
+    limit: usize,
+}
+
+#[derive(Debug, Clone)]
+///Cola de salida de una conexión. Quien envía un mensaje solo lo encola, y un hilo propio de
+/// la conexión lo escribe en el socket, por lo que un lector lento no frena al resto del
+/// servidor. Si los bytes pendientes superan el límite (SendQ) la conexión se cierra.
+pub struct SendQueue {
+    shared: Arc<Shared>,
+}
+
+impl SendQueue {
+    ///Crea la cola del socket pasado y arranca el hilo que la vacía.
+    pub fn new(socket: Arc<TcpStream>, limit: usize) -> SendQueue {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(QueueState::default()),
+            available: Condvar::new(),
+            socket,
+            limit,
+        });
+        let writer_ref = shared.clone();
+        thread::spawn(move || drain(writer_ref));
+        SendQueue { shared }
+    }
+
+    ///Encola una línea, que ya debe incluir el fin de línea. Si con ella se supera el SendQ,
+    /// descarta lo pendiente y cierra la conexión.
+    pub fn push(&self, line: String) -> Result<usize, Error> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.closed {
+            return Err(Error::new(ErrorKind::NotConnected, "Conexión cerrada"));
+        }
+        if state.queued_bytes + line.len() > self.shared.limit {
+            state.exceeded = true;
+            state.closed = true;
+            state.lines.clear();
+            state.queued_bytes = 0;
+            self.shared.available.notify_all();
+            let _ = self.shared.socket.shutdown(Shutdown::Both);
+            return Err(Error::other("Max SendQ exceeded"));
+        }
+        let length = line.len();
+        state.queued_bytes += length;
+        state.lines.push_back(line);
+        self.shared.available.notify_one();
+        Ok(length)
+    }
+
+    ///Deja de aceptar líneas. Las que ya estaban encoladas se envían y después se cierra
+    /// la conexión.
+    pub fn close(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.closed = true;
+        self.shared.available.notify_all();
+    }
+
+    ///Evalúa si la conexión se cerró por superar el SendQ.
+    pub fn is_exceeded(&self) -> bool {
+        self.shared.state.lock().unwrap().exceeded
+    }
+}
+
+///Escribe en el socket las líneas encoladas hasta que la cola se cierra o falla la escritura.
+fn drain(shared: Arc<Shared>) {
+    loop {
+        let line = {
+            let mut state = shared.state.lock().unwrap();
+            loop {
+                if let Some(line) = state.lines.pop_front() {
+                    state.queued_bytes -= line.len();
+                    break Some(line);
+                }
+                if state.closed {
+                    break None;
+                }
+                state = shared.available.wait(state).unwrap();
+            }
+        };
+        match line {
+            Some(line) if shared.socket.as_ref().write_all(line.as_bytes()).is_ok() => {}
+            _ => break,
+        }
+    }
+    shared.state.lock().unwrap().closed = true;
+    let _ = shared.socket.shutdown(Shutdown::Both);
+}
+
+#[cfg(test)]
+mod tests_sendq {
+    use std::io::{BufRead, BufReader};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+
+    use crate::sendq::SendQueue;
+
+    fn connected_pair() -> (Arc<TcpStream>, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server_side, _) = listener.accept().unwrap();
+        (Arc::new(server_side), client)
+    }
+
+    #[test]
+    fn test_las_lineas_encoladas_se_envian_en_orden_antes_de_cerrar() {
+        let (socket, client) = connected_pair();
+        let queue = SendQueue::new(socket, 1024);
+
+        queue.push("PING sv1\n".to_string()).unwrap();
+        queue.push(":sv1 PONG sv1 :sv1\n".to_string()).unwrap();
+        queue.close();
+
+        let lines: Vec<String> = BufReader::new(client)
+            .lines()
+            .map_while(Result::ok)
+            .collect();
+        assert_eq!(vec!["PING sv1", ":sv1 PONG sv1 :sv1"], lines);
+        assert!(queue.push("PING sv1\n".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_superar_el_sendq_cierra_la_conexion() {
+        let (socket, _client) = connected_pair();
+        let queue = SendQueue::new(socket, 10);
+
+        let result = queue.push("a".repeat(11));
+
+        assert!(result.is_err());
+        assert!(queue.is_exceeded());
+    }
+}
@@ -1,28 +1,42 @@
-use crate::interpreter::process_message;
+use crate::command::Command;
+use crate::config::{Limits, ServerConfig};
+use crate::date::unix_time;
+use crate::interpreter::quit_msg;
+use crate::keepalive::{Keepalive, KeepaliveAction, LineReader, ReadEvent};
 use crate::message::Message;
-use crate::registration::validate_connection;
-use crate::replies::err_already_registred;
-use crate::threadpool::ThreadPool;
+use crate::registration::{validate_connection, welcome_messages};
+use crate::registry::Registry;
+use crate::replies::{
+    err_already_registred, err_nickname_in_use, err_you_are_banned_creep, ReplyBuilder,
+};
+use crate::sendq::SendQueue;
+use crate::server_errors::ServerError;
+use crate::state::StateHandle;
 use crate::user::User;
 
-use crate::channel::Channel;
-use std::io::Write;
-use std::net::TcpStream;
-use std::sync::{Arc, Mutex};
-use std::{
-    io::{BufRead, BufReader},
-    net::TcpListener,
-};
+use crate::channel::{channel_burst, user_event_msg, Channel};
+use std::io::{Error, ErrorKind, Write};
+use std::net::TcpListener;
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::{process, thread};
 ///Es la representación de un servidor de un sistema de Internet Relay Chat.
 /// Permite alojar usuarios y canales y además es posible conectarse a otros servidores,
 /// generando una red con topología spanning tree.
+/// El servidor local pertenece al hilo dueño del estado de la red, que es el único que
+/// accede a sus listas.
 pub struct Server {
     pub name: String,
-    pub users: Arc<Mutex<Vec<User>>>,
-    pub channels: Arc<Mutex<Vec<Channel>>>,
-    pub connected_servers: Arc<Mutex<Vec<Server>>>,
+    pub users: Registry<User>,
+    pub channels: Registry<Channel>,
+    pub connected_servers: Vec<Server>,
     pub socket: Option<Arc<TcpStream>>,
+    pub send_queue: Option<SendQueue>,
+    pub config: ServerConfig,
+    pub created: u64,
+    state: Option<StateHandle>,
 }
 impl Default for Server {
     fn default() -> Self {
@@ -33,27 +47,45 @@ impl Server {
     ///Crea un nuevo servidor, inicializando sus atributos.
     pub fn new() -> Server {
         let name = String::new();
-        let users = Arc::new(Mutex::new(Vec::new()));
-        let channels = Arc::new(Mutex::new(Vec::new()));
-        let connected_servers = Arc::new(Mutex::new(Vec::new()));
+        let users = Registry::new();
+        let channels = Registry::new();
+        let connected_servers = Vec::new();
         let socket = None;
+        let send_queue = None;
+        let config = ServerConfig::default();
         Server {
             name,
             users,
             channels,
             connected_servers,
             socket,
+            send_queue,
+            config,
+            created: unix_time(),
+            state: None,
         }
     }
 
+    ///Devuelve el acceso al hilo dueño del estado del servidor, si ya fue arrancado.
+    pub fn state(&self) -> Option<StateHandle> {
+        self.state.clone()
+    }
+
+    ///Crea el servidor local a partir de su configuración.
+    pub fn from_config(config: ServerConfig) -> Server {
+        let mut server = Server::new();
+        server.set_name(config.name.clone());
+        server.config = config;
+        server
+    }
+
     /// Evalúa si el servidor ya ha pasado por la etapa de registración.
     pub fn is_registered(&self) -> bool {
         !self.name.is_empty() && self.socket.is_some()
     }
     /// Evalúa si existe un camino entre el servidor y otro cuyo nombre es pasado por parámetro.
     pub fn is_connected_to(&self, servername: &String) -> bool {
-        let connected_servers_lock = self.connected_servers.lock().unwrap();
-        for connected_server in connected_servers_lock.iter() {
+        for connected_server in self.connected_servers.iter() {
             if &connected_server.name == servername {
                 return true;
             }
@@ -66,32 +98,24 @@ impl Server {
         false
     }
     /// Obtiene todos los servidores a los que se puede acceder desde el servidor dado.
-    pub fn get_connected_servers_from(
-        &self,
-        servername: String,
-    ) -> Option<Arc<Mutex<Vec<Server>>>> {
-        let servers_lock = self.connected_servers.lock().unwrap();
-        let opt_server = servers_lock
-            .iter()
-            .find(|con_ser| con_ser.name == servername);
-        if let Some(found_server) = opt_server {
-            Some(found_server.connected_servers.clone())
-        } else {
-            for server in servers_lock.iter() {
-                let servers = server.get_connected_servers_from(servername.clone());
-                if servers.is_some() {
-                    return servers;
-                }
+    pub fn get_connected_servers_from(&mut self, servername: String) -> Option<&mut Vec<Server>> {
+        for server in self.connected_servers.iter_mut() {
+            if server.name == servername {
+                return Some(&mut server.connected_servers);
+            }
+            if let Some(servers) = server.get_connected_servers_from(servername.clone()) {
+                return Some(servers);
             }
-            None
         }
+        None
     }
 
-    ///Se utiliza para enviarle un mensaje en forma de String a un servidor.
+    ///Se utiliza para enviarle un mensaje en forma de String a un servidor. El mensaje se
+    /// encola en la cola de salida del vínculo.
     pub fn send_message(&self, msg: String) -> Result<usize, std::io::Error> {
-        if let Some(s) = &self.socket {
+        if let Some(queue) = &self.send_queue {
             let content: String = msg;
-            s.as_ref().write((content + "\n").as_bytes())
+            queue.push(content + "\n")
         } else {
             Ok(0)
         }
@@ -100,10 +124,18 @@ impl Server {
     pub fn set_socket(&mut self, socket: Arc<TcpStream>) {
         self.socket = Some(socket);
     }
+    ///Configura la cola de salida del vínculo con el servidor.
+    pub fn set_send_queue(&mut self, send_queue: SendQueue) {
+        self.send_queue = Some(send_queue);
+    }
     ///Configura el nombre del servidor.
     pub fn set_name(&mut self, sv_name: String) {
         self.name = sv_name;
     }
+    ///Configura el acceso al hilo dueño del estado del servidor.
+    pub fn set_state(&mut self, state: StateHandle) {
+        self.state = Some(state);
+    }
 
     pub fn shutdown(&self) {
         println!("Server shutting down...");
@@ -111,152 +143,316 @@ impl Server {
     }
 }
 
-///Atiende los mensajes de las conexiones recibidas por un servidor.
-pub fn handle_client(server: Arc<Server>, socket: Arc<TcpStream>) -> std::io::Result<()> {
-    let reader = BufReader::new(socket.as_ref());
-    let lines = reader.lines();
+///Conexión que completó la registración: un cliente con su nick o un servidor con su nombre,
+/// junto con la cola de salida de la conexión.
+enum Connection {
+    Client(String, SendQueue),
+    Server(String, SendQueue),
+}
 
-    let user_nickname = register_connection(socket.clone(), server.clone());
-    if let Some(aux_nickname) = user_nickname {
-        process_client_messages(lines, server, aux_nickname, socket.clone());
-    } else {
-        process_server_messages(lines, server);
+///Atiende los mensajes de las conexiones recibidas por un servidor.
+pub fn handle_client(state: StateHandle, socket: Arc<TcpStream>) -> std::io::Result<()> {
+    let mut reader = LineReader::new(socket.as_ref(), poll_interval(&state.config.limits));
+    match register_connection(socket.clone(), &mut reader, &state) {
+        Ok(Connection::Client(aux_nickname, queue)) => {
+            process_client_messages(state, aux_nickname, socket.clone(), &mut reader, queue)
+        }
+        Ok(Connection::Server(servername, queue)) => {
+            process_server_messages(state, servername, &mut reader, queue)
+        }
+        Err(error) => println!("Registración rechazada: {}", error),
     }
 
     println!("Connection closed");
     Ok(())
 }
+///Cada cuánto se revisa una conexión que no envía nada, tanto para el vencimiento de la
+/// registración como para el keepalive.
+fn poll_interval(limits: &Limits) -> Duration {
+    Keepalive::new(limits)
+        .poll_interval()
+        .min(Duration::from_secs(limits.registration_timeout))
+}
+///Lee las líneas de una conexión registrada hasta que se cierra o deja de responder. A las
+/// conexiones inactivas se les envía PING y, si no responden a tiempo, se las da por muertas.
+/// Devuelve el motivo por el que terminó la conexión.
+fn read_connection_lines<F: FnMut(String)>(
+    state: &StateHandle,
+    reader: &mut LineReader,
+    queue: &SendQueue,
+    mut handle_line: F,
+) -> &'static str {
+    let mut keepalive = Keepalive::new(&state.config.limits);
+    loop {
+        match reader.next_event() {
+            ReadEvent::Line(line) => {
+                keepalive.activity(Instant::now());
+                handle_line(line);
+            }
+            ReadEvent::Idle => match keepalive.check(Instant::now()) {
+                KeepaliveAction::SendPing => {
+                    let ping: String = Message::from(Command::Ping {
+                        origin: state.name.clone(),
+                        target: None,
+                    })
+                    .into();
+                    let _ = queue.push(ping + "\n");
+                }
+                KeepaliveAction::Timeout => return "Ping timeout",
+                KeepaliveAction::Wait => {}
+            },
+            ReadEvent::Closed => return "Connection closed",
+        }
+    }
+}
 ///Procesa los mensajes emitidos por un cliente al servidor.
 fn process_client_messages(
-    mut lines: std::io::Lines<BufReader<&TcpStream>>,
-    server: Arc<Server>,
+    state: StateHandle,
     mut aux_nickname: String,
     socket: Arc<TcpStream>,
+    reader: &mut LineReader,
+    queue: SendQueue,
 ) {
-    while let Some(Ok(line)) = lines.next() {
-        let message = Message::from(line);
-        let response = process_message(message, server.clone(), &mut aux_nickname);
-        if let Ok(..) = response {
-            for response_msg in response.unwrap().into_iter() {
-                let line: String = response_msg.into();
-                let _ = socket.as_ref().write((line + "\n").as_bytes());
+    let reason = read_connection_lines(&state, reader, &queue, |line| {
+        let message = match line.parse::<Message>() {
+            Ok(message) => message,
+            Err(error) => {
+                println!("Invalid message: {}", error.error_message);
+                return;
             }
+        };
+        let response = state.client_message(&mut aux_nickname, message);
+        let replies = ReplyBuilder::new(&state.name, &aux_nickname);
+        for response_msg in response.into_iter() {
+            let line: String = replies.build(response_msg).into();
+            let _ = queue.push(line + "\n");
         }
+    });
+    let reason = if queue.is_exceeded() {
+        "Max SendQ exceeded"
+    } else {
+        reason
+    };
+    state.disconnect(&aux_nickname, socket, reason);
+    queue.close();
+}
+///Da de baja a un usuario cuya conexión terminó sin enviar QUIT, informándolo a la red.
+pub fn disconnect_user(server: &mut Server, nickname: &str, socket: &Arc<TcpStream>, reason: &str) {
+    let still_connected = server
+        .users
+        .get(nickname)
+        .and_then(|user| user.socket.as_ref())
+        .is_some_and(|user_socket| Arc::ptr_eq(user_socket, socket));
+    if still_connected {
+        let _ = quit_msg(nickname, Some(reason.to_string()), server, "");
     }
 }
 ///Procesa los mensajes emitidos por otro servidor al servidor.
-fn process_server_messages(mut lines: std::io::Lines<BufReader<&TcpStream>>, server: Arc<Server>) {
-    while let Some(Ok(line)) = lines.next() {
-        let message = Message::from(line);
-        let mut sender_nick = "".to_string();
-        let _ = process_message(message, server.clone(), &mut sender_nick);
-    }
+fn process_server_messages(
+    state: StateHandle,
+    servername: String,
+    reader: &mut LineReader,
+    queue: SendQueue,
+) {
+    let reason = read_connection_lines(&state, reader, &queue, |line| {
+        let responses = state.server_message(&servername, Message::from(line));
+        // Del resto de las respuestas no se notifica al servidor, solo se contesta su PING
+        for response in responses.into_iter().filter(|r| r.command == "PONG") {
+            let line: String = response.into();
+            let _ = queue.push(line + "\n");
+        }
+    });
+    let reason = if queue.is_exceeded() {
+        "Max SendQ exceeded"
+    } else {
+        reason
+    };
+    println!("Conexión con servidor {} terminada: {}", servername, reason);
+    queue.close();
+    state.drop_link(&servername, reason);
 }
 ///Se encarga del proceso de registración, ya sea de un nuevo cliente o de un nuevo servidor que se
 /// quiera conectar.
-fn register_connection(socket: Arc<TcpStream>, current_server: Arc<Server>) -> Option<String> {
+fn register_connection(
+    socket: Arc<TcpStream>,
+    reader: &mut LineReader,
+    state: &StateHandle,
+) -> Result<Connection, ServerError> {
     println!("Nueva conexión entrante");
 
-    let users = current_server.users.clone();
-    let connected_servers = current_server.connected_servers.clone();
-
-    let (user, server) = validate_connection(socket).expect("No se pudo registrar el usuario");
+    let nick_in_use = |nickname: &str| state.nick_in_use(nickname);
+    let (mut user, mut server) =
+        validate_connection(socket.clone(), reader, &state.config, &nick_in_use)?;
+    let limits = &state.config.limits;
 
     if user.is_registered() {
-        println!("Nuevo usuario registrado");
-        let user_nickname = add_user_to_net(user, users, &connected_servers, current_server);
-        println!("Usuario {} exitosamente agregado a la red", user_nickname);
-        Some(user_nickname)
+        let queue = SendQueue::new(socket, limits.sendq);
+        user.set_send_queue(queue.clone());
+        match state.register_user(user) {
+            Ok(user_nickname) => Ok(Connection::Client(user_nickname, queue)),
+            Err(error) => {
+                let _ = queue.push(format!("ERROR :Closing Link: {}\n", error));
+                queue.close();
+                Err(error)
+            }
+        }
     } else {
-        if !current_server.is_connected_to(&server.name) {
-            // Se envía como respuesta exitosa de la conexión
-            let msg = ":".to_string() + &current_server.name + " SERVER " + &current_server.name;
-            let _ = server.send_message(msg);
-
-            add_server_to_net(server, current_server);
-        } else {
-            let _ = server.send_message(err_already_registred().into());
+        let queue = SendQueue::new(socket, limits.server_sendq);
+        server.set_send_queue(queue.clone());
+        match state.register_server(server) {
+            Ok(servername) => Ok(Connection::Server(servername, queue)),
+            Err(error) => {
+                queue.close();
+                Err(error)
+            }
         }
-        None
     }
 }
 
-fn add_user_to_net(
-    user: User,
-    users: Arc<Mutex<Vec<User>>>,
-    connected_servers: &Arc<Mutex<Vec<Server>>>,
-    current_server: Arc<Server>,
-) -> String {
-    let current_server_name = current_server.name.clone();
+///Da de alta en la red a un usuario que completó la registración y le envía la bienvenida.
+/// Devuelve su nick, o un error si el servidor ya tiene el máximo de clientes o el usuario
+/// está banneado.
+pub fn register_user(mut user: User, current_server: &mut Server) -> Result<String, ServerError> {
+    if count_local_users(current_server) >= current_server.config.limits.max_clients {
+        println!("Servidor lleno, se rechaza al usuario {}", user.nickname);
+        return Err(ServerError::new("Server full"));
+    }
+    user.set_server(current_server.name.clone());
+    if let Some(ban) = current_server.config.find_ban(&user.prefix()) {
+        println!("Usuario {} banneado: {}", user.nickname, ban.reason);
+        let _ = user.send_reply(err_you_are_banned_creep());
+        return Err(ServerError::new(&format!("Banned ({})", ban.reason)));
+    }
+    if current_server.users.contains(&user.nickname) {
+        println!("Nick en uso, se rechaza al usuario {}", user.nickname);
+        let _ = user.send_reply(err_nickname_in_use(user.nickname.clone()));
+        return Err(ServerError::new("Nickname is already in use"));
+    }
+    println!("Nuevo usuario registrado");
+    for message in welcome_messages(&user, current_server) {
+        let _ = user.send_reply(message);
+    }
+    let user_nickname = add_user_to_net(user, current_server);
+    println!("Usuario {} exitosamente agregado a la red", user_nickname);
+    Ok(user_nickname)
+}
+
+///Da de alta el vínculo con un servidor que completó la registración y devuelve su nombre.
+/// Si el servidor ya formaba parte de la red, le responde ERR_ALREADYREGISTRED y devuelve
+/// un error.
+pub fn register_server(server: Server, current_server: &mut Server) -> Result<String, ServerError> {
+    if current_server.is_connected_to(&server.name) {
+        println!(
+            "Servidor {} ya registrado, se rechaza la conexión",
+            server.name
+        );
+        let _ = server.send_message(err_already_registred().into());
+        return Err(ServerError::new("Server is already registered"));
+    }
+    // Se envía como respuesta exitosa de la conexión
+    let msg = format!(
+        ":{} SERVER {} 1 :{}",
+        current_server.name, current_server.name, current_server.config.description
+    );
+    let _ = server.send_message(msg);
+
+    let servername = server.name.clone();
+    add_server_to_net(server, current_server);
+    Ok(servername)
+}
+
+///Cuenta los usuarios conectados directamente al servidor.
+fn count_local_users(server: &Server) -> usize {
+    server
+        .users
+        .iter()
+        .filter(|user| user.socket.is_some())
+        .count()
+}
+
+fn add_user_to_net(user: User, current_server: &mut Server) -> String {
     let user_nickname = user.nickname.to_owned();
-    let mut mutex = users.lock().expect("No se pudo registrar el usuario");
-    mutex.push(user);
+    let new_user_msg: String = user_burst_msg(&user, &current_server.name).into();
+    // El nick ya se validó al registrar al usuario
+    let _ = current_server.users.insert(user);
     println!("Usuario agregado a la lista de usuarios online");
-    let mut server_users = String::new();
-    for u in mutex.iter() {
-        server_users = server_users + &u.nickname + " ";
+    notify_server_users(&current_server.users);
+    for connected_server in current_server.connected_servers.iter() {
+        let _ = connected_server.send_message(new_user_msg.clone());
+        println!("Informando nuevo usuario a {}", connected_server.name);
     }
-    server_users.pop();
+    user_nickname
+}
+
+///Arma el NICK con el que se presenta un usuario a otro servidor, con un servidor más de
+/// distancia que el que tiene en este. Se informa el host real de la conexión, con el que se
+/// evalúan las máscaras en el resto de la red.
+pub fn user_burst_msg(user: &User, servername: &str) -> Message {
+    let command = Command::RemoteUser {
+        nickname: user.nickname.clone(),
+        hopcount: user.hopcount + 1,
+        signed_on: user.signed_on,
+        username: user.username.clone(),
+        hostname: user.connection_host(),
+        servername: user.server.clone(),
+        modes: user.modes_string(),
+        realname: user.realname.clone(),
+    };
+    user_event_msg(servername, command)
+}
+
+///Les envía a los clientes conectados a este servidor la lista de usuarios de la red.
+pub fn notify_server_users(users: &Registry<User>) {
+    let server_users = users
+        .iter()
+        .map(|user| user.nickname.clone())
+        .collect::<Vec<String>>()
+        .join(" ");
     println!("Users actuales: {}", server_users);
-    let mut user_update_msg = format!(":{current_server_name} SERVER USER_LIST_UPDATE ");
-    for user in mutex.iter() {
+    for user in users.iter() {
         let _ = user.update_server_users(server_users.clone());
-        user_update_msg = user_update_msg
-            + &user.nickname
-            + " "
-            + &user.server
-            + " "
-            + &user.username
-            + " "
-            + &user.hostname
-            + " "
-            + &user.realname
-            + " ";
-    }
-    for connected_server in connected_servers.lock().unwrap().iter() {
-        let _ = connected_server.send_message(user_update_msg.clone() + "\n");
-        println!("Informando nuevo usuario a {}", connected_server.name);
     }
-    user_nickname
 }
 
-fn add_server_to_net(new_server: Server, current_server: Arc<Server>) {
+///Agrega a la red el vínculo con un servidor, intercambiando con él el estado de la red.
+pub fn add_server_to_net(new_server: Server, current_server: &mut Server) {
     let sv_new_name = new_server.name.clone();
 
     println!("Agregando servididor {} a la red", &sv_new_name);
-    let mut connected_servers_lock = current_server.connected_servers.lock().unwrap();
-
     notify_new_server_to_net(
         &new_server.name,
-        &connected_servers_lock,
+        &current_server.connected_servers,
         &current_server.name,
     );
     exchange_servers_list(
         &current_server.name,
         &new_server,
-        &connected_servers_lock,
+        &current_server.connected_servers,
         1,
     );
-    exchange_users_list(current_server.clone(), &new_server);
+    exchange_users_list(current_server, &new_server);
 
-    exchange_channel_list(current_server.clone(), &new_server);
+    exchange_channel_list(current_server, &new_server);
 
-    connected_servers_lock.push(new_server);
+    // El servidor nuevo ya conoce el estado de la red
+    let end_of_burst = user_event_msg(&current_server.name, Command::Eob);
+    let _ = new_server.send_message(end_of_burst.into());
+
+    current_server.connected_servers.push(new_server);
     println!("Servidor {} agregado a la red", &sv_new_name);
 
     // Debugging purposes:
-    drop(connected_servers_lock);
-    show_spanning_tree(&current_server, 0);
+    show_spanning_tree(current_server, 0);
 }
 
 // Recorre cada rama del spanning tree y le informa los servidores conectados a la red al servidor que acaba de iniciar la conexión
 fn exchange_servers_list(
     root_server_name: &String,
     new_server: &Server,
-    connected_servers_lock: &std::sync::MutexGuard<Vec<Server>>,
+    connected_servers: &[Server],
     hopcount: usize,
 ) {
-    for connected_server in connected_servers_lock.iter() {
+    for connected_server in connected_servers.iter() {
         println!(
             "Enviando información del servidor {} con nodo raíz {} (hopcount: {}) al nuevo servidor {}",
             connected_server.name, root_server_name, hopcount, new_server.name
@@ -265,11 +461,10 @@ fn exchange_servers_list(
         let server_name = connected_server.name.clone();
         let server_connection_msg = format!(":{root_server_name} SERVER {server_name} {hopcount}");
         let _ = new_server.send_message(server_connection_msg);
-        let connected_server_childs_lock = connected_server.connected_servers.lock().unwrap();
         exchange_servers_list(
             &connected_server.name,
             new_server,
-            &connected_server_childs_lock,
+            &connected_server.connected_servers,
             hopcount + 1,
         );
     }
@@ -277,67 +472,34 @@ fn exchange_servers_list(
 
 fn notify_new_server_to_net(
     new_server_name: &String,
-    connected_servers_lock: &std::sync::MutexGuard<Vec<Server>>,
+    connected_servers: &[Server],
     current_server_name: &String,
 ) {
     println!("Notificando red sobre nueva conexión");
     let server_connection_msg = format!(":{current_server_name} SERVER {new_server_name} 2");
-    for connected_server in connected_servers_lock.iter() {
+    for connected_server in connected_servers.iter() {
         let _ = connected_server.send_message(server_connection_msg.clone());
     }
 }
 
-fn exchange_users_list(current_server: Arc<Server>, server: &Server) {
+fn exchange_users_list(current_server: &Server, server: &Server) {
     println!("Enviando lista de usuarios a server nuevo");
-    let current_server_name = current_server.name.clone();
-    let mut user_update_msg = format!(":{current_server_name} SERVER USER_LIST_UPDATE ");
-    let users_lock = current_server.users.lock().unwrap();
-
-    for user in users_lock.iter() {
-        user_update_msg = user_update_msg
-            + &user.nickname
-            + " "
-            + &user.server
-            + " "
-            + &user.username
-            + " "
-            + &user.hostname
-            + " "
-            + &user.realname
-            + " ";
-    }
-    let _ = server.send_message(user_update_msg);
+    for user in current_server.users.iter() {
+        let _ = server.send_message(user_burst_msg(user, &current_server.name).into());
+    }
     println!("Lista de usuarios enviada");
 }
-fn exchange_channel_list(current_server: Arc<Server>, server: &Server) {
+fn exchange_channel_list(current_server: &Server, server: &Server) {
     println!("Enviando lista de canales a server nuevo");
-    let current_server_name = current_server.name.clone();
-    let mut channel_update_msg = format!(":{current_server_name} SERVER CHANNEL_LIST_UPDATE ");
-    let channels_lock = current_server.channels.lock().unwrap();
-
-    for channel in channels_lock.iter() {
-        if channel.name.starts_with('&') {
-            channel_update_msg = channel_update_msg
-                + &channel.name
-                + " "
-                + &channel.get_users_list()
-                + " "
-                + &channel.get_topic_option()
-                + " "
-                + &channel.get_admins_list()
-                + " "
-                + &channel.get_limit_option()
-                + " "
-                + &channel.get_ban_list()
-                + " "
-                + &channel.get_can_speak_users_list()
-                + " "
-                + &channel.get_password_option()
-                + " ";
+    for channel in current_server
+        .channels
+        .iter()
+        .filter(|channel| channel.name.starts_with('&'))
+    {
+        for msg in channel_burst(channel, &current_server.name) {
+            let _ = server.send_message(msg.into());
         }
     }
-    println!("{}", channel_update_msg);
-    let _ = server.send_message(channel_update_msg);
     println!("Lista de canales enviada");
 }
 
@@ -345,78 +507,165 @@ fn exchange_channel_list(current_server: Arc<Server>, server: &Server) {
 pub fn show_spanning_tree(server: &Server, level: usize) {
     let spaces = " ".repeat(level);
     println!("{}>{}", spaces, server.name);
-    let inner_servers_lock = server.connected_servers.lock().unwrap();
-    for inner_server in inner_servers_lock.iter() {
+    for inner_server in server.connected_servers.iter() {
         //println!("{}Servidor {} anidado bajo servidor {}", spaces, inner_server.name, server.name);
         show_spanning_tree(inner_server, level + 2);
     }
     //println!("{}{} --------------------------------", spaces, server.name);
 }
 
-pub fn attempt_server_conection(server: Arc<Server>, input: String, servername: String) {
+pub fn attempt_server_conection(state: StateHandle, input: String, servername: String) {
     let lines: Vec<_> = input.split(' ').collect();
 
     if lines.len() == 2 {
         let address: String = lines[0].to_string() + ":" + lines[1];
         println!("Conectando a servidor {}", address);
 
-        let socket = TcpStream::connect(address.clone()).unwrap();
+        let socket = match TcpStream::connect(address.clone()) {
+            Ok(socket) => socket,
+            Err(e) => {
+                println!("No se pudo conectar a {}: {}", address, e);
+                return;
+            }
+        };
         let socket_ref = Arc::new(socket);
-        let reader = BufReader::new(socket_ref.as_ref());
-        let content = "SERVER ".to_string() + &servername + " 1\n";
+        // Si el servidor tiene un bloque link, se le envía la contraseña del vínculo
+        let password = state
+            .config
+            .links
+            .iter()
+            .find(|link| link.host == lines[0] && link.port.to_string() == lines[1])
+            .and_then(|link| link.password.clone());
+        let mut content = String::new();
+        if let Some(password) = password {
+            content += &format!("PASS {}\n", password);
+        }
+        content += &format!("SERVER {} 1 :{}\n", servername, state.config.description);
         let _ = socket_ref.as_ref().write(content.as_bytes());
 
-        let new_server_name = lines[0].to_string() + lines[1];
-
-        let mut lines = reader.lines();
-        if let Some(Ok(line)) = lines.next() {
-            let msg = Message::from(line);
-
-            if msg.command.as_str() == "SERVER" {
-                println!("Conexion nueva aceptada");
+        // La respuesta del otro servidor y sus mensajes posteriores se leen en otro hilo
+        let _ = thread::spawn(move || {
+            let limits = &state.config.limits;
+            let mut reader = LineReader::new(socket_ref.as_ref(), poll_interval(limits));
+            let Some(new_server_name) = await_server_reply(&mut reader, limits) else {
+                println!("Conexión con servidor {} fallida", address);
+                let _ = socket_ref.shutdown(Shutdown::Both);
+                return;
+            };
+            println!("Conexion nueva aceptada");
 
-                let mut new_server = Server::new();
-                new_server.set_socket(socket_ref.clone());
-                new_server.set_name(new_server_name);
+            let queue = SendQueue::new(socket_ref.clone(), limits.server_sendq);
+            let mut new_server = Server::new();
+            new_server.set_socket(socket_ref.clone());
+            new_server.set_send_queue(queue.clone());
+            new_server.set_name(new_server_name.clone());
 
-                add_server_to_net(new_server, server.clone());
+            state.add_link(new_server);
+            println!("Conexión exitosa con servidor {}", address);
 
-                let thread_sv_ref = server;
+            process_server_messages(state, new_server_name, &mut reader, queue);
+        });
+    }
+}
 
-                let _ = thread::spawn(move || {
-                    println!("Listo para escuchar mensajes del nuevo servidor");
+///Espera que el servidor al que se le pidió la conexión responda con su SERVER, dentro del
+/// tiempo de registración. Devuelve el nombre con el que se identificó.
+fn await_server_reply(reader: &mut LineReader, limits: &Limits) -> Option<String> {
+    let deadline = Instant::now() + Duration::from_secs(limits.registration_timeout);
+    loop {
+        match reader.next_event() {
+            ReadEvent::Line(line) => {
+                let msg = Message::from(line);
+                // El servidor remoto se identifica con el nombre de su configuración
+                return (msg.command.as_str() == "SERVER" && !msg.parameters.is_empty())
+                    .then(|| msg.parameters[0].clone());
+            }
+            ReadEvent::Idle if Instant::now() < deadline => {}
+            ReadEvent::Idle | ReadEvent::Closed => return None,
+        }
+    }
+}
 
-                    let socket_thread_ref = socket_ref.clone();
-                    let reader = BufReader::new(socket_thread_ref.as_ref());
-                    let mut lines = reader.lines();
+///Establece la conexión con los servidores configurados con `autoconnect`.
+pub fn connect_configured_links(state: StateHandle) {
+    for link in state.config.links.iter().filter(|link| link.autoconnect) {
+        let input = format!("{} {}", link.host, link.port);
+        attempt_server_conection(state.clone(), input, state.name.clone());
+    }
+}
+///Arranca la ejecución de un servidor, permitiéndole recibir nuevas conexiones y mensajes
+/// en cada una de las direcciones configuradas. Cada conexión se atiende en su propio hilo,
+/// hasta el máximo de conexiones configurado.
+pub fn run(state: StateHandle) -> std::io::Result<()> {
+    let mut listeners = Vec::new();
+    for address in state.config.listen.iter() {
+        listeners.push(TcpListener::bind(address)?);
+        println!("Servidor configurado para escuchar en {}", address);
+    }
 
-                    while let Some(Ok(line)) = lines.next() {
-                        let message = Message::from(line);
-                        let mut sender_nick = "".to_string();
-                        let _ = process_message(message, thread_sv_ref.clone(), &mut sender_nick);
-                    }
-                });
+    let open_connections = Arc::new(AtomicUsize::new(0));
+    let last_listener = listeners
+        .pop()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Sin direcciones de escucha"))?;
+    for listener in listeners {
+        let state_ref = state.clone();
+        let connections_ref = open_connections.clone();
+        thread::spawn(move || accept_connections(listener, state_ref, connections_ref));
+    }
+    accept_connections(last_listener, state, open_connections)
+}
 
-                println!("Conexión exitosa con servidor {}", address);
-            } else {
-                println!("Conexión con servidor {} fallida", address);
-            }
-        }
+///Lugar ocupado por una conexión abierta. Se libera cuando termina el hilo que la atiende,
+/// aunque termine por un pánico.
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    ///Ocupa un lugar si todavía no se llegó al máximo de conexiones.
+    fn acquire(open_connections: &Arc<AtomicUsize>, max_connections: usize) -> Option<Self> {
+        open_connections
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |open| {
+                (open < max_connections).then_some(open + 1)
+            })
+            .ok()
+            .map(|_| ConnectionSlot(open_connections.clone()))
     }
 }
-///Arranca la ejecución de un servidor, permitiéndole recibir nuevas conexiones y mensajes.
-pub fn run(server: Arc<Server>, host: String, port: u16) -> std::io::Result<()> {
-    let address = host + ":" + &port.to_string();
 
-    let listener = TcpListener::bind(&address)?;
-    println!("Servidor configurado para escuchar en {}", &address);
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
 
-    let thread_pool = ThreadPool::new(4);
+fn accept_connections(
+    listener: TcpListener,
+    state: StateHandle,
+    open_connections: Arc<AtomicUsize>,
+) -> std::io::Result<()> {
     for client_stream in listener.incoming() {
-        let client_ref = Arc::new(client_stream?);
-        let server_ref = server.clone();
-        thread_pool.execute(move || {
-            handle_client(server_ref.clone(), client_ref).expect("Error manejando cliente");
+        let client_ref = match client_stream {
+            Ok(stream) => Arc::new(stream),
+            Err(error) => {
+                println!("No se pudo aceptar la conexión: {}", error);
+                continue;
+            }
+        };
+        let max_connections = state.config.limits.max_connections;
+        let slot = match ConnectionSlot::acquire(&open_connections, max_connections) {
+            Some(slot) => slot,
+            None => {
+                println!("Máximo de conexiones alcanzado, se rechaza la conexión");
+                let _ = client_ref
+                    .as_ref()
+                    .write("ERROR :Closing Link: Too many connections\n".as_bytes());
+                let _ = client_ref.shutdown(Shutdown::Both);
+                continue;
+            }
+        };
+        let state_ref = state.clone();
+        thread::spawn(move || {
+            let _slot = slot;
+            handle_client(state_ref, client_ref).expect("Error manejando cliente");
         });
     }
     Ok(())
@@ -1,55 +1,128 @@
-use std::{
-    net::Shutdown,
-    sync::{Arc, Mutex},
-};
+use std::collections::HashSet;
+use std::net::Shutdown;
 
-use crate::channel::Channel;
+use crate::channel::{join_event_msg, send_to_channel_members, user_event_msg, Channel};
+use crate::channel_modes::{mode_changes_parameters, ModeChange};
+use crate::command::Command;
+use crate::registry::{casefold, Registry};
 use crate::{
     message::Message,
-    replies::err_no_privileges,
-    server::{show_spanning_tree, Server},
+    replies::{err_no_privileges, err_no_such_server},
+    server::{notify_server_users, show_spanning_tree, user_burst_msg, Server},
     server_errors::ServerError,
     user::User,
+    user_modes::UserModes,
 };
 
-pub fn server_msg(msg: Message, server: Arc<Server>) -> Result<Vec<Message>, ServerError> {
+///Interpreta las notificaciones SERVER recibidas de otro servidor: el alta de un servidor en la
+/// red.
+pub fn server_msg(
+    parameters: Vec<String>,
+    prefix: Option<String>,
+    server: &mut Server,
+) -> Result<Vec<Message>, ServerError> {
     let response_vec = Vec::new();
+    if parameters.is_empty() {
+        return Ok(response_vec);
+    }
+    let mut msg = Message::from(Command::Server { parameters });
+    msg.prefix = prefix;
+    process_server_notification(msg, server);
+    Ok(response_vec)
+}
 
-    match msg.parameters[0].as_str() {
-        "USER_LIST_UPDATE" => {
-            process_users_list_notification(&server, &msg);
+///Interpreta el estado de la red que informa otro servidor al vincularse y las altas de
+/// usuarios posteriores: usuarios (NICK), canales (SJOIN), listas de máscaras (BMASK) y el fin
+/// del estado (EOB). Salvo el EOB, cada mensaje se reenvía al resto de los servidores.
+pub fn burst_msg(
+    command: Command,
+    prefix: Option<String>,
+    server: &mut Server,
+) -> Result<Vec<Message>, ServerError> {
+    let Some(servername) = prefix else {
+        return Ok(Vec::new());
+    };
+    let original = Message::from(command.clone());
+    let forward = match command {
+        Command::RemoteUser {
+            nickname,
+            hopcount,
+            signed_on,
+            username,
+            hostname,
+            servername: user_server,
+            modes,
+            realname,
+        } => {
+            let mut user = User::new(None);
+            user.set_nickname(nickname);
+            user.set_server(user_server);
+            user.set_host(hostname);
+            user.set_username(username);
+            user.set_realname(realname);
+            user.modes = UserModes::from_letters(&modes);
+            user.hopcount = hopcount;
+            user.signed_on = signed_on;
+            let forward = user_burst_msg(&user, &server.name);
+            add_remote_user(user, server).then_some(forward)
         }
-        "CHANNEL_LIST_UPDATE" => {
-            process_channel_list_notification(&server, &msg);
+        Command::Sjoin {
+            created_at,
+            channel,
+            modes,
+            members,
+        } => {
+            let mut remote = Channel::new(&channel);
+            remote.created_at = created_at;
+            remote.set_mode_parameters(&modes);
+            remote.set_members(&members);
+            merge_remote_channel(remote, server);
+            Some(original)
         }
-        _ => process_server_notification(msg, server),
+        Command::Bmask {
+            created_at,
+            channel,
+            mode,
+            masks,
+        } => {
+            add_remote_masks(created_at, &channel, mode, masks, server);
+            Some(original)
+        }
+        Command::Eob => {
+            println!(
+                "Servidor {} terminó de informar el estado de la red",
+                servername
+            );
+            None
+        }
+        _ => None,
+    };
+    if let Some(msg) = forward {
+        forward_server_msg(msg, &servername, server);
     }
-    Ok(response_vec)
+    Ok(Vec::new())
 }
 
-fn process_server_notification(msg: Message, server: Arc<Server>) {
+fn process_server_notification(msg: Message, server: &mut Server) {
     println!("Agregando nuevo servidor al modelo");
     let parameters = msg.parameters;
-    let mut hopcount: usize = parameters[1].clone().parse().unwrap();
-    let connected_servers = server.get_connected_servers_from(msg.prefix.clone().unwrap());
-
-    if let Some(connected_servers) = connected_servers {
+    let (Some(prefix), Some(Ok(mut hopcount))) = (
+        msg.prefix,
+        parameters.get(1).map(|hopcount| hopcount.parse::<usize>()),
+    ) else {
+        println!("Notificación de servidor inválida: {:?}", parameters);
+        return;
+    };
+    if let Some(connected_servers) = server.get_connected_servers_from(prefix.clone()) {
         let mut new_server_node = Server::new();
         new_server_node.set_name(parameters[0].clone());
-        connected_servers.lock().unwrap().push(new_server_node);
-
-        let connected_servers_lock = server.connected_servers.lock().unwrap();
+        connected_servers.push(new_server_node);
 
-        for connected_server in connected_servers_lock.iter() {
+        for connected_server in server.connected_servers.iter() {
             if !connected_server.is_connected_to(&parameters[0].clone()) {
                 //No devuelvo el mensaje a quien me lo mandó
                 hopcount += 1;
-                let new_msg = format!(
-                    ":{} SERVER {} {}",
-                    msg.prefix.clone().unwrap(),
-                    parameters[0].clone(),
-                    hopcount
-                );
+                let new_msg = format!(":{} SERVER {} {}", prefix, parameters[0].clone(), hopcount);
                 let _ = connected_server.send_message(new_msg);
             }
         }
@@ -57,102 +130,120 @@ fn process_server_notification(msg: Message, server: Arc<Server>) {
     println!("Servidor {} exitosamente añadido al modelo", parameters[0]);
 
     // Debugging purposes:
-    show_spanning_tree(&server, 0);
+    show_spanning_tree(server, 0);
 }
 
-fn process_users_list_notification(server: &Arc<Server>, msg: &Message) {
-    println!("Actualizando lista de usuarios por movimiento en otro servidor");
-    let mut i: usize = 1;
-    let mut current_sv_users = server.users.lock().unwrap();
-    let current_sv_name = server.name.clone();
-    current_sv_users.retain(|user| user.server == current_sv_name);
-    while i < msg.parameters.len() - 1 {
-        let new_user_server = msg.parameters[i + 1].clone();
+///Agrega a la red un usuario de otro servidor. Si el nick ya está registrado se conserva el
+/// usuario existente. Devuelve si el usuario se agregó.
+fn add_remote_user(user: User, server: &mut Server) -> bool {
+    let nickname = user.nickname.clone();
+    if server.users.insert(user).is_err() {
+        println!("Nick {} en uso, se conserva el usuario existente", nickname);
+        return false;
+    }
+    notify_server_users(&server.users);
+    true
+}
 
-        if new_user_server != current_sv_name {
-            let mut new_user = User::new(None);
-            new_user.set_nickname(msg.parameters[i].clone());
-            new_user.set_server(new_user_server);
-            new_user.set_host(msg.parameters[i + 3].clone());
-            new_user.set_username(msg.parameters[i + 2].clone());
-            new_user.set_realname(msg.parameters[i + 4].clone());
-            current_sv_users.push(new_user);
+///Combina un canal informado por otro servidor con el canal local del mismo nombre, o lo
+/// agrega si no existe. Los miembros locales reciben el JOIN de cada miembro remoto nuevo y
+/// los cambios de modo que sufrió el canal.
+fn merge_remote_channel(remote: Channel, server: &mut Server) {
+    for member in remote.members.iter() {
+        if let Some(user) = server.users.get_mut(&member.nickname) {
+            user.add_channel(&remote.name);
         }
-
-        i += 5;
     }
-    let mut server_users = String::new();
-    for u in current_sv_users.iter() {
-        server_users = server_users + &u.nickname + " ";
+    match server.channels.get_mut(&remote.name) {
+        Some(channel) => {
+            let joined: Vec<String> = remote
+                .nicknames()
+                .into_iter()
+                .filter(|nickname| !channel.has_user(nickname))
+                .collect();
+            let changes = channel.merge(remote);
+            announce_remote_joins(channel, &joined, &server.users);
+            announce_server_modes(channel, &changes, &server.users, &server.name);
+        }
+        None => {
+            let _ = server.channels.insert(remote);
+        }
     }
-    server_users.pop();
-    for u in current_sv_users.iter() {
-        let _ = u.update_server_users(server_users.clone());
+}
+
+///Agrega a una lista de máscaras de un canal las informadas por otro servidor. Solo se
+/// aceptan si el canal del otro servidor no es más nuevo que el local, como sus modos.
+fn add_remote_masks(
+    created_at: u64,
+    channel_name: &str,
+    mode: char,
+    masks: Vec<String>,
+    server: &mut Server,
+) {
+    let Some(channel) = server.channels.get_mut(channel_name) else {
+        return;
+    };
+    if created_at > channel.created_at || !matches!(mode, 'b' | 'e' | 'I') {
+        return;
     }
-    let connected_servers_lock = server.connected_servers.lock().unwrap();
-    for connected_server in connected_servers_lock.iter() {
-        if connected_server.name != msg.prefix.clone().unwrap() {
-            println!("{}", connected_server.name);
-            println!("{}", msg.prefix.clone().unwrap());
-            let mut mew_msg = msg.clone();
-            mew_msg.prefix = Some(server.name.clone());
-            let _ = connected_server.send_message(String::from(mew_msg));
+    let mut added = Vec::new();
+    for mask in masks {
+        let change = ModeChange {
+            adding: true,
+            mode,
+            argument: Some(mask),
+        };
+        if channel.apply_mode(&change, &mut Vec::new()) {
+            added.push(change);
         }
     }
-    println!("Lista de usuarios actualizada exitosamente por movimiento en otro servidor");
+    announce_server_modes(channel, &added, &server.users, &server.name);
 }
-fn process_channel_list_notification(server: &Arc<Server>, msg: &Message) {
-    println!("Actualizando lista de canales por movimiento en otro servidor");
-    let mut i: usize = 1;
-    let mut current_sv_channels = server.channels.lock().unwrap();
-    let current_sv_name = server.name.clone();
-    while i < msg.parameters.len() - 1 {
-        let new_channel_server = msg.parameters[i + 1].clone();
-
-        if new_channel_server != current_sv_name {
-            let mut new_channel = Channel::new(&msg.parameters[i].clone());
-            new_channel.set_users(msg.parameters[i + 1].clone());
-            new_channel.set_topic(msg.parameters[i + 2].clone());
-            new_channel.set_admins(msg.parameters[i + 3].clone());
-            new_channel.set_limit(msg.parameters[i + 4].clone());
-            new_channel.set_ban_list(msg.parameters[i + 5].clone());
-            new_channel.set_speak_users(msg.parameters[i + 6].clone());
-            new_channel.set_password(msg.parameters[i + 7].clone());
 
-            current_sv_channels.push(new_channel);
+///Les avisa a los miembros locales de un canal el ingreso de los miembros informados por otro
+/// servidor, con el prefijo completo de cada uno.
+fn announce_remote_joins(channel: &Channel, joined: &[String], users: &Registry<User>) {
+    for nickname in joined {
+        if let Some(user) = users.get(nickname) {
+            let join = join_event_msg(&user.prefix(), &channel.name);
+            send_to_channel_members(channel, users, &join, nickname);
         }
-
-        i += 8;
     }
-    // let mut server_users = String::new();
-    // for u in current_sv_users.iter() {
-    //     server_users = server_users + &u.nickname + " ";
-    // }
-    // server_users.pop();
-    // for u in current_sv_users.iter() {
-    //     let _ = u.update_server_users(server_users.clone());
-    // }
-    let connected_servers_lock = server.connected_servers.lock().unwrap();
-    for connected_server in connected_servers_lock.iter() {
-        if connected_server.name != msg.prefix.clone().unwrap() {
-            println!("{}", connected_server.name);
-            println!("{}", msg.prefix.clone().unwrap());
-            let mut mew_msg = msg.clone();
-            mew_msg.prefix = Some(server.name.clone());
-            let _ = connected_server.send_message(String::from(mew_msg));
-        }
+}
+
+///Les avisa a los miembros locales de un canal los cambios de modo producidos al combinarlo
+/// con el de otro servidor.
+fn announce_server_modes(
+    channel: &Channel,
+    changes: &[ModeChange],
+    users: &Registry<User>,
+    servername: &str,
+) {
+    if changes.is_empty() {
+        return;
     }
-    println!("Lista de canales actualizada exitosamente por movimiento en otro servidor");
+    let command = Command::Mode {
+        target: channel.name.clone(),
+        changes: mode_changes_parameters(changes),
+    };
+    let mode = user_event_msg(servername, command);
+    send_to_channel_members(channel, users, &mode, "");
 }
 /// Se encarga de interpretar el mensaje de SQUIT enviado a un servidor. Desconecta al servidor
 /// del que se acaba de ir de la red y además distribuye esta información al resto de los servidores
 /// conectados.
 pub fn squit_msg(
-    msg: Message,
-    server: Arc<Server>,
-    user_nickname: &String,
+    server_name: String,
+    prefix: Option<String>,
+    server: &mut Server,
+    user_nickname: &str,
 ) -> Result<Vec<Message>, ServerError> {
     let mut response_vec = Vec::new();
+    let mut msg = Message::from(Command::Squit {
+        server: server_name,
+        comment: None,
+    });
+    msg.prefix = prefix;
     if msg.prefix.is_none() {
         // Sin prefijo, es un operador intentando desconectar un servidor
         println!(
@@ -170,24 +261,22 @@ pub fn squit_msg(
 }
 
 fn process_squit_msg_from_oper(
-    server: Arc<Server>,
-    user_nickname: &String,
+    server: &mut Server,
+    user_nickname: &str,
     response_vec: &mut Vec<Message>,
     msg: &Message,
 ) -> Result<(), ServerError> {
-    let mut lock_users = server
+    let current_user = server
         .users
-        .lock()
-        .map_err(|_e| ServerError::new("Cannot obtain users list"))?;
-    let current_user = lock_users
-        .iter_mut()
-        .find(|user| user.nickname == *user_nickname)
+        .get(user_nickname)
         .ok_or_else(|| ServerError::new("Cannot obtain current user"))?;
-    if !current_user.is_admin {
+    let target = &msg.parameters[0];
+    if !current_user.has_oper_privilege("squit") {
         let err_no_privileges = err_no_privileges();
         response_vec.push(err_no_privileges)
+    } else if target != &server.name && !server.is_connected_to(target) {
+        response_vec.push(err_no_such_server(target.clone()));
     } else {
-        drop(lock_users);
         let mut msg = msg.clone();
         msg.prefix = Some("".to_string()); // Prefijo vacío para reenviar a todos los servidores
         process_squit_msg_from_server(&msg, server);
@@ -195,49 +284,105 @@ fn process_squit_msg_from_oper(
     Ok(())
 }
 
-fn process_squit_msg_from_server(msg: &Message, server: Arc<Server>) {
-    forward_squit_msg(msg, server.clone());
+///Da de baja el vínculo con un servidor que dejó de responder o cerró la conexión, como si
+/// se hubiese recibido su SQUIT. Si la baja ya se había procesado no hace nada.
+pub fn drop_server_link(server: &mut Server, server_name: &str, reason: &str) {
+    if !server.is_connected_to(&server_name.to_string()) {
+        return;
+    }
+    let mut msg = Message::from(Command::Squit {
+        server: server_name.to_string(),
+        comment: Some(reason.to_string()),
+    });
+    msg.prefix = Some(server_name.to_string()); // No se le reenvía al servidor caído
+    process_squit_msg_from_server(&msg, server);
+}
+
+fn process_squit_msg_from_server(msg: &Message, server: &mut Server) {
+    forward_server_msg(
+        msg.clone(),
+        msg.prefix.as_deref().unwrap_or_default(),
+        server,
+    );
     if msg.parameters[0] == server.name {
         // Si el server a desconectar es este
         println!("Solicitud de baja de este servidor recibida");
-        disconnect_users_from_server_shutting_down(server.clone());
+        disconnect_users_from_server_shutting_down(server);
         server.shutdown();
     } else {
         println!("Baja de otro servidor de la red informada");
         let removed_server =
-            remove_server_from_network(server.connected_servers.clone(), &msg.parameters[0]);
-        remove_disconnected_server_users(server.clone(), removed_server.unwrap());
-        notify_disconnected_users_to_clients(server);
+            remove_server_from_network(&mut server.connected_servers, &msg.parameters[0]);
+        // Si el servidor no formaba parte de la red no hay usuarios que dar de baja
+        if let Some(removed_server) = removed_server {
+            let reason = msg
+                .parameters
+                .get(1)
+                .cloned()
+                .unwrap_or_else(|| format!("{} {}", server.name, removed_server.name));
+            remove_disconnected_server_users(server, removed_server, &reason);
+            notify_disconnected_users_to_clients(server);
+        }
     }
 }
 
-fn notify_disconnected_users_to_clients(server: Arc<Server>) {
-    let users_lock = server.users.lock().unwrap();
-    let mut server_users = String::new();
-    for u in users_lock.iter() {
-        server_users = server_users + &u.nickname + " ";
-    }
-    server_users.pop();
-    for user in users_lock.iter() {
-        let _ = user.update_server_users(server_users.clone());
-    }
+fn notify_disconnected_users_to_clients(server: &Server) {
+    notify_server_users(&server.users);
     println!("Current clients notified of disconnected users");
 }
 
-fn remove_disconnected_server_users(server: Arc<Server>, disconnected_server: Server) {
-    let mut inner_servers: Vec<String> = Vec::new();
+///Da de baja a los usuarios del servidor desconectado y de los que estaban detrás de él: los
+/// saca de sus canales, borra los canales que quedan vacíos y les envía el QUIT con el motivo
+/// de la desconexión a los usuarios locales que compartían canal con ellos.
+fn remove_disconnected_server_users(
+    server: &mut Server,
+    disconnected_server: Server,
+    reason: &str,
+) {
+    let mut inner_servers: Vec<String> = vec![disconnected_server.name.clone()];
     retrieve_inner_servers(&disconnected_server, &mut inner_servers);
 
-    let mut users_lock = server.users.lock().unwrap();
-    // Retener a aquellos usuarios que no estén en el servidor desconectado ni en uno de sus nodos subyacentes
-    users_lock.retain(|user| {
-        user.server != disconnected_server.name && !inner_servers.contains(&user.server)
-    });
+    let removed_nicknames: Vec<String> = server
+        .users
+        .iter()
+        .filter(|user| inner_servers.contains(&user.server))
+        .map(|user| user.nickname.clone())
+        .collect();
+    for nickname in removed_nicknames {
+        let Some(user) = server.users.remove(&nickname) else {
+            continue;
+        };
+        let quit = user_event_msg(
+            &user.prefix(),
+            Command::Quit {
+                message: Some(reason.to_string()),
+            },
+        );
+        let mut neighbours = HashSet::new();
+        for channel in server.channels.iter_mut() {
+            if channel.has_user(&nickname) {
+                channel.remove_user(&nickname);
+                neighbours.extend(
+                    channel
+                        .members
+                        .iter()
+                        .map(|member| casefold(&member.nickname)),
+                );
+            }
+        }
+        for neighbour in server.users.iter() {
+            if neighbour.socket.is_some() && neighbours.contains(&casefold(&neighbour.nickname)) {
+                let _ = neighbour.send_private_message(quit.clone());
+            }
+        }
+    }
+    server
+        .channels
+        .retain(|channel| !channel.members.is_empty());
 }
 
 fn retrieve_inner_servers(root_server: &Server, vec_inner_servers: &mut Vec<String>) {
-    let inner_servers_lock = root_server.connected_servers.lock().unwrap();
-    for srv in inner_servers_lock.iter() {
+    for srv in root_server.connected_servers.iter() {
         println!(
             "Se removerán también usuarios del servidor {} por desconexión del servidor {}",
             srv.name, root_server.name
@@ -247,65 +392,67 @@ fn retrieve_inner_servers(root_server: &Server, vec_inner_servers: &mut Vec<Stri
     }
 }
 
-fn disconnect_users_from_server_shutting_down(server: Arc<Server>) {
-    let users_lock = server.users.lock().unwrap();
-    for user in users_lock.iter() {
-        if user.socket.is_some() {
+fn disconnect_users_from_server_shutting_down(server: &Server) {
+    for user in server.users.iter() {
+        if let Some(socket) = &user.socket {
             println!(
                 "Desconectando usuario {} por cierre de servidor",
                 user.nickname
             );
             let usr_quit_msg = Message::from("QUIT :Server shutting down".to_string());
             let _ = user.send_private_message(usr_quit_msg);
-            user.socket
-                .as_ref()
-                .unwrap()
+            socket
                 .shutdown(Shutdown::Both)
                 .expect("client shutdown call failed");
         }
     }
 }
 
-fn forward_squit_msg(msg: &Message, server: Arc<Server>) {
-    let mut squit_msg = msg.clone();
-    squit_msg.prefix = Some(server.name.clone());
-    let connected_servers_lock = server.connected_servers.lock().unwrap();
-    for connected_server in connected_servers_lock.iter() {
-        if connected_server.name.clone() != msg.prefix.clone().unwrap() {
+///Reenvía un mensaje a los servidores conectados, con este servidor como prefijo, salvo al
+/// servidor del que se recibió.
+fn forward_server_msg(mut msg: Message, received_from: &str, server: &Server) {
+    msg.prefix = Some(server.name.clone());
+    let line: String = msg.into();
+    for connected_server in server.connected_servers.iter() {
+        if connected_server.name != received_from {
             // No le devuelvo el mensaje a quien me lo mandó
-            let _ = connected_server.send_message(squit_msg.clone().into());
+            let _ = connected_server.send_message(line.clone());
         }
     }
 }
-pub fn forward_invite_msg(msg: Message, connected_servers: Arc<Mutex<Vec<Server>>>) {
-    let connected_servers_lock = connected_servers.lock().unwrap();
-    if msg.prefix.is_some() {
-        for connected_server in connected_servers_lock.iter() {
-            if connected_server.name.clone() != msg.prefix.clone().unwrap() {
-                // No le devuelvo el mensaje a quien me lo mandó
-                let _ = connected_server.send_message(msg.clone().into());
-            }
+///Reenvía a los servidores conectados un evento de un usuario (NICK, JOIN, PART, KICK o QUIT)
+/// con su nick como prefijo, salvo al servidor del que se recibió el evento. Los eventos de
+/// los usuarios locales se reciben de un servidor vacío y se envían a todos.
+pub fn propagate_user_msg(
+    mut msg: Message,
+    nickname: &str,
+    received_from: &str,
+    connected_servers: &[Server],
+) {
+    msg.prefix = Some(nickname.to_string());
+    let line: String = msg.into();
+    for connected_server in connected_servers.iter() {
+        if connected_server.name != received_from {
+            let _ = connected_server.send_message(line.clone());
         }
     }
 }
 
 // Se recorre recursivamente el árbol para desconectar los servidores o la red de servidores que esté detrás del nodo que se intenta desconectar
 fn remove_server_from_network(
-    connected_servers: Arc<Mutex<Vec<Server>>>,
+    connected_servers: &mut Vec<Server>,
     disconnected_server_name: &String,
 ) -> Option<Server> {
-    let mut connected_servers_lock = connected_servers.lock().unwrap();
-    for (pos, connected_server) in connected_servers_lock.iter().enumerate() {
-        if &connected_server.name == disconnected_server_name {
-            return Some(connected_servers_lock.remove(pos));
-        }
-        let removed_server = remove_server_from_network(
-            connected_server.connected_servers.clone(),
-            disconnected_server_name,
-        );
-        if removed_server.is_some() {
-            return removed_server;
-        }
+    if let Some(pos) = connected_servers
+        .iter()
+        .position(|connected_server| &connected_server.name == disconnected_server_name)
+    {
+        return Some(connected_servers.remove(pos));
     }
-    None
+    connected_servers.iter_mut().find_map(|connected_server| {
+        remove_server_from_network(
+            &mut connected_server.connected_servers,
+            disconnected_server_name,
+        )
+    })
 }
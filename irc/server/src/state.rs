@@ -0,0 +1,422 @@
+use std::net::TcpStream;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use crate::config::ServerConfig;
+use crate::interpreter::{process_link_message, process_message};
+use crate::message::Message;
+use crate::server::{
+    add_server_to_net, disconnect_user, register_server, register_user, show_spanning_tree, Server,
+};
+use crate::server_errors::ServerError;
+use crate::server_messages_interpreter::drop_server_link;
+use crate::user::User;
+
+///Pedido que una conexión le hace al hilo dueño del estado de la red. Los pedidos que esperan
+/// una respuesta traen el canal por el que se les contesta.
+pub enum StateRequest {
+    ///Mensaje de un cliente registrado. Se responde con su nick, que puede haber cambiado,
+    /// y las respuestas para el cliente.
+    ClientMessage {
+        nickname: String,
+        message: Message,
+        respond: Sender<(String, Vec<Message>)>,
+    },
+    ///Mensaje recibido por el vínculo con otro servidor, con el nombre de ese servidor.
+    ServerMessage {
+        link: String,
+        message: Message,
+        respond: Sender<Vec<Message>>,
+    },
+    ///Alta de un usuario que completó la registración.
+    RegisterUser {
+        user: User,
+        respond: Sender<Result<String, ServerError>>,
+    },
+    ///Consulta si un nickname ya pertenece a un usuario de la red.
+    NickInUse {
+        nickname: String,
+        respond: Sender<bool>,
+    },
+    ///Alta del vínculo con un servidor que completó la registración.
+    RegisterServer {
+        server: Server,
+        respond: Sender<Result<String, ServerError>>,
+    },
+    ///Alta del vínculo con un servidor que aceptó la conexión iniciada por este servidor.
+    AddLink { server: Server },
+    ///Baja de un usuario cuya conexión terminó sin QUIT.
+    Disconnect {
+        nickname: String,
+        socket: Arc<TcpStream>,
+        reason: String,
+    },
+    ///Baja del vínculo con un servidor que dejó de responder o cerró la conexión.
+    DropLink { servername: String, reason: String },
+    ///Muestra el spanning tree de la red, para depurar.
+    ShowNetwork,
+    ///Consulta sobre el estado de la red, que contesta la propia función.
+    Query(Box<dyn FnOnce(&Server) + Send>),
+}
+
+#[derive(Debug, Clone)]
+///Acceso al hilo que es dueño del estado de la red: usuarios, canales y vínculos con otros
+/// servidores. Los hilos de las conexiones no comparten esas listas, sino que le envían
+/// pedidos a este hilo, que los atiende de a uno. Los mensajes para otras conexiones se
+/// encolan en sus colas de salida, por lo que atender un pedido nunca se bloquea. Las
+/// conexiones también obtienen de acá el nombre y la configuración del servidor.
+pub struct StateHandle {
+    sender: Sender<StateRequest>,
+    pub name: String,
+    pub config: Arc<ServerConfig>,
+}
+
+impl StateHandle {
+    ///Arranca el hilo dueño del estado del servidor pasado, que pasa a pertenecerle.
+    pub fn spawn(mut server: Server) -> StateHandle {
+        let (sender, receiver) = channel();
+        let state = StateHandle {
+            sender,
+            name: server.name.clone(),
+            config: Arc::new(server.config.clone()),
+        };
+        server.set_state(state.clone());
+        thread::spawn(move || {
+            for request in receiver {
+                serve_contained(request, &mut server);
+            }
+        });
+        state
+    }
+
+    ///Procesa el mensaje de un cliente. Actualiza el nick si el mensaje lo cambió y devuelve
+    /// las respuestas para el cliente.
+    pub fn client_message(&self, nickname: &mut String, message: Message) -> Vec<Message> {
+        let (respond, response) = channel();
+        let request = StateRequest::ClientMessage {
+            nickname: nickname.clone(),
+            message,
+            respond,
+        };
+        if self.sender.send(request).is_err() {
+            return Vec::new();
+        }
+        match response.recv() {
+            Ok((new_nickname, replies)) => {
+                *nickname = new_nickname;
+                replies
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+
+    ///Procesa un mensaje recibido por el vínculo con el servidor pasado.
+    pub fn server_message(&self, link: &str, message: Message) -> Vec<Message> {
+        let (respond, response) = channel();
+        let request = StateRequest::ServerMessage {
+            link: link.to_string(),
+            message,
+            respond,
+        };
+        if self.sender.send(request).is_err() {
+            return Vec::new();
+        }
+        response.recv().unwrap_or_default()
+    }
+
+    ///Agrega a la red un usuario registrado y devuelve su nick.
+    pub fn register_user(&self, user: User) -> Result<String, ServerError> {
+        let (respond, response) = channel();
+        self.sender
+            .send(StateRequest::RegisterUser { user, respond })
+            .map_err(|_| ServerError::new("El estado del servidor no está disponible"))?;
+        response
+            .recv()
+            .map_err(|_| ServerError::new("El estado del servidor no está disponible"))?
+    }
+
+    ///Evalúa si un nickname ya pertenece a un usuario de la red.
+    pub fn nick_in_use(&self, nickname: &str) -> bool {
+        let (respond, response) = channel();
+        let request = StateRequest::NickInUse {
+            nickname: nickname.to_string(),
+            respond,
+        };
+        if self.sender.send(request).is_err() {
+            return false;
+        }
+        response.recv().unwrap_or(false)
+    }
+
+    ///Agrega a la red el vínculo con un servidor registrado.
+    pub fn register_server(&self, server: Server) -> Result<String, ServerError> {
+        let (respond, response) = channel();
+        self.sender
+            .send(StateRequest::RegisterServer { server, respond })
+            .map_err(|_| ServerError::new("El estado del servidor no está disponible"))?;
+        response
+            .recv()
+            .map_err(|_| ServerError::new("El estado del servidor no está disponible"))?
+    }
+
+    ///Agrega a la red el vínculo con un servidor que aceptó la conexión de este servidor.
+    pub fn add_link(&self, server: Server) {
+        let _ = self.sender.send(StateRequest::AddLink { server });
+    }
+
+    ///Da de baja al usuario conectado por el socket pasado, si sigue en la red.
+    pub fn disconnect(&self, nickname: &str, socket: Arc<TcpStream>, reason: &str) {
+        let _ = self.sender.send(StateRequest::Disconnect {
+            nickname: nickname.to_string(),
+            socket,
+            reason: reason.to_string(),
+        });
+    }
+
+    ///Da de baja el vínculo con un servidor.
+    pub fn drop_link(&self, servername: &str, reason: &str) {
+        let _ = self.sender.send(StateRequest::DropLink {
+            servername: servername.to_string(),
+            reason: reason.to_string(),
+        });
+    }
+
+    ///Muestra el spanning tree de la red.
+    pub fn show_network(&self) {
+        let _ = self.sender.send(StateRequest::ShowNetwork);
+    }
+
+    ///Consulta el estado de la red con la función pasada y devuelve su resultado, o nada si
+    /// la consulta falló.
+    pub fn query<T: Send + 'static>(
+        &self,
+        query: impl FnOnce(&Server) -> T + Send + 'static,
+    ) -> Option<T> {
+        let (respond, response) = channel();
+        let request = StateRequest::Query(Box::new(move |server| {
+            let _ = respond.send(query(server));
+        }));
+        self.sender.send(request).ok()?;
+        response.recv().ok()
+    }
+}
+
+///Atiende un pedido sin que un error en su procesamiento detenga al hilo dueño del estado.
+/// Si falla, quien hizo el pedido no recibe respuesta y el resto de los pedidos se siguen
+/// atendiendo.
+fn serve_contained(request: StateRequest, server: &mut Server) {
+    if panic::catch_unwind(AssertUnwindSafe(|| serve(request, server))).is_err() {
+        println!("Error al atender un pedido, se descarta");
+    }
+}
+
+fn serve(request: StateRequest, server: &mut Server) {
+    match request {
+        StateRequest::ClientMessage {
+            mut nickname,
+            message,
+            respond,
+        } => {
+            let replies = process_message(message, server, &mut nickname);
+            let _ = respond.send((nickname, replies.unwrap_or_default()));
+        }
+        StateRequest::ServerMessage {
+            link,
+            message,
+            respond,
+        } => {
+            let replies = process_link_message(message, server, &link);
+            let _ = respond.send(replies.unwrap_or_default());
+        }
+        StateRequest::RegisterUser { user, respond } => {
+            let _ = respond.send(register_user(user, server));
+        }
+        StateRequest::NickInUse { nickname, respond } => {
+            let _ = respond.send(server.users.contains(&nickname));
+        }
+        StateRequest::RegisterServer {
+            server: new_server,
+            respond,
+        } => {
+            let _ = respond.send(register_server(new_server, server));
+        }
+        StateRequest::AddLink { server: new_server } => add_server_to_net(new_server, server),
+        StateRequest::Disconnect {
+            nickname,
+            socket,
+            reason,
+        } => disconnect_user(server, &nickname, &socket, &reason),
+        StateRequest::DropLink { servername, reason } => {
+            drop_server_link(server, &servername, &reason)
+        }
+        StateRequest::ShowNetwork => show_spanning_tree(server, 0),
+        StateRequest::Query(query) => query(server),
+    }
+}
+
+#[cfg(test)]
+mod tests_state {
+    use std::io::{BufRead, BufReader};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::message::Message;
+    use crate::sendq::SendQueue;
+    use crate::server::Server;
+    use crate::state::StateHandle;
+    use crate::user::User;
+
+    fn registered_user(nickname: &str) -> User {
+        let mut user = User::new(None);
+        user.nickname = nickname.to_string();
+        user.username = "user".to_string();
+        user.hostname = "host".to_string();
+        user.realname = "real".to_string();
+        user
+    }
+
+    fn message(line: &str) -> Message {
+        Message::from(line.to_string())
+    }
+
+    #[test]
+    fn test_el_estado_registra_usuarios_y_actualiza_el_nick() {
+        let state = StateHandle::spawn(Server::new());
+        state.register_user(registered_user("nick1")).unwrap();
+
+        let mut nickname = "nick1".to_string();
+        let replies = state.client_message(&mut nickname, message("NICK nick2"));
+
+        assert!(replies.is_empty());
+        assert_eq!("nick2", nickname);
+        let renamed = state.query(|server| server.users[0].nickname.clone());
+        assert_eq!(Some("nick2".to_string()), renamed);
+    }
+
+    #[test]
+    fn test_rechazo_por_nick_en_uso_lleva_el_nombre_del_servidor() {
+        let mut server = Server::new();
+        server.set_name("server1".to_string());
+        let state = StateHandle::spawn(server);
+        state.register_user(registered_user("nick1")).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let socket = Arc::new(listener.accept().unwrap().0);
+        let mut user = registered_user("nick1");
+        user.socket = Some(socket.clone());
+        let queue = SendQueue::new(socket, 1024);
+        user.set_send_queue(queue.clone());
+
+        assert!(state.register_user(user).is_err());
+        queue.close();
+
+        let mut reply = String::new();
+        BufReader::new(client).read_line(&mut reply).unwrap();
+        assert!(reply.starts_with(":server1 433 "));
+    }
+
+    #[test]
+    fn test_privmsg_y_kick_concurrentes_no_bloquean_al_servidor() {
+        let state = StateHandle::spawn(Server::new());
+        for nickname in ["nick1", "nick2"] {
+            state.register_user(registered_user(nickname)).unwrap();
+            let mut nickname = nickname.to_string();
+            state.client_message(&mut nickname, message("JOIN #canal"));
+        }
+
+        let mut handles = Vec::new();
+        for (nickname, line) in [
+            ("nick1", "KICK #canal nick3"),
+            ("nick2", "PRIVMSG #canal :hola"),
+        ] {
+            let state = state.clone();
+            handles.push(thread::spawn(move || {
+                let mut nickname = nickname.to_string();
+                for _ in 0..100 {
+                    state.client_message(&mut nickname, message(line));
+                }
+            }));
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let members = state.query(|server| server.channels[0].nicknames());
+        assert_eq!(
+            Some(vec!["nick1".to_string(), "nick2".to_string()]),
+            members
+        );
+    }
+
+    #[test]
+    fn test_un_pedido_que_falla_no_detiene_al_estado() {
+        let state = StateHandle::spawn(Server::new());
+        state.register_user(registered_user("nick1")).unwrap();
+
+        let failed: Option<()> = state.query(|_| panic!("error al atender el pedido"));
+        let mut nickname = "nick1".to_string();
+        state.client_message(&mut nickname, message("NICK nick2"));
+
+        assert!(failed.is_none());
+        assert_eq!("nick2", nickname);
+        let renamed = state.query(|server| server.users[0].nickname.clone());
+        assert_eq!(Some("nick2".to_string()), renamed);
+    }
+
+    #[test]
+    fn test_squit_de_un_servidor_desconocido_devuelve_no_such_server() {
+        let state = StateHandle::spawn(Server::new());
+        let mut oper = registered_user("nick1");
+        oper.become_oper(vec!["squit".to_string()]);
+        state.register_user(oper).unwrap();
+
+        let mut nickname = "nick1".to_string();
+        let replies = state.client_message(&mut nickname, message("SQUIT desconocido"));
+        assert_eq!("402", replies[0].command);
+        let replies = state.client_message(&mut nickname, message("NAMES"));
+        assert_eq!("366", replies[replies.len() - 1].command);
+    }
+
+    #[test]
+    fn test_servidor_duplicado_es_rechazado_sin_tocar_el_vinculo_existente() {
+        let state = StateHandle::spawn(Server::new());
+        let linked_server = |name: &str| {
+            let mut linked = Server::new();
+            linked.name = name.to_string();
+            linked
+        };
+
+        assert_eq!("sv2", state.register_server(linked_server("sv2")).unwrap());
+        assert!(state.register_server(linked_server("sv2")).is_err());
+        let links = state.query(|server| server.connected_servers.len());
+        assert_eq!(Some(1), links);
+    }
+
+    #[test]
+    fn test_caida_de_un_vinculo_saca_a_sus_usuarios_de_los_canales() {
+        let state = StateHandle::spawn(Server::new());
+        let mut linked = Server::new();
+        linked.name = "sv2".to_string();
+        state.register_server(linked).unwrap();
+        state.register_user(registered_user("nick1")).unwrap();
+        for line in [
+            ":sv2 NICK remoto 1 1700000000 rem host2 sv2 +i :Usuario Remoto",
+            ":remoto JOIN #canal",
+            ":remoto JOIN #solo",
+        ] {
+            state.server_message("sv2", message(line));
+        }
+        let mut nickname = "nick1".to_string();
+        state.client_message(&mut nickname, message("JOIN #canal"));
+
+        state.drop_link("sv2", "Ping timeout");
+
+        let replies = state.client_message(&mut nickname, message("NAMES #canal"));
+        assert_eq!(vec!["=", "#canal", "nick1"], replies[0].parameters);
+        let remaining = state.query(|server| (server.users.len(), server.channels.len()));
+        assert_eq!(Some((1, 1)), remaining);
+    }
+}
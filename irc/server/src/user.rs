@@ -1,13 +1,22 @@
-use std::{io::Write, net::TcpStream, sync::Arc};
+use std::{
+    io::{Error, ErrorKind},
+    net::TcpStream,
+    sync::Arc,
+};
 
+use crate::date::unix_time;
 use crate::message::Message;
-use crate::replies::err_nickname_in_use;
+use crate::registry::same_name;
+use crate::replies::{err_nickname_in_use, ReplyBuilder};
+use crate::sendq::SendQueue;
+use crate::user_modes::UserModes;
 
 #[derive(Debug, Clone)]
 ///Es la representación de un usuario de un sistema de Internet Relay Chat.
-/// Cuenta con los atributos necesarios para registrarse en la red, un flag para identificar
-/// si es operador, el socket al que está conectado desde la aplicación cliente, los canales
-/// a los que pertenece y, si tiene, un mensaje de away.
+/// Cuenta con los atributos necesarios para registrarse en la red, sus modos (entre ellos
+/// el de operador), el socket al que está conectado desde la aplicación cliente con su cola de
+/// salida, los canales a los que pertenece y, si tiene, un mensaje de away.
+/// Los usuarios de otros servidores conocen además a cuántos servidores de distancia están.
 pub struct User {
     pub password: String,
     pub nickname: String,
@@ -15,18 +24,23 @@ pub struct User {
     pub hostname: String,
     pub server: String,
     pub realname: String,
-    pub is_admin: bool,
+    pub modes: UserModes,
+    pub oper_privileges: Vec<String>,
     pub socket: Option<Arc<TcpStream>>,
+    pub send_queue: Option<SendQueue>,
     pub channels: Vec<String>,
     pub away_message: Option<String>,
+    // cantidad de servidores que separan al usuario de este servidor (0 si es local)
+    pub hopcount: usize,
+    // momento en que el usuario se conectó a la red
+    pub signed_on: u64,
 }
 
 impl User {
     /// Evalúa si el usuario ya ha pasado por la etapa de registración.
-    // All params are present
+    // All params are present (PASS es opcional y se valida contra la configuración)
     pub fn is_registered(&self) -> bool {
-        !(self.password.is_empty()
-            || self.nickname.is_empty()
+        !(self.nickname.is_empty()
             || self.username.is_empty()
             || self.hostname.is_empty()
             || self.server.is_empty()
@@ -41,45 +55,107 @@ impl User {
             hostname: Default::default(),
             server: Default::default(),
             realname: Default::default(),
-            is_admin: false,
+            modes: UserModes::new(),
+            oper_privileges: Vec::new(),
             socket,
+            send_queue: None,
             channels: Vec::new(),
             away_message: None,
+            hopcount: 0,
+            signed_on: unix_time(),
         }
     }
-    ///Se utiliza para enviarle un Mensaje al usuario.
-    //Precondition: User must be online ("socket" must not be None)
+    ///Se utiliza para enviarle un Mensaje al usuario. El mensaje se encola en la cola de
+    /// salida de su conexión.
     pub fn send_private_message(
         &self,
         msg: crate::message::Message,
     ) -> Result<usize, std::io::Error> {
         let content: String = msg.into();
-        self.socket
-            .as_ref()
-            .unwrap()
-            .as_ref()
-            .write((content + "\n").as_bytes())
+        match &self.send_queue {
+            Some(queue) => queue.push(content + "\n"),
+            None => Err(Error::new(
+                ErrorKind::NotConnected,
+                "El usuario no está conectado a este servidor",
+            )),
+        }
+    }
+    ///Configura la cola de salida de la conexión del usuario.
+    pub fn set_send_queue(&mut self, send_queue: SendQueue) {
+        self.send_queue = Some(send_queue);
+    }
+    ///Envía una respuesta numérica al usuario, con el prefijo de su servidor y su nick.
+    pub fn send_reply(&self, reply: Message) -> Result<usize, std::io::Error> {
+        let reply = ReplyBuilder::new(&self.server, &self.nickname).build(reply);
+        self.send_private_message(reply)
+    }
+    ///Evalúa si el usuario es operador (modo `+o`).
+    pub fn is_admin(&self) -> bool {
+        self.modes.o
+    }
+    ///Obtiene los modos del usuario como se informan en RPL_UMODEIS: `+iw`.
+    pub fn modes_string(&self) -> String {
+        "+".to_string() + &self.modes.letters()
+    }
+    ///Evalúa si otro usuario puede ver a este en WHO y NAMES. Los usuarios invisibles (`+i`)
+    /// solo son visibles para sí mismos y para quienes comparten algún canal con ellos.
+    pub fn is_visible_to(&self, other: &User) -> bool {
+        !self.modes.i
+            || same_name(&self.nickname, &other.nickname)
+            || self
+                .channels
+                .iter()
+                .any(|channel| other.is_in_channel(channel))
     }
     ///Convierte al usuario en operador.
     pub fn become_admin(&mut self) {
-        self.is_admin = true;
+        self.modes.o = true;
         println!("{} is admin!", self.nickname);
     }
+    ///Convierte al usuario en operador con los privilegios pasados.
+    pub fn become_oper(&mut self, privileges: Vec<String>) {
+        self.become_admin();
+        self.oper_privileges = privileges;
+    }
+    ///Evalúa si el usuario es operador y cuenta con un determinado privilegio.
+    pub fn has_oper_privilege(&self, privilege: &str) -> bool {
+        self.is_admin() && self.oper_privileges.iter().any(|p| p == privilege)
+    }
+    ///Obtiene el host desde el que se conecta el usuario. Si está conectado a este
+    /// servidor se usa la dirección real del socket y no la informada en USER.
+    pub fn connection_host(&self) -> String {
+        self.socket
+            .as_ref()
+            .and_then(|socket| socket.peer_addr().ok())
+            .map(|address| address.ip().to_string())
+            .unwrap_or_else(|| self.hostname.clone())
+    }
+    ///Obtiene el prefijo con el que se identifican los mensajes del usuario: `nick!user@host`.
+    pub fn prefix(&self) -> String {
+        format!(
+            "{}!{}@{}",
+            self.nickname,
+            self.username,
+            self.connection_host()
+        )
+    }
     ///Evalúa si el usuario pertenece a un determinado canal.
-    pub fn is_in_channel(&self, channel_name: &String) -> bool {
-        self.channels.iter().any(|channel| channel_name == channel)
+    pub fn is_in_channel(&self, channel_name: &str) -> bool {
+        self.channels
+            .iter()
+            .any(|channel| same_name(channel_name, channel))
     }
     ///Agrega un canal a la lista de canales a los que pertenece el usuario.
-    pub fn add_channel(&mut self, channel_name: &String) {
+    pub fn add_channel(&mut self, channel_name: &str) {
         if !self.is_in_channel(channel_name) {
-            self.channels.push(channel_name.clone());
+            self.channels.push(channel_name.to_string());
         }
     }
     ///Cuando un usuario abandona un canal, elimina el canal de la lista de canales del usuario.
-    pub fn leave_channel(&mut self, channel_name: &String) {
+    pub fn leave_channel(&mut self, channel_name: &str) {
         self.channels
             .iter()
-            .position(|channel| channel_name == channel)
+            .position(|channel| same_name(channel_name, channel))
             .map(|position| self.channels.remove(position));
     }
 
@@ -98,7 +174,7 @@ impl User {
         if self.socket.is_some() {
             // Construir el mensaje adecuado para enviar el update con el command
             let message = err_nickname_in_use(nick);
-            self.send_private_message(message)
+            self.send_reply(message)
         } else {
             Ok(0)
         }
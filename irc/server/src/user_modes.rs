@@ -0,0 +1,75 @@
+#[derive(Debug, Clone, Default)]
+///Almacena la información sobre qué modos están activados para un determinado usuario:
+/// invisible (`i`), wallops (`w`), avisos del servidor (`s`) y operador (`o`).
+pub struct UserModes {
+    pub i: bool,
+    pub w: bool,
+    pub s: bool,
+    pub o: bool,
+}
+
+impl UserModes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///Activa o desactiva uno de los modos del usuario. Devuelve si el modo cambió de estado.
+    pub fn set(&mut self, mode: char, value: bool) -> bool {
+        let flag = match mode {
+            'i' => &mut self.i,
+            'w' => &mut self.w,
+            's' => &mut self.s,
+            'o' => &mut self.o,
+            _ => return false,
+        };
+        let changed = *flag != value;
+        *flag = value;
+        changed
+    }
+
+    ///Obtiene las letras de los modos activados, en el orden `iwso`.
+    pub fn letters(&self) -> String {
+        [(self.i, 'i'), (self.w, 'w'), (self.s, 's'), (self.o, 'o')]
+            .iter()
+            .filter(|(active, _)| *active)
+            .map(|(_, letter)| *letter)
+            .collect()
+    }
+
+    ///Activa los modos indicados en un texto como `+iw`, que es como se informan los modos
+    /// de los usuarios entre servidores.
+    pub fn from_letters(letters: &str) -> Self {
+        let mut modes = Self::new();
+        for mode in letters.chars() {
+            modes.set(mode, true);
+        }
+        modes
+    }
+}
+
+#[cfg(test)]
+mod tests_user_modes {
+    use crate::user_modes::UserModes;
+
+    #[test]
+    fn test_modos_se_activan_y_se_listan_en_orden() {
+        let mut modes = UserModes::new();
+
+        assert!(modes.set('o', true));
+        assert!(modes.set('i', true));
+        assert!(!modes.set('i', true));
+        assert!(!modes.set('x', true));
+        assert_eq!("io", modes.letters());
+        assert!(modes.set('o', false));
+        assert_eq!("i", modes.letters());
+    }
+
+    #[test]
+    fn test_modos_se_leen_desde_el_texto_de_otro_servidor() {
+        let modes = UserModes::from_letters("+ws");
+
+        assert!(modes.w && modes.s);
+        assert!(!modes.i && !modes.o);
+        assert_eq!("", UserModes::from_letters("+").letters());
+    }
+}
@@ -1,4 +1,5 @@
-use server;
+use server::config::{LinkBlock, OperatorBlock};
+use server::state::StateHandle;
 use std::net::Shutdown;
 use std::sync::mpsc::channel;
 use std::{
@@ -61,19 +62,17 @@ impl Client {
 #[test]
 
 fn register_four_clients_test() {
-    let mut argv = Vec::new();
-    argv.push("localhost".to_string());
-    argv.push("8081".to_string());
+    let argv = ["localhost".to_string(), "8081".to_string()];
     let host = argv[0].to_owned();
     let port = argv[1].parse::<u16>().expect("Puerto inválido");
     let servername = host.clone() + &port.to_string();
 
     let mut server = server::server::Server::new();
     server.set_name(servername.clone());
-    let server_ref = Arc::new(server);
+    let server_ref = StateHandle::spawn(server);
 
     let address = host.clone() + ":" + &port.to_string();
-    let listener = TcpListener::bind(&address.clone()).unwrap();
+    let listener = TcpListener::bind(address.clone()).unwrap();
     println!("Servidor configurado para escuchar en {}", &address);
 
     let mut clients_array: Vec<Client> = Vec::new();
@@ -106,8 +105,6 @@ fn register_four_clients_test() {
     register_array.push(msg_nick3);
     register_array.push(msg_nick4);
 
-    let thread_pool = server::threadpool::ThreadPool::new(4);
-
     //let client_ref = Client::new("localhost:8080".to_string(), "juan".to_string());
     //let arc_client = Arc::new(client_ref.socket);
     let mut i = 0;
@@ -117,7 +114,7 @@ fn register_four_clients_test() {
     for client_stream in listener.incoming() {
         let arc_server = server_ref.clone();
         let arc_server_ref = arc_server.clone();
-        thread_pool.execute(move || {
+        thread::spawn(move || {
             let arc_client = Arc::new(client_stream.unwrap());
             server::server::handle_client(arc_server.clone(), arc_client)
                 .expect("Error manejando cliente");
@@ -131,42 +128,39 @@ fn register_four_clients_test() {
 
         thread::sleep(ten_millis);
 
-        i = i + 1;
+        i += 1;
 
         if i == 4 {
-            nick_array.push(arc_server_ref.users.lock().unwrap()[0].nickname.clone());
-            nick_array.push(arc_server_ref.users.lock().unwrap()[1].nickname.clone());
-            nick_array.push(arc_server_ref.users.lock().unwrap()[2].nickname.clone());
-            nick_array.push(arc_server_ref.users.lock().unwrap()[3].nickname.clone());
+            nick_array = arc_server_ref
+                .query(|server| server.users.iter().map(|u| u.nickname.clone()).collect())
+                .unwrap();
             break;
         }
     }
 
-    assert_eq!(nick_array.contains(&"juan".to_string()), true);
-    assert_eq!(nick_array.contains(&"mateo".to_string()), true);
-    assert_eq!(nick_array.contains(&"martin".to_string()), true);
-    assert_eq!(nick_array.contains(&"franco".to_string()), true);
+    assert!(nick_array.contains(&"juan".to_string()));
+    assert!(nick_array.contains(&"mateo".to_string()));
+    assert!(nick_array.contains(&"martin".to_string()));
+    assert!(nick_array.contains(&"franco".to_string()));
 }
 
 #[test]
 fn privmsg_two_users() {
-    let mut argv = Vec::new();
-    argv.push("localhost".to_string());
-    argv.push("8080".to_string());
+    let argv = ["localhost".to_string(), "8080".to_string()];
     let host = argv[0].to_owned();
     let port = argv[1].parse::<u16>().expect("Puerto inválido");
     let servername = host.clone() + &port.to_string();
 
     let mut server = server::server::Server::new();
     server.set_name(servername.clone());
-    let server_ref = Arc::new(server);
+    let server_ref = StateHandle::spawn(server);
 
     let address = host.clone() + ":" + &port.to_string();
     //let sv_name = host
     //let mut srv = Server::new();
     //srv.name = host + &port.to_string();
     //let server = Arc::new(srv);
-    let listener = TcpListener::bind(&address.clone()).unwrap();
+    let listener = TcpListener::bind(address.clone()).unwrap();
     println!("Servidor configurado para escuchar en {}", &address);
 
     let mut clients_array: Vec<Client> = Vec::new();
@@ -191,8 +185,6 @@ fn privmsg_two_users() {
     register_array.push(msg_nick1);
     register_array.push(msg_nick2);
 
-    let thread_pool = server::threadpool::ThreadPool::new(4);
-
     //let client_ref = Client::new("localhost:8080".to_string(), "juan".to_string());
     //let arc_client = Arc::new(client_ref.socket);
     let mut i = 0;
@@ -200,7 +192,7 @@ fn privmsg_two_users() {
     let mut msg_recibido = "".to_string();
     for client_stream in listener.incoming() {
         let arc_server = server_ref.clone();
-        thread_pool.execute(move || {
+        thread::spawn(move || {
             let arc_client = Arc::new(client_stream.unwrap());
             server::server::handle_client(arc_server.clone(), arc_client)
                 .expect("Error manejando cliente");
@@ -214,7 +206,7 @@ fn privmsg_two_users() {
 
         thread::sleep(ten_millis);
 
-        i = i + 1;
+        i += 1;
 
         if i == 2 {
             clients_array[0].send(priv_msg_juan_martin.clone());
@@ -233,36 +225,45 @@ fn privmsg_two_users() {
 
             thread::sleep(ten_millis);
 
-            let _ = receiver.recv().unwrap();
-            msg_recibido = receiver.recv().unwrap();
+            // Se descartan las respuestas de la registración hasta recibir el mensaje
+            msg_recibido = receiver
+                .iter()
+                .find(|line| line.contains(" PRIVMSG "))
+                .unwrap();
             //println!("recibidoo {}", recibido);
 
             break;
         }
     }
 
-    assert_eq!(msg_recibido, priv_msg_juan_martin);
+    assert_eq!(msg_recibido, ":juan ".to_string() + &priv_msg_juan_martin);
 }
 
 #[test]
 fn client_is_oper() {
-    let mut argv = Vec::new();
-    argv.push("localhost".to_string());
-    argv.push("8082".to_string());
+    let argv = ["localhost".to_string(), "8082".to_string()];
     let host = argv[0].to_owned();
     let port = argv[1].parse::<u16>().expect("Puerto inválido");
     let servername = host.clone() + &port.to_string();
 
     let mut server = server::server::Server::new();
     server.set_name(servername.clone());
-    let server_ref = Arc::new(server);
+    server.config.operators.push(OperatorBlock {
+        name: "admin".to_string(),
+        salt: "f1ub4s4l".to_string(),
+        password_hash: "6a2bc7767aa15f429e4d7d40b6473e308181153882e99bd002718df72bd36ccf"
+            .to_string(),
+        hosts: vec!["*@*".to_string()],
+        privileges: Vec::new(),
+    });
+    let server_ref = StateHandle::spawn(server);
 
     let address = host.clone() + ":" + &port.to_string();
     //let sv_name = host
     //let mut srv = Server::new();
     //srv.name = host + &port.to_string();
     //let server = Arc::new(srv);
-    let listener = TcpListener::bind(&address.clone()).unwrap();
+    let listener = TcpListener::bind(address.clone()).unwrap();
     println!("Servidor configurado para escuchar en {}", &address);
 
     let mut clients_array: Vec<Client> = Vec::new();
@@ -282,8 +283,6 @@ fn client_is_oper() {
 
     register_array.push(msg_nick1);
 
-    let thread_pool = server::threadpool::ThreadPool::new(4);
-
     //let client_ref = Client::new("localhost:8080".to_string(), "juan".to_string());
     //let arc_client = Arc::new(client_ref.socket);
     let mut i = 0;
@@ -291,7 +290,7 @@ fn client_is_oper() {
     let mut msg_recibido = false;
     for client_stream in listener.incoming() {
         let arc_server = server_ref.clone();
-        thread_pool.execute(move || {
+        thread::spawn(move || {
             let arc_client = Arc::new(client_stream.unwrap());
             server::server::handle_client(arc_server.clone(), arc_client)
                 .expect("Error manejando cliente");
@@ -305,7 +304,7 @@ fn client_is_oper() {
 
         thread::sleep(ten_millis);
 
-        i = i + 1;
+        i += 1;
 
         if i == 1 {
             clients_array[0].send(msg_oper.clone());
@@ -314,35 +313,35 @@ fn client_is_oper() {
 
             thread::sleep(ten_millis);
 
-            msg_recibido = server_ref.clone().users.lock().unwrap()[0].is_admin;
+            msg_recibido = server_ref
+                .query(|server| server.users[0].is_admin())
+                .unwrap();
             //println!("recibidoo {}", recibido);
 
             break;
         }
     }
 
-    assert_eq!(msg_recibido, true);
+    assert!(msg_recibido);
 }
 
 #[test]
 fn two_users_join_channel() {
-    let mut argv = Vec::new();
-    argv.push("localhost".to_string());
-    argv.push("8083".to_string());
+    let argv = ["localhost".to_string(), "8083".to_string()];
     let host = argv[0].to_owned();
     let port = argv[1].parse::<u16>().expect("Puerto inválido");
     let servername = host.clone() + &port.to_string();
 
     let mut server = server::server::Server::new();
     server.set_name(servername.clone());
-    let server_ref = Arc::new(server);
+    let server_ref = StateHandle::spawn(server);
 
     let address = host.clone() + ":" + &port.to_string();
     //let sv_name = host
     //let mut srv = Server::new();
     //srv.name = host + &port.to_string();
     //let server = Arc::new(srv);
-    let listener = TcpListener::bind(&address.clone()).unwrap();
+    let listener = TcpListener::bind(address.clone()).unwrap();
     println!("Servidor configurado para escuchar en {}", &address);
 
     let mut clients_array: Vec<Client> = Vec::new();
@@ -369,8 +368,6 @@ fn two_users_join_channel() {
 
     register_array.push(msg_nick2);
 
-    let thread_pool = server::threadpool::ThreadPool::new(4);
-
     let mut i = 0;
 
     let mut cliente_en_canal1 = "".to_string();
@@ -378,7 +375,7 @@ fn two_users_join_channel() {
 
     for client_stream in listener.incoming() {
         let arc_server = server_ref.clone();
-        thread_pool.execute(move || {
+        thread::spawn(move || {
             let arc_client = Arc::new(client_stream.unwrap());
             server::server::handle_client(arc_server.clone(), arc_client)
                 .expect("Error manejando cliente");
@@ -392,7 +389,7 @@ fn two_users_join_channel() {
 
         thread::sleep(ten_millis);
 
-        i = i + 1;
+        i += 1;
 
         if i == 2 {
             clients_array[0].send(msg_join.clone());
@@ -403,8 +400,11 @@ fn two_users_join_channel() {
 
             thread::sleep(ten_millis);
 
-            cliente_en_canal1 = server_ref.clone().channels.lock().unwrap()[0].users[0].clone();
-            cliente_en_canal2 = server_ref.clone().channels.lock().unwrap()[0].users[1].clone();
+            let members = server_ref
+                .query(|server| server.channels[0].nicknames())
+                .unwrap();
+            cliente_en_canal1 = members[0].clone();
+            cliente_en_canal2 = members[1].clone();
 
             break;
         }
@@ -413,3 +413,309 @@ fn two_users_join_channel() {
     assert_eq!(cliente_en_canal1, "juan".to_string());
     assert_eq!(cliente_en_canal2, "martin".to_string());
 }
+
+#[test]
+fn idle_client_is_pinged_and_disconnected() {
+    let mut server = server::server::Server::new();
+    server.set_name("localhost8084".to_string());
+    server.config.limits.ping_frequency = 1;
+    server.config.limits.ping_timeout = 1;
+    let server_ref = StateHandle::spawn(server);
+
+    let listener = TcpListener::bind("localhost:8084").unwrap();
+    let client = Client::new("localhost:8084".to_string(), "juan".to_string());
+    let (client_stream, _) = listener.accept().unwrap();
+    let arc_server = server_ref.clone();
+    thread::spawn(move || {
+        server::server::handle_client(arc_server, Arc::new(client_stream))
+            .expect("Error manejando cliente");
+    });
+
+    client.send("NICK juan".to_string());
+    client.send("USER user server localhost8084 real".to_string());
+
+    let (sender, receiver) = channel();
+    let reader = client.socket.try_clone().unwrap();
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines().map_while(Result::ok) {
+            let _ = sender.send(line);
+        }
+    });
+
+    let lines: Vec<String> = receiver
+        .iter()
+        .take_while(|line| !line.starts_with("ERROR"))
+        .collect();
+
+    assert!(lines.iter().any(|line| line == "PING localhost8084"));
+    assert_eq!(
+        Some(true),
+        server_ref.query(|server| server.users.is_empty())
+    );
+}
+
+#[test]
+fn five_hundred_clients_register_at_once() {
+    let clients = 500;
+    let config = server::config::ServerConfig::from_address("localhost", 8085);
+    let server_ref = StateHandle::spawn(server::server::Server::from_config(config));
+    let run_ref = server_ref.clone();
+    thread::spawn(move || server::server::run(run_ref));
+
+    let (sender, receiver) = channel();
+    let mut sockets = Vec::new();
+    for i in 0..clients {
+        let socket = loop {
+            match TcpStream::connect("localhost:8085") {
+                Ok(socket) => break socket,
+                Err(_) => thread::sleep(time::Duration::from_millis(10)),
+            }
+        };
+        let reader = socket.try_clone().unwrap();
+        let sender = sender.clone();
+        // Cada cliente sigue leyendo después de registrarse para no frenar al servidor
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines().map_while(Result::ok) {
+                if line.contains(" 001 ") {
+                    let _ = sender.send(i);
+                }
+            }
+        });
+        sockets.push(socket);
+    }
+    for (i, mut socket) in sockets.iter().enumerate() {
+        let registration = format!("NICK c{}\nUSER user server localhost8085 real\n", i);
+        let _ = socket.write(registration.as_bytes());
+    }
+
+    let registered = (0..clients)
+        .filter(|_| receiver.recv_timeout(time::Duration::from_secs(60)).is_ok())
+        .count();
+
+    assert_eq!(clients, registered);
+    // La bienvenida se envía justo antes de agregar al usuario a la lista
+    let deadline = time::Instant::now() + time::Duration::from_secs(5);
+    let registered_users = || server_ref.query(|server| server.users.len()).unwrap();
+    while registered_users() < clients && time::Instant::now() < deadline {
+        thread::sleep(time::Duration::from_millis(10));
+    }
+    assert_eq!(clients, registered_users());
+}
+
+#[test]
+fn unregistered_client_gets_451_and_registration_times_out() {
+    let mut server = server::server::Server::new();
+    server.set_name("localhost8086".to_string());
+    server.config.name = "localhost8086".to_string();
+    server.config.limits.registration_timeout = 1;
+    let server_ref = StateHandle::spawn(server);
+
+    let listener = TcpListener::bind("localhost:8086").unwrap();
+    let mut socket = TcpStream::connect("localhost:8086").unwrap();
+    let (server_stream, _) = listener.accept().unwrap();
+    let (finished, handler_done) = channel();
+    thread::spawn(move || {
+        let _ = server::server::handle_client(server_ref, Arc::new(server_stream));
+        let _ = finished.send(());
+    });
+
+    let _ = socket.write("JOIN #canal\n".as_bytes());
+    let lines: Vec<String> = BufReader::new(socket)
+        .lines()
+        .map_while(Result::ok)
+        .collect();
+
+    assert_eq!(
+        vec![
+            ":localhost8086 451 * :You have not registered",
+            "ERROR :Closing Link: Registration timed out"
+        ],
+        lines
+    );
+    assert!(handler_done
+        .recv_timeout(time::Duration::from_secs(5))
+        .is_ok());
+}
+
+#[test]
+fn client_sending_lines_without_registering_still_times_out() {
+    let mut server = server::server::Server::new();
+    server.set_name("localhost8089".to_string());
+    server.config.name = "localhost8089".to_string();
+    server.config.limits.registration_timeout = 1;
+    let server_ref = StateHandle::spawn(server);
+
+    let listener = TcpListener::bind("localhost:8089").unwrap();
+    let socket = TcpStream::connect("localhost:8089").unwrap();
+    let (server_stream, _) = listener.accept().unwrap();
+    let (finished, handler_done) = channel();
+    thread::spawn(move || {
+        let _ = server::server::handle_client(server_ref, Arc::new(server_stream));
+        let _ = finished.send(());
+    });
+
+    let start = time::Instant::now();
+    let mut writer = socket.try_clone().unwrap();
+    thread::spawn(move || {
+        for _ in 0..25 {
+            if writer.write("PING localhost8089\n".as_bytes()).is_err() {
+                break;
+            }
+            thread::sleep(time::Duration::from_millis(200));
+        }
+    });
+    let lines: Vec<String> = BufReader::new(socket)
+        .lines()
+        .map_while(Result::ok)
+        .collect();
+
+    assert!(start.elapsed() < time::Duration::from_secs(3));
+    assert_eq!(
+        Some(&"ERROR :Closing Link: Registration timed out".to_string()),
+        lines.last()
+    );
+    assert!(handler_done
+        .recv_timeout(time::Duration::from_secs(5))
+        .is_ok());
+}
+
+#[test]
+fn connection_closed_before_registration_ends_its_handler() {
+    let server_ref = StateHandle::spawn(server::server::Server::new());
+
+    let listener = TcpListener::bind("localhost:8087").unwrap();
+    let socket = TcpStream::connect("localhost:8087").unwrap();
+    let (server_stream, _) = listener.accept().unwrap();
+    let (finished, handler_done) = channel();
+    thread::spawn(move || {
+        let _ = server::server::handle_client(server_ref, Arc::new(server_stream));
+        let _ = finished.send(());
+    });
+
+    let _ = socket.shutdown(Shutdown::Both);
+
+    assert!(handler_done
+        .recv_timeout(time::Duration::from_secs(5))
+        .is_ok());
+}
+
+#[test]
+fn channel_members_see_join_part_kick_and_quit() {
+    let mut server = server::server::Server::new();
+    server.set_name("localhost8088".to_string());
+    let server_ref = StateHandle::spawn(server);
+
+    let listener = TcpListener::bind("localhost:8088").unwrap();
+    let mut receivers = Vec::new();
+    let mut clients = Vec::new();
+    for nickname in ["juan", "martin"] {
+        let client = Client::new("localhost:8088".to_string(), nickname.to_string());
+        let (client_stream, _) = listener.accept().unwrap();
+        let arc_server = server_ref.clone();
+        thread::spawn(move || {
+            let _ = server::server::handle_client(arc_server, Arc::new(client_stream));
+        });
+        client.send(format!("NICK {}", nickname));
+        client.send("USER user server localhost8088 real".to_string());
+
+        let (sender, receiver) = channel();
+        let reader = client.socket.try_clone().unwrap();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines().map_while(Result::ok) {
+                let _ = sender.send(line);
+            }
+        });
+        receivers.push(receiver);
+        clients.push(client);
+    }
+    let wait_for = |receiver: &std::sync::mpsc::Receiver<String>, expected: &str| {
+        while let Ok(line) = receiver.recv_timeout(time::Duration::from_secs(5)) {
+            if line == expected {
+                return true;
+            }
+        }
+        false
+    };
+    let juan = ":juan!user@127.0.0.1";
+    let martin = ":martin!user@127.0.0.1";
+
+    clients[0].send("JOIN #canal".to_string());
+    assert!(wait_for(&receivers[0], &format!("{} JOIN #canal", juan)));
+    clients[1].send("JOIN #canal".to_string());
+    assert!(wait_for(&receivers[0], &format!("{} JOIN #canal", martin)));
+
+    clients[1].send("PART #canal :me voy".to_string());
+    assert!(wait_for(
+        &receivers[0],
+        &format!("{} PART #canal :me voy", martin)
+    ));
+
+    clients[1].send("JOIN #canal".to_string());
+    assert!(wait_for(&receivers[0], &format!("{} JOIN #canal", martin)));
+    clients[0].send("KICK #canal martin :por spam".to_string());
+    assert!(wait_for(
+        &receivers[1],
+        &format!("{} KICK #canal martin :por spam", juan)
+    ));
+
+    clients[1].send("JOIN #canal".to_string());
+    assert!(wait_for(&receivers[0], &format!("{} JOIN #canal", martin)));
+    clients[0].send("MODE #canal +tl-s+v 5 martin".to_string());
+    assert!(wait_for(
+        &receivers[1],
+        &format!("{} MODE #canal +tlv 5 martin", juan)
+    ));
+    clients[1].send("QUIT :chau".to_string());
+    assert!(wait_for(&receivers[0], &format!("{} QUIT chau", martin)));
+
+    let members = server_ref.query(|server| server.channels.get("#canal").unwrap().nicknames());
+    assert_eq!(Some(vec!["juan".to_string()]), members);
+}
+
+#[test]
+fn server_registration_needs_a_link_block_and_its_password() {
+    let mut server = server::server::Server::new();
+    server.set_name("localhost8090".to_string());
+    server.config.name = "localhost8090".to_string();
+    server.config.links.push(LinkBlock {
+        name: "sv2".to_string(),
+        host: "127.0.0.1".to_string(),
+        port: 8091,
+        autoconnect: false,
+        password: Some("enlace".to_string()),
+    });
+    let server_ref = StateHandle::spawn(server);
+    let listener = TcpListener::bind("localhost:8090").unwrap();
+
+    let register = |lines: &str| -> Vec<String> {
+        let mut socket = TcpStream::connect("localhost:8090").unwrap();
+        let (server_stream, _) = listener.accept().unwrap();
+        let state = server_ref.clone();
+        thread::spawn(move || {
+            let _ = server::server::handle_client(state, Arc::new(server_stream));
+        });
+        let _ = socket.write(lines.as_bytes());
+        let _ = socket.set_read_timeout(Some(time::Duration::from_secs(2)));
+        BufReader::new(socket)
+            .lines()
+            .map_while(Result::ok)
+            .take(2)
+            .collect()
+    };
+
+    assert_eq!(
+        vec!["ERROR :Closing Link: No link block"],
+        register("PASS enlace\nSERVER intruso 1 :otro\n")
+    );
+    assert_eq!(
+        vec![
+            ":localhost8090 464 sv2 :Password incorrect",
+            "ERROR :Closing Link: Bad password"
+        ],
+        register("SERVER sv2 1 :otro\n")
+    );
+    assert_eq!(
+        vec![":localhost8090 SERVER localhost8090 1 :"],
+        register("PASS enlace\nSERVER sv2 1 :otro\n")[..1]
+    );
+}